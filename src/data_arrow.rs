@@ -0,0 +1,682 @@
+use std::ffi::{c_char, c_void, CStr, CString};
+use std::ptr::{null, null_mut};
+use std::sync::Arc;
+use thiserror::Error;
+use crate::data_schema::*;
+use crate::data_buffer::*;
+use crate::data_value::Value;
+use crate::data_parser_string::FlatStrBuilder;
+use crate::util_bytes::{Bytes, MemoryError};
+use crate::util_datetime::MonthDayMicros;
+
+/*                                          */
+/* Arrow C Data Interface import and export */
+/*                                          */
+
+// Export points the arrow buffers into the columns without copying, arrow offsets carry the view.
+// Types map as below, a chain of pairs becomes one struct with a child per element, named by its field:
+//   i8..i64, u8..u64, f32, f64 -> c s i l C S I L f g      str, blob -> U Z (64-bit offsets, like ours)
+//   bool -> b   nil -> n   decimal(p, s) -> d:p,s   binary(w) -> w:w   dict str -> indices with a U dictionary
+//   date -> tdD   time -> ttu   timestamp -> tsu:   timestamp with time zone -> tsu:UTC   interval -> tin
+//   list -> +L with one child named item (64-bit offsets into the whole element columns)
+// Import also takes 32-bit offsets (u z +l), string views (vu vz) and other time units, converted to ours.
+// Arrow columns never contain nulls here, except the null type.
+// Import copies into new columns: a `Bytes` owns its allocation, so it cannot adopt foreign buffers.
+
+#[derive(Debug, Error, PartialEq, Eq)]
+pub enum ArrowError {
+    #[error("arrow format {0:?} is not supported")]
+    Unsupported(String),
+    #[error("{0:?} has no arrow type")]
+    NoArrowType(String),
+    #[error("arrow array of {0:?} has nulls")]
+    Nulls(String),
+    #[error("arrow structure is released")]
+    Released,
+    #[error("invalid arrow data: {0}")]
+    Invalid(String),
+    #[error(transparent)]
+    Memory(#[from] MemoryError),
+}
+
+const ARROW_FLAG_NULLABLE: i64 = 2;
+
+/// `struct ArrowSchema` of the Arrow C Data Interface
+/// 
+/// Only a producer across the C interface or this crate writes the fields, 
+/// so the accessors trust the pointers once they are checked for null. 
+#[repr(C)]
+pub struct ArrowSchema {
+    pub(crate) format: *const c_char,
+    pub(crate) name: *const c_char,
+    pub(crate) metadata: *const c_char,
+    pub(crate) flags: i64,
+    pub(crate) n_children: i64,
+    pub(crate) children: *mut *mut ArrowSchema,
+    pub(crate) dictionary: *mut ArrowSchema,
+    pub(crate) release: Option<unsafe extern "C" fn(*mut ArrowSchema)>,
+    pub(crate) private_data: *mut c_void,
+}
+
+/// `struct ArrowArray` of the Arrow C Data Interface, with crate-private fields like [`ArrowSchema`]
+#[repr(C)]
+pub struct ArrowArray {
+    pub(crate) length: i64,
+    pub(crate) null_count: i64,
+    pub(crate) offset: i64,
+    pub(crate) n_buffers: i64,
+    pub(crate) n_children: i64,
+    pub(crate) buffers: *mut *const c_void,
+    pub(crate) children: *mut *mut ArrowArray,
+    pub(crate) dictionary: *mut ArrowArray,
+    pub(crate) release: Option<unsafe extern "C" fn(*mut ArrowArray)>,
+    pub(crate) private_data: *mut c_void,
+}
+
+impl ArrowSchema {
+    /// A released schema, for a producer to fill in
+    pub fn empty() -> ArrowSchema {
+        ArrowSchema { format: null(), name: null(), metadata: null(), flags: 0, n_children: 0, children: null_mut(), dictionary: null_mut(), release: None, private_data: null_mut() }
+    }
+    pub fn is_released(&self) -> bool {
+        self.release.is_none()
+    }
+    pub fn format(&self) -> &str {
+        if self.format.is_null() { return "" }
+        unsafe { CStr::from_ptr(self.format) }.to_str().unwrap_or("")
+    }
+    pub fn name(&self) -> &str {
+        if self.name.is_null() { return "" }
+        unsafe { CStr::from_ptr(self.name) }.to_str().unwrap_or("")
+    }
+    pub fn children(&self) -> &[&ArrowSchema] {
+        if self.n_children <= 0 || self.children.is_null() { return &[] }
+        unsafe { std::slice::from_raw_parts(self.children as *const &ArrowSchema, self.n_children as usize) }
+    }
+    pub fn dictionary(&self) -> Option<&ArrowSchema> {
+        unsafe { self.dictionary.as_ref() }
+    }
+}
+
+impl ArrowArray {
+    /// A released array, for a producer to fill in
+    pub fn empty() -> ArrowArray {
+        ArrowArray { length: 0, null_count: 0, offset: 0, n_buffers: 0, n_children: 0, buffers: null_mut(), children: null_mut(), dictionary: null_mut(), release: None, private_data: null_mut() }
+    }
+    pub fn is_released(&self) -> bool {
+        self.release.is_none()
+    }
+    pub fn buffers(&self) -> &[*const c_void] {
+        if self.n_buffers <= 0 || self.buffers.is_null() { return &[] }
+        unsafe { std::slice::from_raw_parts(self.buffers as *const *const c_void, self.n_buffers as usize) }
+    }
+    pub fn children(&self) -> &[&ArrowArray] {
+        if self.n_children <= 0 || self.children.is_null() { return &[] }
+        unsafe { std::slice::from_raw_parts(self.children as *const &ArrowArray, self.n_children as usize) }
+    }
+    pub fn dictionary(&self) -> Option<&ArrowArray> {
+        unsafe { self.dictionary.as_ref() }
+    }
+    /// An array over buffers owned by the caller, which must outlive it
+    pub(crate) fn borrowed(length: usize, null_count: usize, buffers: Vec<*const c_void>, children: Vec<ArrowArray>, dictionary: Option<ArrowArray>) -> ArrowArray {
+        ArrayNode { length, offset: 0, null_count, buffers, children, dictionary, scratch: vec![] }.finish(&None)
+    }
+}
+
+// the consumer owns a structure until it calls release, dropping it releases it
+impl Drop for ArrowSchema {
+    fn drop(&mut self) {
+        if let Some(release) = self.release { unsafe { release(self) } }
+    }
+}
+
+impl Drop for ArrowArray {
+    fn drop(&mut self) {
+        if let Some(release) = self.release { unsafe { release(self) } }
+    }
+}
+
+/*        */
+/* Export */
+/*        */
+
+struct SchemaPrivate {
+    format: CString,
+    name: CString,
+    children: Box<[*mut ArrowSchema]>,
+    dictionary: *mut ArrowSchema,
+}
+
+unsafe extern "C" fn release_schema(schema: *mut ArrowSchema) {
+    let private = Box::from_raw((*schema).private_data as *mut SchemaPrivate);
+    for child in private.children.iter() { drop(Box::from_raw(*child)) }
+    if !private.dictionary.is_null() { drop(Box::from_raw(private.dictionary)) }
+    (*schema).release = None;
+}
+
+pub(crate) fn schema_node(format: &str, name: &str, flags: i64, children: Vec<ArrowSchema>, dictionary: Option<ArrowSchema>) -> Result<ArrowSchema, ArrowError> {
+    let name = CString::new(name).map_err(|_| ArrowError::Invalid(format!("field name {name:?} has a nul byte")))?;
+    let mut private = Box::new(SchemaPrivate {
+        format: CString::new(format).unwrap(),
+        name,
+        children: children.into_iter().map(|c| Box::into_raw(Box::new(c))).collect(),
+        dictionary: dictionary.map_or(null_mut(), |d| Box::into_raw(Box::new(d))),
+    });
+    Ok(ArrowSchema {
+        format: private.format.as_ptr(),
+        name: private.name.as_ptr(),
+        metadata: null(),
+        flags,
+        n_children: private.children.len() as i64,
+        children: private.children.as_mut_ptr(),
+        dictionary: private.dictionary,
+        release: Some(release_schema),
+        private_data: Box::into_raw(private) as *mut c_void,
+    })
+}
+
+// elements of a chain of pairs (a, (b, (c, d))), nested pairs that are named stay whole
+fn pair_elements(schema: DSchemaRef<'_>) -> Vec<DSchemaRef<'_>> {
+    let mut elements = vec![];
+    let mut schema = schema;
+    while let DSchemaEnum::Pair(_, snd, fst) = schema.decode() {
+        elements.push(fst);
+        schema = snd;
+    }
+    elements.push(schema);
+    elements
+}
+
+/// Arrow schema of the columns
+pub fn export_schema(schema: DSchemaRef<'_>) -> Result<ArrowSchema, ArrowError> {
+    fn node(schema: DSchemaRef<'_>, name: &str) -> Result<ArrowSchema, ArrowError> {
+        let leaf = |format: &str| schema_node(format, name, 0, vec![], None);
+        match schema.decode() {
+            DSchemaEnum::I8 => leaf("c"), DSchemaEnum::I16 => leaf("s"), DSchemaEnum::I32 => leaf("i"), DSchemaEnum::I64 => leaf("l"),
+            DSchemaEnum::U8 => leaf("C"), DSchemaEnum::U16 => leaf("S"), DSchemaEnum::U32 => leaf("I"), DSchemaEnum::U64 => leaf("L"),
+            DSchemaEnum::F32 => leaf("f"), DSchemaEnum::F64 => leaf("g"),
+            DSchemaEnum::Str => leaf("U"), DSchemaEnum::Blob => leaf("Z"), DSchemaEnum::Bool => leaf("b"),
+            DSchemaEnum::Date => leaf("tdD"), DSchemaEnum::Time => leaf("ttu"),
+            DSchemaEnum::Timestamp => leaf("tsu:"), DSchemaEnum::TimestampTz => leaf("tsu:UTC"),
+            DSchemaEnum::Interval => leaf("tin"),
+            DSchemaEnum::Decimal(precision, scale) => leaf(&format!("d:{precision},{scale}")),
+            DSchemaEnum::FixedBinary(width) => leaf(&format!("w:{width}")),
+            DSchemaEnum::Nil => schema_node("n", name, ARROW_FLAG_NULLABLE, vec![], None),
+            DSchemaEnum::DictStr(width) => {
+                let index = match width { 1 => "C", 2 => "S", _ => "I" };
+                schema_node(index, name, 0, vec![], Some(schema_node("U", "", 0, vec![], None)?))
+            }
+            DSchemaEnum::Field(name, child) => node(child, name),
+            DSchemaEnum::Pair(..) => {
+                let children = pair_elements(schema).into_iter().map(|e| match e.decode() {
+                    DSchemaEnum::Field(name, child) => node(child, name),
+                    _ => node(e, ""),
+                }).collect::<Result<Vec<_>, _>>()?;
+                schema_node("+s", name, 0, children, None)
+            }
+            DSchemaEnum::List(child) => schema_node("+L", name, 0, vec![node(child, "item")?], None),
+            DSchemaEnum::Enum(..) => Err(ArrowError::NoArrowType(format!("{schema:?}"))),
+        }
+    }
+    node(schema, "")
+}
+
+struct ArrayPrivate {
+    buffers: Box<[*const c_void]>,
+    children: Box<[*mut ArrowArray]>,
+    dictionary: *mut ArrowArray,
+    // keeps the exported columns alive, `None` for a borrowed view
+    _owner: Option<Arc<VBuf>>,
+    // values converted on export
+    _scratch: Vec<MonthDayMicros>,
+}
+
+unsafe extern "C" fn release_array(array: *mut ArrowArray) {
+    let private = Box::from_raw((*array).private_data as *mut ArrayPrivate);
+    for child in private.children.iter() { drop(Box::from_raw(*child)) }
+    if !private.dictionary.is_null() { drop(Box::from_raw(private.dictionary)) }
+    (*array).release = None;
+}
+
+struct ArrayNode {
+    length: usize,
+    offset: usize,
+    null_count: usize,
+    buffers: Vec<*const c_void>,
+    children: Vec<ArrowArray>,
+    dictionary: Option<ArrowArray>,
+    scratch: Vec<MonthDayMicros>,
+}
+
+impl ArrayNode {
+    fn leaf(view: VBufRef<'_>, buffers: Vec<*const c_void>) -> ArrayNode {
+        ArrayNode { length: view.len, offset: view.offset, null_count: 0, buffers, children: vec![], dictionary: None, scratch: vec![] }
+    }
+    fn finish(self, owner: &Option<Arc<VBuf>>) -> ArrowArray {
+        let mut private = Box::new(ArrayPrivate {
+            buffers: self.buffers.into(),
+            children: self.children.into_iter().map(|c| Box::into_raw(Box::new(c))).collect(),
+            dictionary: self.dictionary.map_or(null_mut(), |d| Box::into_raw(Box::new(d))),
+            _owner: owner.clone(),
+            _scratch: self.scratch,
+        });
+        ArrowArray {
+            length: self.length as i64,
+            null_count: self.null_count as i64,
+            offset: self.offset as i64,
+            n_buffers: private.buffers.len() as i64,
+            n_children: private.children.len() as i64,
+            buffers: private.buffers.as_mut_ptr(),
+            children: private.children.as_mut_ptr(),
+            dictionary: private.dictionary,
+            release: Some(release_array),
+            private_data: Box::into_raw(private) as *mut c_void,
+        }
+    }
+}
+
+// arrow wants a valid pointer even for empty buffers, and offsets of an empty string column are [0]
+static EMPTY: [u64; 2] = [0, 0];
+
+fn data(column: &Bytes, skip: usize) -> *const c_void {
+    if column.len() <= skip { return EMPTY.as_ptr() as *const c_void }
+    column.slice(skip..).as_ptr() as *const c_void
+}
+
+fn export_array(view: VBufRef<'_>, owner: &Option<Arc<VBuf>>) -> Result<ArrowArray, ArrowError> {
+    let last = &view.buffer[view.buffer.len().saturating_sub(1)];
+    let node = match view.schema.decode() {
+        DSchemaEnum::Nil => ArrayNode { null_count: view.len, ..ArrayNode::leaf(view, vec![]) },
+        // the column starts with the bit count, the bits follow
+        DSchemaEnum::Bool => ArrayNode::leaf(view, vec![null(), data(last, 8)]),
+        DSchemaEnum::Str | DSchemaEnum::Blob => ArrayNode::leaf(view, vec![null(), data(&view.buffer[1], 0), data(&view.buffer[0], 0)]),
+        DSchemaEnum::Interval => {
+            let nanos = Interval::vector_cast(view).iter().map(|x| MonthDayMicros { micros: x.micros * 1000, ..*x }).collect::<Vec<_>>();
+            let buffers = vec![null(), if nanos.is_empty() { EMPTY.as_ptr() as *const c_void } else { nanos.as_ptr() as *const c_void }];
+            ArrayNode { offset: 0, scratch: nanos, ..ArrayNode::leaf(view, buffers) }
+        }
+        DSchemaEnum::DictStr(_) => {
+            let entries = (view.buffer[1].len() / 8).saturating_sub(1);
+            let dict = ArrayNode { length: entries, offset: 0, ..ArrayNode::leaf(view, vec![null(), data(&view.buffer[1], 0), data(&view.buffer[0], 0)]) };
+            ArrayNode { dictionary: Some(dict.finish(owner)), ..ArrayNode::leaf(view, vec![null(), data(last, 0)]) }
+        }
+        DSchemaEnum::Field(..) => return export_array(view.field(), owner),
+        DSchemaEnum::Pair(..) => {
+            // the view of a pair is applied to each child, the struct itself starts at 0
+            let mut children = vec![];
+            let mut rest = view;
+            while let DSchemaEnum::Pair(..) = rest.schema.decode() {
+                let (fst, snd) = rest.pair();
+                children.push(export_array(fst, owner)?);
+                rest = snd;
+            }
+            children.push(export_array(rest, owner)?);
+            ArrayNode { offset: 0, children, ..ArrayNode::leaf(view, vec![null()]) }
+        }
+        DSchemaEnum::List(_) => {
+            // offsets are absolute rows of the element columns, so the columns are exported whole
+            let values = export_array(List::vector_cast(view).column(), owner)?;
+            ArrayNode { children: vec![values], ..ArrayNode::leaf(view, vec![null(), data(last, 0)]) }
+        }
+        DSchemaEnum::Enum(..) => Err(ArrowError::NoArrowType(format!("{:?}", view.schema)))?,
+        // fixed-size values are laid out like arrow
+        _ => ArrayNode::leaf(view, vec![null(), data(last, 0)]),
+    };
+    Ok(node.finish(owner))
+}
+
+/// Export the columns without copying, the array keeps them alive until it is released
+pub fn export(buf: VBuf) -> Result<(ArrowArray, ArrowSchema), ArrowError> {
+    let schema = export_schema(buf.schema.as_ref())?;
+    let owner = Arc::new(buf);
+    let array = export_array(owner.as_ref().as_ref(), &Some(owner.clone()))?;
+    Ok((array, schema))
+}
+
+/// Export a view of the columns without copying
+///
+/// # Safety
+///
+/// The columns must outlive the array, and must not be written until it is released.
+pub unsafe fn export_ref(view: VBufRef<'_>) -> Result<(ArrowArray, ArrowSchema), ArrowError> {
+    Ok((export_array(view, &None)?, export_schema(view.schema)?))
+}
+
+/*        */
+/* Import */
+/*        */
+
+unsafe fn import_array(array: &ArrowArray, schema: &ArrowSchema) -> Result<VBuf, ArrowError> {
+    if array.is_released() || schema.is_released() { Err(ArrowError::Released)? }
+    let (format, len, offset) = (schema.format(), array.length as usize, array.offset as usize);
+    let buffers = array.buffers();
+    if format != "n" && array.null_count != 0 && buffers.first().is_some_and(|b| !b.is_null()) {
+        Err(ArrowError::Nulls(format.to_string()))?
+    }
+    let buffer = |i: usize| -> Result<*const u8, ArrowError> {
+        match buffers.get(i) {
+            Some(b) if !b.is_null() => Ok(*b as *const u8),
+            _ if len == 0 => Ok(EMPTY.as_ptr() as *const u8),
+            _ => Err(ArrowError::Invalid(format!("buffer {i} of {format:?} is missing"))),
+        }
+    };
+    // copy `len` values of `width` bytes into a primitive column
+    let fixed = |schema: DSchema, width: usize| -> Result<VBuf, ArrowError> {
+        let mut column = VBuf::new(schema);
+        column.buffer[0].extend(std::slice::from_raw_parts(buffer(1)?.add(offset * width), len * width));
+        column.len = len;
+        Ok(column)
+    };
+    // convert `len` integers of `width` bytes in the unit to micros
+    let scaled = |schema: DSchema, width: usize, unit: char, value: fn(i64) -> Value<'static>| -> Result<VBuf, ArrowError> {
+        let values = buffer(1)?;
+        let mut column = VBuf::new(schema);
+        for i in offset..offset + len {
+            let x = if width == 4 { *(values as *const i32).add(i) as i64 } else { *(values as *const i64).add(i) };
+            column.push(&value(match unit { 's' => x * 1_000_000, 'm' => x * 1000, 'n' => x.div_euclid(1000), _ => x }));
+        }
+        Ok(column)
+    };
+    // bytes of each value of a view column, short values are inlined in the view
+    let views = || -> Result<Vec<&[u8]>, ArrowError> {
+        let views = buffer(1)? as *const [u8; 16];
+        (offset..offset + len).map(|i| {
+            let view = &*views.add(i);
+            let size = i32::from_le_bytes(view[..4].try_into().unwrap()) as usize;
+            if size <= 12 { return Ok(&view[4..4 + size]) }
+            let at = |i: usize| i32::from_le_bytes(view[i..i + 4].try_into().unwrap()) as usize;
+            // the last buffer holds the sizes of the data buffers
+            let index = 2 + at(8);
+            if index + 1 >= buffers.len() { Err(ArrowError::Invalid(format!("view of {format:?} has no data buffer {}", at(8))))? }
+            Ok(std::slice::from_raw_parts((buffers[index] as *const u8).add(at(12)), size))
+        }).collect()
+    };
+    // (start, end) of each value of a variable-sized column
+    let ranges = |wide: bool| -> Result<Vec<(usize, usize)>, ArrowError> {
+        let offsets = buffer(1)?;
+        let at = |i: usize| if wide { *(offsets as *const i64).add(offset + i) as usize } else { *(offsets as *const i32).add(offset + i) as usize };
+        Ok((0..len).map(|i| (at(i), at(i + 1))).collect())
+    };
+    if !schema.dictionary.is_null() {
+        return import_dictionary(array, schema);
+    }
+    Ok(match format {
+        "c" => fixed(I8::encode(&[]), 1)?, "s" => fixed(I16::encode(&[]), 2)?, "i" => fixed(I32::encode(&[]), 4)?, "l" => fixed(I64::encode(&[]), 8)?,
+        "C" => fixed(U8::encode(&[]), 1)?, "S" => fixed(U16::encode(&[]), 2)?, "I" => fixed(U32::encode(&[]), 4)?, "L" => fixed(U64::encode(&[]), 8)?,
+        "f" => fixed(F32::encode(&[]), 4)?, "g" => fixed(F64::encode(&[]), 8)?,
+        "tdD" => fixed(Date::encode(&[]), 4)?, "ttu" => fixed(Time::encode(&[]), 8)?,
+        "tsu:" => fixed(Timestamp::encode(&[]), 8)?,
+        // timestamps with a time zone are instants in UTC, whatever the zone
+        _ if format.starts_with("tsu:") => fixed(TimestampTz::encode(&[]), 8)?,
+        // other units are converted to days and micros
+        "tdm" => scaled(Date::encode(&[]), 8, 'm', |x| Value::Date(x.div_euclid(86_400_000_000) as i32))?,
+        "tts" => scaled(Time::encode(&[]), 4, 's', Value::Time)?,
+        "ttm" => scaled(Time::encode(&[]), 4, 'm', Value::Time)?,
+        "ttn" => scaled(Time::encode(&[]), 8, 'n', Value::Time)?,
+        "tss:" | "tsm:" | "tsn:" => scaled(Timestamp::encode(&[]), 8, format.as_bytes()[2] as char, Value::Timestamp)?,
+        _ if format.starts_with("tss:") || format.starts_with("tsm:") || format.starts_with("tsn:") => {
+            scaled(TimestampTz::encode(&[]), 8, format.as_bytes()[2] as char, Value::TimestampTz)?
+        }
+        "tin" => {
+            let nanos = std::slice::from_raw_parts(buffer(1)? as *const MonthDayMicros, offset + len);
+            let mut column = VBuf::new(Interval::encode(&[]));
+            for x in &nanos[offset..] { column.push(&Value::Interval(MonthDayMicros { micros: x.micros / 1000, ..*x })) }
+            column
+        }
+        "n" => {
+            let mut column = VBuf::new(Nil::encode(&[]));
+            column.buffer[0].add_count(len);
+            column.len = len;
+            column
+        }
+        "b" => {
+            let bits = buffer(1)?;
+            Bool::column((offset..offset + len).map(|i| *bits.add(i / 8) >> (i % 8) & 1 == 1))
+        }
+        "u" | "U" => {
+            let (values, ranges) = (buffer(2)?, ranges(format == "U")?);
+            let mut builder = FlatStrBuilder::with_capacity(len, ranges.last().map_or(0, |r| r.1) - ranges.first().map_or(0, |r| r.0));
+            for (start, end) in ranges {
+                let x = std::str::from_utf8(std::slice::from_raw_parts(values.add(start), end - start)).map_err(|e| ArrowError::Invalid(e.to_string()))?;
+                builder.push(x);
+            }
+            builder.finish()
+        }
+        "vu" => {
+            let mut builder = FlatStrBuilder::new();
+            for x in views()? { builder.push(std::str::from_utf8(x).map_err(|e| ArrowError::Invalid(e.to_string()))?) }
+            builder.finish()
+        }
+        "vz" => {
+            let mut column = VBuf::new(Blob::encode(&[]));
+            for x in views()? { column.push(&Value::Blob(x)) }
+            column
+        }
+        "z" | "Z" => {
+            let values = buffer(2)?;
+            let mut column = VBuf::new(Blob::encode(&[]));
+            for (start, end) in ranges(format == "Z")? {
+                column.push(&Value::Blob(std::slice::from_raw_parts(values.add(start), end - start)));
+            }
+            column
+        }
+        "+l" | "+L" => {
+            let (Some(s), Some(a)) = (schema.children().first(), array.children().first()) else { Err(ArrowError::Invalid(format!("{format:?} has no child")))? };
+            let values = import_array(a, s)?;
+            // the conventional name of the child is dropped, other names are kept as a field
+            let values = if s.name().is_empty() || s.name() == "item" { values } else {
+                let schema = Field::with(s.name(), values.schema.as_ref()).ok_or(ArrowError::Unsupported("schema too large for u16 offsets".into()))?;
+                VBuf { schema, ..values }
+            };
+            let mut column = VBuf::new(List::encode(&[values.schema.as_ref()]));
+            for (start, end) in ranges(format == "+L")? {
+                if start > end || end > values.len { Err(ArrowError::Invalid(format!("offsets of {format:?} are out of range")))? }
+                List::vector_push(column.as_mut(), values.as_ref().slice(start..end))?;
+                column.len += 1;
+            }
+            column
+        }
+        "+s" => {
+            let children = schema.children().iter().zip(array.children()).map(|(s, a)| {
+                let child = import_array(a, s)?;
+                let child = if s.name().is_empty() { child } else {
+                    let schema = Field::with(s.name(), child.schema.as_ref()).ok_or(ArrowError::Unsupported("schema too large for u16 offsets".into()))?;
+                    VBuf { schema, ..child }
+                };
+                // the offset of a struct applies to its children
+                if offset == 0 && child.len == len { return Ok(child) }
+                Ok(crate::kernel_select::concat(&[child.as_ref().slice(offset..offset + len)]))
+            }).collect::<Result<Vec<_>, ArrowError>>()?;
+            let mut children = children.into_iter().rev();
+            let last = children.next().ok_or(ArrowError::Unsupported("+s with no children".into()))?;
+            children.try_fold(last, |snd, fst| Ok::<_, ArrowError>(VBuf {
+                schema: Pair::with(fst.schema.as_ref(), snd.schema.as_ref()).ok_or(ArrowError::Unsupported("schema too large for u16 offsets".into()))?,
+                buffer: fst.buffer.into_iter().chain(snd.buffer).collect(),
+                len,
+            }))?
+        }
+        _ => {
+            if let Some(width) = format.strip_prefix("w:") {
+                let width = width.parse::<u32>().map_err(|_| ArrowError::Unsupported(format.to_string()))?;
+                let schema = FixedBinary::with(width).ok_or(ArrowError::Unsupported(format.to_string()))?;
+                return fixed(schema, width as usize)
+            }
+            if let Some(spec) = format.strip_prefix("d:") {
+                let spec = spec.split(',').map(|x| x.parse::<u8>()).collect::<Result<Vec<_>, _>>().map_err(|_| ArrowError::Unsupported(format.to_string()))?;
+                let schema = match spec[..] {
+                    [precision, scale] | [precision, scale, 128] => Decimal::with(precision, scale),
+                    _ => None,
+                }.ok_or(ArrowError::Unsupported(format.to_string()))?;
+                return fixed(schema, 16)
+            }
+            Err(ArrowError::Unsupported(format.to_string()))?
+        }
+    })
+}
+
+// indices of any integer type become codes of the same width, or wider if the dictionary needs it
+unsafe fn import_dictionary(array: &ArrowArray, schema: &ArrowSchema) -> Result<VBuf, ArrowError> {
+    let (dict_schema, dict_array) = (&*schema.dictionary, &*array.dictionary);
+    if !["u", "U", "vu"].contains(&dict_schema.format()) { Err(ArrowError::Unsupported(format!("dictionary of {:?}", dict_schema.format())))? }
+    let dict = import_array(dict_array, dict_schema)?;
+    let codes = dictionary_codes(array, schema.format())?;
+    if codes.iter().any(|c| *c as usize >= dict.len) { Err(ArrowError::Invalid("dictionary index is out of range".into()))? }
+    let declared = match schema.format() { "c" | "C" => 1, "s" | "S" => 2, _ => 4 };
+    let width = declared.max(match dict.len { 0..=0x100 => 1, 0x101..=0x10000 => 2, _ => 4 });
+    let mut column = VBuf { schema: DictStr::with(width).unwrap(), buffer: dict.buffer, len: codes.len() };
+    column.buffer.push(Bytes::new());
+    for code in codes { DictStr::push_code(column.as_mut(), code)? }
+    Ok(column)
+}
+
+unsafe fn dictionary_codes(array: &ArrowArray, format: &str) -> Result<Vec<u32>, ArrowError> {
+    let (len, offset) = (array.length as usize, array.offset as usize);
+    if len == 0 { return Ok(vec![]) }
+    let ptr = *array.buffers().get(1).ok_or(ArrowError::Invalid("dictionary indices are missing".into()))?;
+    macro_rules! Read {($($f: literal: $Y: ty, )*) => {
+        match format {
+            $($f => std::slice::from_raw_parts((ptr as *const $Y).add(offset), len).iter().map(|x| u32::try_from(*x).ok()).collect::<Option<Vec<u32>>>(), )*
+            _ => Err(ArrowError::Unsupported(format!("dictionary index {format:?}")))?,
+        }
+    };}
+    Read! { "c": i8, "C": u8, "s": i16, "S": u16, "i": i32, "I": u32, "l": i64, "L": u64, }
+        .ok_or(ArrowError::Invalid("dictionary index is out of range".into()))
+}
+
+/// Copy an arrow array into new columns, the array is released afterwards
+///
+/// # Safety
+///
+/// The structures must follow the Arrow C Data Interface.
+pub unsafe fn import(array: ArrowArray, schema: &ArrowSchema) -> Result<VBuf, ArrowError> {
+    import_array(&array, schema)
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    fn formats(schema: &ArrowSchema) -> Vec<String> {
+        std::iter::once(format!("{}:{}", schema.name(), schema.format()))
+            .chain(schema.children().iter().flat_map(|c| formats(c)))
+            .collect()
+    }
+
+    // (a: i32, (s: str, (b: bool, (n: nil, (d: decimal(10, 2), (t: timestamptz, (i: interval, (x: binary(2), c: dict str))))))))
+    fn columns() -> VBuf {
+        let field = |name: &str, schema: DSchema| Field::named(name, schema.as_ref());
+        let tail = Pair::encode(&[field("x", FixedBinary::with(2).unwrap()).as_ref(), field("c", DictStr::with(1).unwrap()).as_ref()]);
+        let tail = Pair::encode(&[field("i", Interval::encode(&[])).as_ref(), tail.as_ref()]);
+        let tail = Pair::encode(&[field("t", TimestampTz::encode(&[])).as_ref(), tail.as_ref()]);
+        let tail = Pair::encode(&[field("d", Decimal::with(10, 2).unwrap()).as_ref(), tail.as_ref()]);
+        let tail = Pair::encode(&[field("n", Nil::encode(&[])).as_ref(), tail.as_ref()]);
+        let tail = Pair::encode(&[field("b", Bool::encode(&[])).as_ref(), tail.as_ref()]);
+        let tail = Pair::encode(&[field("s", Str::encode(&[])).as_ref(), tail.as_ref()]);
+        let schema = Pair::encode(&[field("a", I32::encode(&[])).as_ref(), tail.as_ref()]);
+        let strings = (0..20).map(|i| format!("s{i}")).collect::<Vec<_>>();
+        let mut columns = VBuf::new(schema);
+        for i in 0..20 {
+            let mut value = Value::pair(Value::field("x", Value::FixedBinary(&strings[i].as_bytes()[..2])), Value::field("c", Value::Str(["de", "fr"][i % 2])));
+            value = Value::pair(Value::field("i", Value::Interval(MonthDayMicros { months: 1, days: i as i32, micros: 5 })), value);
+            value = Value::pair(Value::field("t", Value::TimestampTz(i as i64 * 1000)), value);
+            value = Value::pair(Value::field("d", Value::Decimal(i as i128 * 101)), value);
+            value = Value::pair(Value::field("n", Value::Nil), value);
+            value = Value::pair(Value::field("b", Value::Bool(i % 3 == 0)), value);
+            value = Value::pair(Value::field("s", Value::Str(&strings[i])), value);
+            columns.push(&Value::pair(Value::field("a", Value::I32(i as i32)), value));
+        }
+        columns
+    }
+
+    #[test]
+    fn round_trip() {
+        let columns = columns();
+        let (array, schema) = unsafe { export_ref(columns.as_ref().slice(5..15)).unwrap() };
+        assert!(formats(&schema) == [":+s", "a:i", "s:U", "b:b", "n:n", "d:d:10,2", "t:tsu:UTC", "i:tin", "x:w:2", "c:C"]);
+        assert!(array.length == 10 && array.children()[0].offset == 5);
+        // the int column is shared, not copied
+        assert!(array.children()[0].buffers()[1] == columns.buffer[0].slice(..).as_ptr() as *const c_void);
+        let back = unsafe { import(array, &schema).unwrap() };
+        assert!(back.schema.as_ref() == columns.schema.as_ref());
+        for i in 0..10 { assert!(back.get(i) == columns.get(i + 5), "{:?} != {:?}", back.get(i), columns.get(i + 5)) }
+        // an owned export keeps the columns alive after they are dropped here
+        let (array, schema) = export(columns).unwrap();
+        let back = unsafe { import(array, &schema).unwrap() };
+        assert!(back.len() == 20 && back.get(19) == self::columns().get(19));
+    }
+
+    #[test]
+    fn release_and_errors() {
+        let column = VBuf::new(Str::encode(&[]));
+        let (mut array, mut schema) = unsafe { export_ref(column.as_ref()).unwrap() };
+        assert!(array.length == 0 && array.n_buffers == 3 && schema.format() == "U");
+        let (release_array, release_schema) = (array.release.unwrap(), schema.release.unwrap());
+        unsafe { release_array(&mut array); release_schema(&mut schema) };
+        assert!(array.is_released() && schema.is_released());
+        assert!(unsafe { import_array(&array, &schema) }.err() == Some(ArrowError::Released));
+        // a released structure has null pointers, the accessors don't follow them
+        let (schema, array) = (ArrowSchema::empty(), ArrowArray::empty());
+        assert!(schema.format().is_empty() && schema.children().is_empty() && schema.dictionary().is_none());
+        assert!(array.buffers().is_empty() && array.children().is_empty() && array.dictionary().is_none());
+        // a validity buffer with nulls is refused
+        let mut ints = VBuf::new(I64::encode(&[]));
+        ints.push(&Value::I64(1));
+        let (mut array, schema) = unsafe { export_ref(ints.as_ref()).unwrap() };
+        let validity = [0u8; 8];
+        let mut buffers = [validity.as_ptr() as *const c_void, array.buffers()[1]];
+        let own = array.buffers;
+        array.buffers = buffers.as_mut_ptr();
+        array.null_count = 1;
+        assert!(unsafe { import_array(&array, &schema) }.err() == Some(ArrowError::Nulls("l".into())));
+        array.buffers = own;
+        array.null_count = 0;
+        assert!(unsafe { import(array, &schema) }.unwrap().get(0) == Value::I64(1));
+    }
+
+    #[test]
+    fn lists() {
+        // list of (a: i32, b: list of str)
+        let inner = List::encode(&[Str::encode(&[]).as_ref()]);
+        let element = Pair::encode(&[Field::named("a", I32::encode(&[]).as_ref()).as_ref(), Field::named("b", inner.as_ref()).as_ref()]);
+        let strings = (0..10).map(|i| i.to_string()).collect::<Vec<_>>();
+        let value = |i: usize| Value::List((0..i % 3).map(|j| Value::pair(
+            Value::field("a", Value::I32((i * 10 + j) as i32)),
+            Value::field("b", Value::List(strings[..j + i % 2].iter().map(|s| Value::Str(s)).collect())),
+        )).collect());
+        let mut columns = VBuf::new(List::encode(&[element.as_ref()]));
+        for i in 0..20 { columns.push(&value(i)) }
+        let (array, schema) = unsafe { export_ref(columns.as_ref().slice(5..15)).unwrap() };
+        assert!(formats(&schema) == [":+L", "item:+s", "a:i", "b:+L", "item:U"]);
+        assert!(array.offset == 5 && array.children()[0].offset == 0);
+        let back = unsafe { import(array, &schema).unwrap() };
+        assert!(back.schema.as_ref() == columns.schema.as_ref());
+        for i in 0..10 { assert!(back.get(i) == value(i + 5), "{:?} != {:?}", back.get(i), value(i + 5)) }
+        // 32-bit offsets, out of range offsets are refused
+        let mut ints = VBuf::new(I64::encode(&[]));
+        for i in 0..4 { ints.push(&Value::I64(i)) }
+        let (values, item) = unsafe { export_ref(ints.as_ref()).unwrap() };
+        let mut offsets = [0i32, 3, 3, 4];
+        let schema = schema_node("+l", "", 0, vec![item], None).unwrap();
+        let array = ArrowArray::borrowed(3, 0, vec![null(), offsets.as_ptr() as *const c_void], vec![values], None);
+        let back = unsafe { import(array, &schema).unwrap() };
+        assert!(back.get(0) == Value::List(vec![Value::I64(0), Value::I64(1), Value::I64(2)]) && back.get(1) == Value::List(vec![]));
+        offsets[3] = 5;
+        let (values, _) = unsafe { export_ref(ints.as_ref()).unwrap() };
+        let array = ArrowArray::borrowed(3, 0, vec![null(), offsets.as_ptr() as *const c_void], vec![values], None);
+        assert!(matches!(unsafe { import(array, &schema) }, Err(ArrowError::Invalid(_))));
+    }
+
+    #[test]
+    fn too_large_struct() {
+        let empty = |n: usize| ArrowArray::borrowed(0, 0, vec![null(); n], vec![], None);
+        let unsupported = Some(ArrowError::Unsupported("schema too large for u16 offsets".into()));
+        // each element adds a pair and a field, 3000 of them overflow the u16 offsets
+        let children = (0..3000).map(|i| schema_node("l", &format!("c{i}"), 0, vec![], None).unwrap()).collect();
+        let schema = schema_node("+s", "", 0, children, None).unwrap();
+        let array = ArrowArray::borrowed(0, 0, vec![null()], (0..3000).map(|_| empty(2)).collect(), None);
+        assert!(unsafe { import(array, &schema) }.err() == unsupported);
+        let schema = schema_node("+s", "", 0, vec![schema_node("l", &"x".repeat(70000), 0, vec![], None).unwrap()], None).unwrap();
+        let array = ArrowArray::borrowed(0, 0, vec![null()], vec![empty(2)], None);
+        assert!(unsafe { import(array, &schema) }.err() == unsupported);
+    }
+}
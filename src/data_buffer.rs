@@ -1,6 +1,8 @@
+use std::sync::Arc;
 use crate::{
-    util_bytes::Bytes, 
+    util_bytes::{Bytes, MemoryError, MemoryPool}, 
     data_schema::*,
+    data_value::Value,
 };
 
 /*                                 */
@@ -8,58 +10,253 @@ use crate::{
 /*                                 */
 
 /// Generic Scalar Buffer
+/// 
+/// A row is laid out as a `#[repr(C)]` struct according to [`ScalarLayout`]. 
+/// Variable-sized values (e.g. str) store `[offset: u64, len: u64]` in the row, pointing into `heap`. 
+#[derive(Clone)]
 pub struct SBuf {
     pub buffer: Bytes,
+    pub heap: Bytes,
     pub schema: DSchema,
 }
 
 impl SBuf {
     pub fn new(schema: DSchema) -> Self {
-        macro_rules! Match {($($X: ident,)*) => {{
-            let schema_ref = schema.as_ref();
-            match Tag::from(schema_ref.tag()) {
-                $(Tag::$X => $X::scalar_layout(schema_ref.cut(1)), )*
-            }
-        }};}
-        let size = crate::Fill!{Match{<Here>}};
-        let mut buffer = Bytes::new();
-        todo!()
+        let layout = schema.as_ref().scalar_layout();
+        // bytes are aligned to 16, which is enough for any scalar layout
+        debug_assert!(layout.align <= 16);
+        let buffer = Bytes::filled(layout.size, 0);
+        SBuf { buffer, heap: Bytes::new(), schema }
+    }
+    pub fn as_ref(&self) -> SBufRef<'_> {
+        SBufRef { buffer: self.buffer.slice(..), heap: self.heap.slice(..), schema: self.schema.as_ref() }
+    }
+    pub fn as_mut(&mut self) -> SBufMut<'_> {
+        SBufMut { buffer: self.buffer.slice_mut(..), heap: &mut self.heap, schema: self.schema.as_ref() }
     }
 }
 
 /// Immutable Reference of Generic Scalar Buffer
+#[derive(Clone, Copy)]
 pub struct SBufRef<'a> {
     pub buffer: &'a [u8],
+    pub heap: &'a [u8],
     pub schema: DSchemaRef<'a>,
 }
 
-/// 
+impl<'a> SBufRef<'a> {
+    /// Split a pair into its first and second element
+    pub fn pair(self) -> (SBufRef<'a>, SBufRef<'a>) {
+        let DSchemaEnum::Pair(_, snd, fst) = self.schema.decode() else { panic!("{:?} is not a pair", self.schema) };
+        let offset = fst.scalar_layout().offset_of(snd.scalar_layout());
+        let fst = SBufRef { buffer: &self.buffer[..fst.scalar_layout().size], heap: self.heap, schema: fst };
+        let snd = SBufRef { buffer: &self.buffer[offset..offset+snd.scalar_layout().size], heap: self.heap, schema: snd };
+        (fst, snd)
+    }
+    /// Unwrap a named field
+    pub fn field(self) -> SBufRef<'a> {
+        let DSchemaEnum::Field(_, child) = self.schema.decode() else { panic!("{:?} is not a field", self.schema) };
+        SBufRef { schema: child, ..self }
+    }
+    /// Variable-sized data this slot points to
+    pub fn get_heap(self) -> &'a [u8] {
+        let offset = bytemuck::pod_read_unaligned::<u64>(&self.buffer[..8]) as usize;
+        let len = bytemuck::pod_read_unaligned::<u64>(&self.buffer[8..16]) as usize;
+        &self.heap[offset..offset+len]
+    }
+    /// Elements of a list, the slot points to `len` rows of the element schema in the heap
+    pub fn list(self) -> impl ExactSizeIterator<Item = SBufRef<'a>> {
+        let DSchemaEnum::List(child) = self.schema.decode() else { panic!("{:?} is not a list", self.schema) };
+        let offset = bytemuck::pod_read_unaligned::<u64>(&self.buffer[..8]) as usize;
+        let len = bytemuck::pod_read_unaligned::<u64>(&self.buffer[8..16]) as usize;
+        let (size, heap) = (child.scalar_layout().size, self.heap);
+        (0..len).map(move |k| SBufRef { buffer: &heap[offset+k*size..offset+(k+1)*size], heap, schema: child })
+    }
+}
+
+/// Mutable Reference of Generic Scalar Buffer
+pub struct SBufMut<'a> {
+    pub buffer: &'a mut [u8],
+    pub heap: &'a mut Bytes,
+    pub schema: DSchemaRef<'a>,
+}
+
+impl<'a> SBufMut<'a> {
+    pub fn reborrow(&mut self) -> SBufMut<'_> {
+        SBufMut { buffer: self.buffer, heap: self.heap, schema: self.schema }
+    }
+    /// The first element of a pair
+    pub fn fst(&mut self) -> SBufMut<'_> {
+        let DSchemaEnum::Pair(_, _, fst) = self.schema.decode() else { panic!("{:?} is not a pair", self.schema) };
+        let size = fst.scalar_layout().size;
+        SBufMut { buffer: &mut self.buffer[..size], heap: self.heap, schema: fst }
+    }
+    /// The second element of a pair
+    pub fn snd(&mut self) -> SBufMut<'_> {
+        let DSchemaEnum::Pair(_, snd, fst) = self.schema.decode() else { panic!("{:?} is not a pair", self.schema) };
+        let offset = fst.scalar_layout().offset_of(snd.scalar_layout());
+        let size = snd.scalar_layout().size;
+        SBufMut { buffer: &mut self.buffer[offset..offset+size], heap: self.heap, schema: snd }
+    }
+    /// Unwrap a named field
+    pub fn field(&mut self) -> SBufMut<'_> {
+        let DSchemaEnum::Field(_, child) = self.schema.decode() else { panic!("{:?} is not a field", self.schema) };
+        SBufMut { buffer: self.buffer, heap: self.heap, schema: child }
+    }
+    /// Copy a value of the same schema, variable-sized data is copied into this buffer's heap
+    pub fn assign(&mut self, src: SBufRef<'_>) {
+        assert!(self.schema == src.schema, "cannot assign {:?} to {:?}", src.schema, self.schema);
+        match self.schema.decode() {
+            DSchemaEnum::Pair(..) => {
+                let (fst, snd) = src.pair();
+                self.fst().assign(fst);
+                self.snd().assign(snd);
+            }
+            DSchemaEnum::Field(..) => self.field().assign(src.field()),
+            DSchemaEnum::Str | DSchemaEnum::Blob | DSchemaEnum::DictStr(_) => self.put_heap(src.get_heap()),
+            DSchemaEnum::List(_) => self.put_list(src.list()),
+            _ => self.buffer.copy_from_slice(src.buffer),
+        }
+    }
+    /// Append variable-sized data to heap, and point this slot to it
+    pub fn put_heap(&mut self, data: &[u8]) {
+        let offset = self.heap.len() as u64;
+        self.heap.extend(data);
+        self.buffer[..16].copy_from_slice(bytemuck::cast_slice(&[offset, data.len() as u64]));
+    }
+    /// Append the elements of a list to heap as rows of the element schema, and point this slot to them
+    pub fn put_list<'b>(&mut self, elems: impl ExactSizeIterator<Item = SBufRef<'b>>) {
+        let DSchemaEnum::List(child) = self.schema.decode() else { panic!("{:?} is not a list", self.schema) };
+        let layout = child.scalar_layout();
+        self.heap.pad(layout.align.max(1));
+        let (offset, len) = (self.heap.len(), elems.len());
+        self.heap.resize(offset + len * layout.size, 0);
+        // an element is assigned in a slot of its own, since its heap data may move the heap
+        let mut slot = Bytes::filled(layout.size, 0);
+        for (k, elem) in elems.enumerate() {
+            SBufMut { buffer: slot.slice_mut(..), heap: self.heap, schema: child }.assign(elem);
+            self.heap.slice_mut(offset+k*layout.size..offset+(k+1)*layout.size).copy_from_slice(slot.slice(..));
+        }
+        self.buffer[..16].copy_from_slice(bytemuck::cast_slice(&[offset as u64, len as u64]));
+    }
+}
+
+/// Typed access to a scalar buffer
 pub trait SBufParser<const TAG: u8> {
     type ScalarRef<'a>;
     fn scalar_cast<'a>(buf: SBufRef<'a>) -> Self::ScalarRef<'a>;
+    fn scalar_write(buf: SBufMut<'_>, elem: Self::ScalarRef<'_>);
 }
 
 /*                                 */
 /* Implementation of vector buffer */
 /*                                 */
 
+/// Resolve a range of rows against the number of rows, panics if it is out of bound
+pub fn resolve_range(range: impl std::ops::RangeBounds<usize>, len: usize) -> std::ops::Range<usize> {
+    use std::ops::Bound::*;
+    let start = match range.start_bound() {
+        Included(x) => *x,
+        Excluded(x) => *x+1,
+        Unbounded => 0,
+    };
+    let end = match range.end_bound() {
+        Included(x) => *x+1,
+        Excluded(x) => *x,
+        Unbounded => len,
+    };
+    assert!(start <= end && end <= len, "{start}..{end} is out of 0..{len}");
+    start..end
+}
+
 /// Generic Vector Buffer
+/// 
+/// `len` counts the rows pushed through [`VBuf`], writing columns through [`VBufMut`] directly doesn't update it. 
+/// A clone is a snapshot sharing the columns, they are copied on the first write.
+#[derive(Clone)]
 pub struct VBuf {
     pub buffer: Vec<Bytes>,
     pub schema: DSchema,
+    pub len: usize,
 }
 
 impl VBuf {
     pub fn new(schema: DSchema) -> VBuf {
-        macro_rules! Match {($($X: ident,)*) => {{
-            let schema_ref = schema.as_ref();
-            match Tag::from(schema_ref.tag()) {
-                $(Tag::$X => $X::num_columns(schema_ref.cut(1)), )*
+        VBuf::with_columns(schema, &|| Ok(Bytes::new())).unwrap()
+    }
+    /// Empty columns allocated in `pool`, which counts the memory of the vector as it grows
+    pub fn try_new_in(schema: DSchema, pool: &Arc<MemoryPool>) -> Result<VBuf, MemoryError> {
+        VBuf::with_columns(schema, &|| Bytes::try_with_capacity_in(0, pool))
+    }
+    fn with_columns(schema: DSchema, column: &dyn Fn() -> Result<Bytes, MemoryError>) -> Result<VBuf, MemoryError> {
+        fn columns(schema: DSchemaRef<'_>, buffer: &mut Vec<Bytes>, column: &dyn Fn() -> Result<Bytes, MemoryError>) -> Result<(), MemoryError> {
+            match schema.decode() {
+                DSchemaEnum::Pair(_, snd, fst) => { columns(fst, buffer, column)?; columns(snd, buffer, column) }
+                DSchemaEnum::Field(_, child) => columns(child, buffer, column),
+                // nil column only counts its values, it holds no bytes
+                DSchemaEnum::Nil => { buffer.push(Bytes::counter()); Ok(()) }
+                // element columns, then offsets
+                DSchemaEnum::List(child) => { columns(child, buffer, column)?; buffer.push(column()?); Ok(()) }
+                _ => (0..schema.num_columns()).try_for_each(|_| { buffer.push(column()?); Ok(()) }),
+            }
+        }
+        let mut buffer = Vec::new();
+        columns(schema.as_ref(), &mut buffer, column)?;
+        Ok(VBuf {schema, buffer, len: 0})
+    }
+    pub fn len(&self) -> usize {
+        self.len
+    }
+    pub fn is_empty(&self) -> bool {
+        self.len == 0
+    }
+    pub fn as_ref(&self) -> VBufRef<'_> {
+        VBufRef { buffer: &self.buffer, schema: self.schema.as_ref(), offset: 0, len: self.len }
+    }
+    pub fn as_mut(&mut self) -> VBufMut<'_> {
+        VBufMut { buffer: &mut self.buffer, schema: self.schema.as_ref() }
+    }
+    /// Append a row to the columns
+    ///
+    /// Panics if the pool of the columns is out of memory, see [`VBuf::try_push_row`].
+    pub fn push_row(&mut self, row: SBufRef<'_>) {
+        self.try_push_row(row).unwrap_or_else(|e| panic!("{e}"))
+    }
+    /// Append a row to the columns, or fail and leave the columns as they were
+    pub fn try_push_row(&mut self, row: SBufRef<'_>) -> Result<(), MemoryError> {
+        self.rollback_on_error(|columns| columns.try_push_row(row))
+    }
+    /// Extract the i-th row
+    pub fn row(&self, i: usize) -> SBuf {
+        let mut row = SBuf::new(DSchema::from_ref(self.schema.as_ref()));
+        self.as_ref().load_row(i, row.as_mut());
+        row
+    }
+    /// Append a dynamically typed value to the columns
+    ///
+    /// Panics if the pool of the columns is out of memory, see [`VBuf::try_push`].
+    pub fn push(&mut self, value: &Value<'_>) {
+        self.try_push(value).unwrap_or_else(|e| panic!("{e}"))
+    }
+    /// Append a dynamically typed value to the columns, or fail and leave the columns as they were
+    pub fn try_push(&mut self, value: &Value<'_>) -> Result<(), MemoryError> {
+        self.rollback_on_error(|columns| columns.try_push(value))
+    }
+    // a row that fails half way has written some columns, they are cut back to where they were
+    fn rollback_on_error(&mut self, f: impl FnOnce(VBufMut<'_>) -> Result<(), MemoryError>) -> Result<(), MemoryError> {
+        let lens = self.buffer.iter().map(|x| if x.is_counter() { x.count() } else { x.len() }).collect::<Vec<_>>();
+        if let Err(e) = f(self.as_mut()) {
+            for (column, len) in self.buffer.iter_mut().zip(lens) {
+                if column.is_counter() { *column = Bytes::counter(); column.add_count(len) } else { column.truncate(len) }
             }
-        }};}
-        let column = crate::Fill!{Match{<Here>}};
-        let buffer = (0..column).map(|_| Bytes::new()).collect();
-        VBuf {schema, buffer}
+            return Err(e)
+        }
+        self.len += 1;
+        Ok(())
+    }
+    /// Read the i-th element as a dynamically typed value
+    pub fn get(&self, i: usize) -> Value<'_> {
+        self.as_ref().get(i)
     }
 }
 
@@ -69,17 +266,288 @@ pub struct VBufMut<'a> {
     pub schema: DSchemaRef<'a>,
 }
 
+impl<'a> VBufMut<'a> {
+    pub fn reborrow(&mut self) -> VBufMut<'_> {
+        VBufMut { buffer: self.buffer, schema: self.schema }
+    }
+    /// Append a row to the columns, the row must have the same schema
+    ///
+    /// Panics if the pool of the columns is out of memory, see [`VBufMut::try_push_row`].
+    pub fn push_row(self, row: SBufRef<'_>) {
+        self.try_push_row(row).unwrap_or_else(|e| panic!("{e}"))
+    }
+    /// Append a row to the columns, a failure may leave some of the columns written
+    pub fn try_push_row(self, row: SBufRef<'_>) -> Result<(), MemoryError> {
+        assert!(self.schema == row.schema, "cannot push {:?} to {:?}", row.schema, self.schema);
+        macro_rules! Match {($($X: ident, )*) => {
+            match self.schema.tag() {
+                $($X::NUM => $X::vector_tail(self, row), )*
+                _ => unreachable!()
+            }
+        };}
+        crate::Fill!{Match{<Here>}}
+    }
+    /// Append a dynamically typed value, panics if the value does not match the schema
+    ///
+    /// Also panics if the pool of the columns is out of memory, see [`VBufMut::try_push`].
+    pub fn push(self, value: &Value<'_>) {
+        self.try_push(value).unwrap_or_else(|e| panic!("{e}"))
+    }
+    /// Append a dynamically typed value, a failure may leave some of the columns written
+    pub fn try_push(self, value: &Value<'_>) -> Result<(), MemoryError> {
+        macro_rules! Match {($($X: ident, )*) => {
+            match self.schema.tag() {
+                $($X::NUM => $X::vector_put(self, value), )*
+                _ => unreachable!()
+            }
+        };}
+        crate::Fill!{Match{<Here>}}
+    }
+    /// Split columns of a pair into the columns of its first and second element
+    pub fn pair(self) -> (VBufMut<'a>, VBufMut<'a>) {
+        let DSchemaEnum::Pair(_, snd, fst) = self.schema.decode() else { panic!("{:?} is not a pair", self.schema) };
+        let (a, b) = self.buffer.split_at_mut(fst.num_columns());
+        (VBufMut { buffer: a, schema: fst }, VBufMut { buffer: b, schema: snd })
+    }
+    /// Unwrap a named field
+    pub fn field(self) -> VBufMut<'a> {
+        let DSchemaEnum::Field(_, child) = self.schema.decode() else { panic!("{:?} is not a field", self.schema) };
+        VBufMut { buffer: self.buffer, schema: child }
+    }
+}
+
 /// Immutable Reference of Generic Vector Buffer
+/// 
+/// A view of rows `offset..offset+len` of the columns, row indices given to a view are relative to `offset`. 
 #[derive(Clone, Copy)]
 pub struct VBufRef<'a> {
     pub buffer: &'a [Bytes],
     pub schema: DSchemaRef<'a>,
+    pub offset: usize,
+    pub len: usize,
+}
+
+impl<'a> VBufRef<'a> {
+    pub fn len(&self) -> usize {
+        self.len
+    }
+    pub fn is_empty(&self) -> bool {
+        self.len == 0
+    }
+    /// A view of the rows in the range, without copying
+    pub fn slice(self, range: impl std::ops::RangeBounds<usize>) -> VBufRef<'a> {
+        let range = resolve_range(range, self.len);
+        VBufRef { offset: self.offset + range.start, len: range.len(), ..self }
+    }
+    /// Split the view into rows `..mid` and `mid..`
+    pub fn split_at(self, mid: usize) -> (VBufRef<'a>, VBufRef<'a>) {
+        (self.slice(..mid), self.slice(mid..))
+    }
+    /// Split the view into chunks of at most `size` rows
+    pub fn chunks(self, size: usize) -> impl Iterator<Item = VBufRef<'a>> {
+        assert!(size > 0, "chunk size must be positive");
+        (0..self.len).step_by(size).map(move |i| self.slice(i..(i + size).min(self.len)))
+    }
+    /// Write the i-th row into a scalar buffer of the same schema
+    pub fn load_row(self, i: usize, row: SBufMut<'_>) {
+        assert!(self.schema == row.schema, "cannot load {:?} into {:?}", self.schema, row.schema);
+        macro_rules! Match {($($X: ident, )*) => {
+            match self.schema.tag() {
+                $($X::NUM => $X::vector_load(self, i, row), )*
+                _ => unreachable!()
+            }
+        };}
+        crate::Fill!{Match{<Here>}}
+    }
+    /// Read the i-th element as a dynamically typed value
+    pub fn get(self, i: usize) -> Value<'a> {
+        macro_rules! Match {($($X: ident, )*) => {
+            match self.schema.tag() {
+                $($X::NUM => $X::vector_get(self, i), )*
+                _ => unreachable!()
+            }
+        };}
+        crate::Fill!{Match{<Here>}}
+    }
+    /// Split columns of a pair into the columns of its first and second element
+    pub fn pair(self) -> (VBufRef<'a>, VBufRef<'a>) {
+        let DSchemaEnum::Pair(_, snd, fst) = self.schema.decode() else { panic!("{:?} is not a pair", self.schema) };
+        let (a, b) = self.buffer.split_at(fst.num_columns());
+        (VBufRef { buffer: a, schema: fst, ..self }, VBufRef { buffer: b, schema: snd, ..self })
+    }
+    /// Unwrap a named field
+    pub fn field(self) -> VBufRef<'a> {
+        let DSchemaEnum::Field(_, child) = self.schema.decode() else { panic!("{:?} is not a field", self.schema) };
+        VBufRef { schema: child, ..self }
+    }
 }
 
+/// Typed access to a vector buffer
+/// 
+/// `vector_tail` and `vector_load` transpose between rows ([`SBuf`]) and columns ([`VBuf`]). 
+/// `vector_get` and `vector_put` work on [`Value`], for code that only knows the schema at runtime. 
 pub trait BufferParser<const TAG: u8> {
     type VectorRef<'a>;
     type ScalarRef<'a>;
-    fn vector_cast<'a>(buffer: VBufRef<'a>) -> Self::VectorRef<'a> { todo!() }
-    fn vector_push<'a>(buffer: VBufMut<'a>, elem: Self::ScalarRef<'a>) { todo!() }
-    fn vector_tail<'a>(buffer: VBufMut<'a>, elem: SBuf) -> SBuf { todo!() }
-}
\ No newline at end of file
+    fn vector_cast<'a>(buffer: VBufRef<'a>) -> Self::VectorRef<'a>;
+    /// Append an element, a failure may leave some of the columns written
+    fn vector_push<'a>(buffer: VBufMut<'a>, elem: Self::ScalarRef<'a>) -> Result<(), MemoryError>;
+    /// Append a row to the tail of the columns
+    fn vector_tail(buffer: VBufMut<'_>, elem: SBufRef<'_>) -> Result<(), MemoryError>;
+    /// Write the i-th row of the columns into a scalar buffer
+    fn vector_load(buffer: VBufRef<'_>, i: usize, elem: SBufMut<'_>);
+    /// Read the i-th element as a dynamically typed value
+    fn vector_get(buffer: VBufRef<'_>, i: usize) -> Value<'_>;
+    /// Append a dynamically typed value, panics if the value does not match the schema
+    fn vector_put(buffer: VBufMut<'_>, elem: &Value<'_>) -> Result<(), MemoryError>;
+}
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn scalar_layout() {
+        let schema = Pair::encode(&[U8::encode(&[]).as_ref(), Pair::encode(&[I64::encode(&[]).as_ref(), U16::encode(&[]).as_ref()]).as_ref()]);
+        assert!(schema.as_ref().scalar_layout() == ScalarLayout { size: 24, align: 8 });
+        let row = SBuf::new(schema);
+        let (a, b) = row.as_ref().pair();
+        let (c, d) = b.pair();
+        assert!(a.buffer.len() == 1 && c.buffer.len() == 8 && d.buffer.len() == 2);
+        assert!(c.buffer.as_ptr() as usize - a.buffer.as_ptr() as usize == 8);
+        assert!(d.buffer.as_ptr() as usize - a.buffer.as_ptr() as usize == 16);
+    }
+
+    #[test]
+    fn transpose() {
+        // (a: i32, s: str, p: (bool, nil), d: decimal(10, 2), f: binary(4))
+        let schema = || {
+            let p = Pair::encode(&[Bool::encode(&[]).as_ref(), Nil::encode(&[]).as_ref()]);
+            let d = Pair::encode(&[Decimal::with(10, 2).unwrap().as_ref(), FixedBinary::with(4).unwrap().as_ref()]);
+            let p = Pair::encode(&[Field::named("p", p.as_ref()).as_ref(), d.as_ref()]);
+            let s = Pair::encode(&[Field::named("s", Str::encode(&[]).as_ref()).as_ref(), p.as_ref()]);
+            Pair::encode(&[Field::named("a", I32::encode(&[]).as_ref()).as_ref(), s.as_ref()])
+        };
+        let mut columns = VBuf::new(schema());
+        let mut row = SBuf::new(schema());
+        for i in 0..100 {
+            let mut buf = row.as_mut();
+            I32::scalar_write(buf.fst().field(), &i);
+            let mut rest = buf.snd();
+            Str::scalar_write(rest.fst().field(), &"x".repeat(i as usize % 7));
+            let mut rest = rest.snd();
+            Bool::scalar_write(rest.fst().field().fst(), i % 3 == 0);
+            Decimal::scalar_write(rest.snd().fst(), &(i as i128 * 100));
+            FixedBinary::scalar_write(rest.snd().snd(), &i.to_le_bytes());
+            columns.push_row(row.as_ref());
+        }
+        assert!(I32::vector_cast(columns.as_ref().pair().0.field()).len() == 100);
+        for i in 0..100 {
+            let row = columns.row(i as usize);
+            let (a, rest) = row.as_ref().pair();
+            let (s, rest) = rest.pair();
+            let (p, rest) = rest.pair();
+            let (d, f) = rest.pair();
+            assert!(*I32::scalar_cast(a.field()) == i);
+            assert!(Str::scalar_cast(s.field()) == "x".repeat(i as usize % 7));
+            assert!(Bool::scalar_cast(p.field().pair().0) == (i % 3 == 0));
+            assert!(*Decimal::scalar_cast(d) == i as i128 * 100);
+            assert!(FixedBinary::scalar_cast(f) == i.to_le_bytes());
+        }
+    }
+
+    #[test]
+    fn morsels() {
+        // (a: i64, s: str)
+        let schema = Pair::encode(&[I64::encode(&[]).as_ref(), Str::encode(&[]).as_ref()]);
+        let mut columns = VBuf::new(schema);
+        let strings = (0..1000).map(|i| i.to_string()).collect::<Vec<_>>();
+        for (i, s) in strings.iter().enumerate() {
+            columns.push(&Value::pair(Value::I64(i as i64), Value::Str(s)));
+        }
+        assert!(columns.len() == 1000);
+        let view = columns.as_ref();
+        let (head, tail) = view.split_at(300);
+        assert!(head.len() == 300 && tail.len() == 700);
+        assert!(tail.get(0) == Value::pair(Value::I64(300), Value::Str("300")));
+        assert!(tail.slice(10..20).slice(5..).get(0) == view.get(315));
+        // hand morsels of the batch to worker threads
+        let sums = std::thread::scope(|scope| {
+            let workers = view.chunks(128).map(|morsel| scope.spawn(move || {
+                let (a, s) = morsel.pair();
+                let a = I64::vector_cast(a).iter().sum::<i64>();
+                let s = Str::vector_cast(s).iter().map(|s| s.parse::<i64>().unwrap()).sum::<i64>();
+                (a, s)
+            })).collect::<Vec<_>>();
+            workers.into_iter().map(|w| w.join().unwrap()).collect::<Vec<_>>()
+        });
+        assert!(sums.len() == 8);
+        assert!(sums.iter().all(|(a, s)| a == s));
+        assert!(sums.iter().map(|(a, _)| a).sum::<i64>() == 999 * 1000 / 2);
+    }
+
+    #[test]
+    fn memory_pool() {
+        // (a: i64, (s: str, n: nil))
+        let schema = || Pair::encode(&[I64::encode(&[]).as_ref(), Pair::encode(&[Str::encode(&[]).as_ref(), Nil::encode(&[]).as_ref()]).as_ref()]);
+        let pool = MemoryPool::new(1 << 20);
+        let mut columns = VBuf::try_new_in(schema(), &pool).unwrap();
+        let empty = pool.used();
+        assert!(empty > 0);
+        for i in 0..1000 {
+            columns.push(&Value::pair(Value::I64(i), Value::pair(Value::Str("abc"), Value::Nil)));
+        }
+        assert!(pool.used() > empty + 1000 * 8 && pool.peak() >= pool.used());
+        // a snapshot is free until written, then its copies are counted too
+        let mut snapshot = columns.clone();
+        let used = pool.used();
+        snapshot.push(&Value::pair(Value::I64(0), Value::pair(Value::Str(""), Value::Nil)));
+        assert!(pool.used() > used && snapshot.get(1000) != columns.get(999));
+        drop((columns, snapshot));
+        assert!(pool.used() == 0);
+        assert!(VBuf::try_new_in(schema(), &MemoryPool::new(0)).err() == Some(MemoryError::Limit(48, 0)));
+        // a row that runs out of memory after its first column leaves the columns as they were
+        let pool = MemoryPool::new(4096);
+        let mut columns = VBuf::try_new_in(schema(), &pool).unwrap();
+        let long = "x".repeat(8192);
+        let row = |i, s| Value::pair(Value::I64(i), Value::pair(Value::Str(s), Value::Nil));
+        for i in 0..3 { columns.try_push(&row(i, "abc")).unwrap() }
+        let lens = columns.buffer.iter().map(|x| if x.is_counter() { x.count() } else { x.len() }).collect::<Vec<_>>();
+        assert!(matches!(columns.try_push(&row(3, &long)), Err(MemoryError::Limit(..))));
+        assert!(columns.len() == 3 && columns.buffer.iter().map(|x| if x.is_counter() { x.count() } else { x.len() }).eq(lens));
+        columns.try_push(&row(3, "def")).unwrap();
+        assert!((0..4).map(|i| columns.get(i)).eq([row(0, "abc"), row(1, "abc"), row(2, "abc"), row(3, "def")]));
+    }
+
+    #[test]
+    fn scalar_read_write() {
+        // (a: i32, s: str, d: decimal(10, 2), b: bool)
+        let schema = || {
+            let s = Field::named("s", Str::encode(&[]).as_ref());
+            let d = Field::named("d", Decimal::with(10, 2).unwrap().as_ref());
+            let b = Field::named("b", Bool::encode(&[]).as_ref());
+            let rest = Pair::encode(&[d.as_ref(), b.as_ref()]);
+            let rest = Pair::encode(&[s.as_ref(), rest.as_ref()]);
+            Pair::encode(&[Field::named("a", I32::encode(&[]).as_ref()).as_ref(), rest.as_ref()])
+        };
+        let mut row = SBuf::new(schema());
+        let mut buf = row.as_mut();
+        I32::scalar_write(buf.fst().field(), &-7);
+        let mut rest = buf.snd();
+        Str::scalar_write(rest.fst().field(), "hello");
+        Str::scalar_write(rest.fst().field(), "world");
+        Decimal::scalar_write(rest.snd().fst().field(), &1234);
+        Bool::scalar_write(rest.snd().snd().field(), true);
+        let mut copy = SBuf::new(schema());
+        copy.as_mut().assign(row.as_ref());
+        for row in [row.as_ref(), copy.as_ref()] {
+            let (a, rest) = row.pair();
+            let (s, rest) = rest.pair();
+            let (d, b) = rest.pair();
+            assert!(*I32::scalar_cast(a.field()) == -7);
+            assert!(Str::scalar_cast(s.field()) == "world");
+            assert!(*Decimal::scalar_cast(d.field()) == 1234);
+            assert!(Bool::scalar_cast(b.field()));
+        }
+        assert!(copy.heap.len() == 5);
+    }
+}
@@ -0,0 +1,438 @@
+use crate::data_schema::*;
+use crate::data_buffer::*;
+use crate::kernel_primitive::{self, CmpOp, Native};
+use crate::util_bytes::Bytes;
+
+/*                                            */
+/* Lightweight encodings of integer columns   */
+/*                                            */
+
+// A column is cut into chunks, and an analyzer picks the smallest encoding for each chunk from
+// one pass of statistics. Scans decode a chunk at a time; comparisons with a constant run on
+// min/max, on runs and on packed offsets before falling back to decoding.
+// Values are handled as i128 keys while encoding, which holds any integer type without overflow.
+
+/// Rows in a chunk of [`EncodedColumn`]
+pub const CHUNK_ROWS: usize = 1 << 16;
+/// Rows between the absolute values a delta chunk keeps, so a random access sums at most this many deltas
+pub const DELTA_CHECKPOINT: usize = 128;
+
+/// Integer types that can be encoded
+pub trait Packable: Native {
+    fn key(self) -> i128;
+    /// The key is always in range of the type
+    fn from_key(key: i128) -> Self;
+}
+
+macro_rules! ImplPackable {($($Y: ty, )*) => {$(
+    impl Packable for $Y {
+        #[inline(always)]
+        fn key(self) -> i128 { self as i128 }
+        #[inline(always)]
+        fn from_key(key: i128) -> $Y { key as $Y }
+    }
+)*};}
+
+ImplPackable! { i64, i32, i16, i8, u64, u32, u16, u8, }
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Encoding {
+    /// Values as they are
+    Plain,
+    /// Distinct consecutive values and where each run ends
+    RunLength,
+    /// Offsets from the minimum, bit-packed
+    FrameOfReference,
+    /// Differences between consecutive values, offset from the smallest difference and bit-packed
+    Delta,
+}
+
+// typed view of a column written through bytemuck, an untouched column is not aligned
+fn typed<T: bytemuck::Pod>(bytes: &Bytes) -> &[T] {
+    if bytes.len() == 0 { &[] } else { bytemuck::cast_slice(bytes.slice(..)) }
+}
+
+// bits needed for offsets in 0..=range, or `None` if they don't fit in 64 bits
+fn bits(range: i128) -> Option<u32> {
+    (range <= u64::MAX as i128).then(|| 64 - (range as u64).leading_zeros())
+}
+
+/// Unsigned integers of `bits` bits each, packed back to back in u64 words
+pub struct BitPacked {
+    bits: u32,
+    len: usize,
+    words: Bytes,
+}
+
+impl BitPacked {
+    pub fn pack(values: impl ExactSizeIterator<Item = u64>, bits: u32) -> BitPacked {
+        assert!(bits <= 64, "cannot pack {bits} bits in a u64");
+        let len = values.len();
+        let mut words = vec![0u64; (len * bits as usize).div_ceil(64)];
+        for (i, v) in values.enumerate() {
+            debug_assert!(bits == 64 || v >> bits == 0, "{v} doesn't fit in {bits} bits");
+            let (w, off) = (i * bits as usize / 64, (i * bits as usize % 64) as u32);
+            if bits == 0 { continue }
+            words[w] |= v << off;
+            if off + bits > 64 { words[w + 1] |= v >> (64 - off) }
+        }
+        let mut bytes = Bytes::new();
+        bytes.extend(bytemuck::cast_slice(&words));
+        BitPacked { bits, len, words: bytes }
+    }
+    pub fn bits(&self) -> u32 {
+        self.bits
+    }
+    pub fn len(&self) -> usize {
+        self.len
+    }
+    pub fn is_empty(&self) -> bool {
+        self.len == 0
+    }
+    pub fn get(&self, i: usize) -> u64 {
+        assert!(i < self.len, "{i} is out of 0..{}", self.len);
+        if self.bits == 0 { return 0 }
+        let words = typed::<u64>(&self.words);
+        let (w, off) = (i * self.bits as usize / 64, (i * self.bits as usize % 64) as u32);
+        let mut v = words[w] >> off;
+        if off + self.bits > 64 { v |= words[w + 1] << (64 - off) }
+        if self.bits == 64 { v } else { v & ((1 << self.bits) - 1) }
+    }
+    pub fn unpack(&self) -> Vec<u64> {
+        (0..self.len).map(|i| self.get(i)).collect()
+    }
+    pub fn size(&self) -> usize {
+        self.words.len()
+    }
+}
+
+/// Statistics an encoding is chosen from
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Stats {
+    pub len: usize,
+    pub min: i128,
+    pub max: i128,
+    /// Number of runs of equal consecutive values
+    pub runs: usize,
+    /// Smallest and largest difference of consecutive values
+    pub delta_min: i128,
+    pub delta_max: i128,
+}
+
+impl Stats {
+    pub fn analyze<T: Packable>(values: &[T]) -> Stats {
+        let mut stats = Stats { len: values.len(), min: i128::MAX, max: i128::MIN, runs: 0, delta_min: i128::MAX, delta_max: i128::MIN };
+        let mut last = None;
+        for x in values {
+            let x = x.key();
+            stats.min = stats.min.min(x);
+            stats.max = stats.max.max(x);
+            if let Some(last) = last {
+                stats.delta_min = stats.delta_min.min(x - last);
+                stats.delta_max = stats.delta_max.max(x - last);
+            }
+            stats.runs += (last != Some(x)) as usize;
+            last = Some(x);
+        }
+        stats
+    }
+    /// Bytes taken by the encoding, or `None` if it cannot hold the values
+    pub fn estimate(&self, encoding: Encoding, width: usize) -> Option<usize> {
+        match encoding {
+            Encoding::Plain => Some(self.len * width),
+            Encoding::RunLength => Some(self.runs * (width + 4)),
+            Encoding::FrameOfReference if self.len == 0 => None,
+            Encoding::FrameOfReference => Some((self.len * bits(self.max - self.min)? as usize).div_ceil(64) * 8),
+            Encoding::Delta if self.len < 2 => None,
+            Encoding::Delta => Some(((self.len - 1) * bits(self.delta_max - self.delta_min)? as usize).div_ceil(64) * 8 + self.len.div_ceil(DELTA_CHECKPOINT) * width),
+        }
+    }
+    /// The smallest encoding, ties go to the one that is cheaper to decode
+    pub fn choose(&self, width: usize) -> Encoding {
+        [Encoding::Plain, Encoding::RunLength, Encoding::FrameOfReference, Encoding::Delta].into_iter()
+            .filter_map(|e| Some((self.estimate(e, width)?, e)))
+            .min_by_key(|(size, _)| *size)
+            .map_or(Encoding::Plain, |(_, e)| e)
+    }
+}
+
+enum Encoded<T> {
+    Plain(Bytes),
+    RunLength { values: Bytes, ends: Bytes },
+    FrameOfReference { base: T, packed: BitPacked },
+    // values at every DELTA_CHECKPOINT-th row, starting with the first
+    Delta { checkpoints: Bytes, base: i128, packed: BitPacked },
+}
+
+/// A chunk of integers in one encoding, with its min and max to skip it in filters
+pub struct EncodedChunk<T> {
+    len: usize,
+    min: T,
+    max: T,
+    data: Encoded<T>,
+}
+
+impl<T: Packable> EncodedChunk<T> {
+    /// Encode with the encoding chosen by [`Stats::choose`]
+    pub fn encode(values: &[T]) -> EncodedChunk<T> {
+        let stats = Stats::analyze(values);
+        Self::encode_with(values, &stats, stats.choose(std::mem::size_of::<T>()))
+    }
+    /// Encode with the given encoding, panics if it cannot hold the values (see [`Stats::estimate`])
+    pub fn encode_as(values: &[T], encoding: Encoding) -> EncodedChunk<T> {
+        Self::encode_with(values, &Stats::analyze(values), encoding)
+    }
+    fn encode_with(values: &[T], stats: &Stats, encoding: Encoding) -> EncodedChunk<T> {
+        assert!(values.len() <= u32::MAX as usize, "a chunk holds at most {} rows", u32::MAX);
+        assert!(stats.estimate(encoding, std::mem::size_of::<T>()).is_some(), "{encoding:?} cannot hold the values");
+        let (len, min, max) = (values.len(), T::from_key(stats.min), T::from_key(stats.max));
+        let data = match encoding {
+            Encoding::Plain => {
+                let mut bytes = Bytes::new();
+                bytes.extend(bytemuck::cast_slice(values));
+                Encoded::Plain(bytes)
+            }
+            Encoding::RunLength => {
+                let (mut runs, mut ends) = (Vec::<T>::new(), Vec::<u32>::new());
+                for (i, x) in values.iter().enumerate() {
+                    if runs.last() != Some(x) { runs.push(*x); ends.push(0) }
+                    *ends.last_mut().unwrap() = i as u32 + 1;
+                }
+                let (mut values, mut bytes) = (Bytes::new(), Bytes::new());
+                values.extend(bytemuck::cast_slice(&runs));
+                bytes.extend(bytemuck::cast_slice(&ends));
+                Encoded::RunLength { values, ends: bytes }
+            }
+            Encoding::FrameOfReference => {
+                let bits = bits(stats.max - stats.min).unwrap();
+                let packed = BitPacked::pack(values.iter().map(|x| (x.key() - stats.min) as u64), bits);
+                Encoded::FrameOfReference { base: min, packed }
+            }
+            Encoding::Delta => {
+                let bits = bits(stats.delta_max - stats.delta_min).unwrap();
+                let packed = BitPacked::pack(values.windows(2).map(|w| (w[1].key() - w[0].key() - stats.delta_min) as u64), bits);
+                let mut checkpoints = Bytes::new();
+                for x in values.iter().step_by(DELTA_CHECKPOINT) { checkpoints.extend(bytemuck::bytes_of(x)) }
+                Encoded::Delta { checkpoints, base: stats.delta_min, packed }
+            }
+        };
+        EncodedChunk { len, min, max, data }
+    }
+    pub fn encoding(&self) -> Encoding {
+        match self.data {
+            Encoded::Plain(_) => Encoding::Plain,
+            Encoded::RunLength { .. } => Encoding::RunLength,
+            Encoded::FrameOfReference { .. } => Encoding::FrameOfReference,
+            Encoded::Delta { .. } => Encoding::Delta,
+        }
+    }
+    pub fn len(&self) -> usize {
+        self.len
+    }
+    pub fn is_empty(&self) -> bool {
+        self.len == 0
+    }
+    /// Bytes of the encoded values
+    pub fn size(&self) -> usize {
+        match &self.data {
+            Encoded::Plain(bytes) => bytes.len(),
+            Encoded::RunLength { values, ends } => values.len() + ends.len(),
+            Encoded::FrameOfReference { packed, .. } => packed.size(),
+            Encoded::Delta { checkpoints, packed, .. } => checkpoints.len() + packed.size(),
+        }
+    }
+    /// The i-th value, delta sums up the differences since the last checkpoint
+    pub fn get(&self, i: usize) -> T {
+        assert!(i < self.len, "{i} is out of 0..{}", self.len);
+        match &self.data {
+            Encoded::Plain(bytes) => typed::<T>(bytes)[i],
+            Encoded::RunLength { values, ends } => typed::<T>(values)[typed::<u32>(ends).partition_point(|end| *end as usize <= i)],
+            Encoded::FrameOfReference { base, packed } => T::from_key(base.key() + packed.get(i) as i128),
+            Encoded::Delta { checkpoints, base, packed } => {
+                let at = i / DELTA_CHECKPOINT;
+                let start = typed::<T>(checkpoints)[at].key();
+                T::from_key((at * DELTA_CHECKPOINT..i).fold(start, |x, k| x + base + packed.get(k) as i128))
+            }
+        }
+    }
+    /// Append the decoded values
+    pub fn decode_into(&self, out: &mut Vec<T>) {
+        out.reserve(self.len);
+        match &self.data {
+            Encoded::Plain(bytes) => out.extend_from_slice(typed::<T>(bytes)),
+            Encoded::RunLength { values, ends } => {
+                let mut start = 0;
+                for (x, end) in typed::<T>(values).iter().zip(typed::<u32>(ends)) {
+                    out.extend(std::iter::repeat_n(*x, *end as usize - start));
+                    start = *end as usize;
+                }
+            }
+            Encoded::FrameOfReference { base, packed } => {
+                let base = base.key();
+                out.extend(packed.unpack().into_iter().map(|p| T::from_key(base + p as i128)));
+            }
+            Encoded::Delta { checkpoints, base, packed } => {
+                let first = typed::<T>(checkpoints)[0];
+                let mut x = first.key();
+                out.push(first);
+                out.extend(packed.unpack().into_iter().map(|p| { x += base + p as i128; T::from_key(x) }));
+            }
+        }
+    }
+    pub fn decode(&self) -> Vec<T> {
+        let mut out = Vec::new();
+        self.decode_into(&mut out);
+        out
+    }
+    /// Compare each value with a constant into a bool column, without decoding when possible
+    pub fn compare_scalar(&self, op: CmpOp, value: T) -> VBuf {
+        let constant = |b: bool| Bool::column(std::iter::repeat_n(b, self.len));
+        let (v, min, max) = (value.key(), self.min.key(), self.max.key());
+        // the constant is outside the values, so every row compares the same
+        if self.len == 0 || v < min || v > max {
+            let above = v > max;
+            return constant(match op {
+                CmpOp::Eq => false,
+                CmpOp::Ne => true,
+                CmpOp::Lt | CmpOp::Le => above,
+                CmpOp::Gt | CmpOp::Ge => !above,
+            })
+        }
+        match &self.data {
+            Encoded::RunLength { values, ends } => {
+                let hits = kernel_primitive::compare_scalar(typed::<T>(values), value, op);
+                let hits = Bool::vector_cast(hits.as_ref());
+                let mut start = 0;
+                Bool::column(typed::<u32>(ends).iter().enumerate().flat_map(|(r, end)| {
+                    let run = std::iter::repeat_n(hits.get(r), *end as usize - start);
+                    start = *end as usize;
+                    run
+                }))
+            }
+            // min <= v <= max, so v - min is a valid packed offset
+            Encoded::FrameOfReference { packed, .. } => kernel_primitive::compare_scalar(&packed.unpack(), (v - min) as u64, op),
+            _ => kernel_primitive::compare_scalar(&self.decode(), value, op),
+        }
+    }
+}
+
+/// An integer column cut into chunks of [`CHUNK_ROWS`], each with its own encoding
+pub struct EncodedColumn<T> {
+    pub chunks: Vec<EncodedChunk<T>>,
+}
+
+impl<T: Packable> EncodedColumn<T> {
+    pub fn encode(values: &[T]) -> EncodedColumn<T> {
+        Self::encode_chunks(values, CHUNK_ROWS)
+    }
+    pub fn encode_chunks(values: &[T], rows: usize) -> EncodedColumn<T> {
+        EncodedColumn { chunks: values.chunks(rows).map(EncodedChunk::encode).collect() }
+    }
+    pub fn len(&self) -> usize {
+        self.chunks.iter().map(|c| c.len()).sum()
+    }
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+    /// Bytes of the encoded values
+    pub fn size(&self) -> usize {
+        self.chunks.iter().map(|c| c.size()).sum()
+    }
+    /// Decode one chunk at a time into a primitive column
+    pub fn scan(&self) -> impl Iterator<Item = VBuf> + '_ {
+        self.chunks.iter().map(|c| column(&c.decode()))
+    }
+    pub fn decode(&self) -> VBuf {
+        let mut out = Vec::with_capacity(self.len());
+        for c in &self.chunks { c.decode_into(&mut out) }
+        column(&out)
+    }
+    /// Compare each value with a constant into a bool column
+    pub fn compare_scalar(&self, op: CmpOp, value: T) -> VBuf {
+        if self.chunks.is_empty() { return Bool::column([]) }
+        let hits = self.chunks.iter().map(|c| c.compare_scalar(op, value)).collect::<Vec<_>>();
+        crate::kernel_select::concat(&hits.iter().map(|h| h.as_ref()).collect::<Vec<_>>())
+    }
+}
+
+// a column of the primitive type of `T`
+fn column<T: Native>(values: &[T]) -> VBuf {
+    let mut column = VBuf::new(DSchema::from_primitive::<T::Tag>());
+    column.buffer[0].extend(bytemuck::cast_slice(values));
+    column.len = values.len();
+    column
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use rand::*;
+    use rand_xoshiro::*;
+
+    fn all_ops() -> [CmpOp; 6] {
+        [CmpOp::Eq, CmpOp::Ne, CmpOp::Lt, CmpOp::Le, CmpOp::Gt, CmpOp::Ge]
+    }
+
+    fn check<T: Packable + std::fmt::Debug>(values: &[T], probes: &[T]) {
+        for encoding in [Encoding::Plain, Encoding::RunLength, Encoding::FrameOfReference, Encoding::Delta] {
+            if Stats::analyze(values).estimate(encoding, std::mem::size_of::<T>()).is_none() { continue }
+            let chunk = EncodedChunk::encode_as(values, encoding);
+            assert!(chunk.encoding() == encoding);
+            assert!(chunk.decode() == values, "{encoding:?}");
+            assert!((0..values.len()).step_by(7).all(|i| chunk.get(i) == values[i]), "{encoding:?}");
+            for op in all_ops() {
+                for p in probes {
+                    let expect = kernel_primitive::compare_scalar(values, *p, op);
+                    let actual = chunk.compare_scalar(op, *p);
+                    let (expect, actual) = (Bool::vector_cast(expect.as_ref()), Bool::vector_cast(actual.as_ref()));
+                    assert!(expect.iter().eq(actual.iter()), "{encoding:?} {op:?} {p:?}");
+                }
+            }
+        }
+    }
+
+    #[test]
+    fn round_trip() {
+        let mut rng = Xoroshiro128Plus::seed_from_u64(4300);
+        let small = (0..1000).map(|_| rng.gen_range(-50..50i32)).collect::<Vec<_>>();
+        check(&small, &[-51, -50, 0, 49, 50, 100]);
+        let sorted = (0..1000).scan(1_700_000_000_000_000i64, |t, _| { *t += rng.gen_range(900..1100); Some(*t) }).collect::<Vec<_>>();
+        check(&sorted, &[0, sorted[0], sorted[500], sorted[999] + 1]);
+        let delta = EncodedChunk::encode_as(&sorted, Encoding::Delta);
+        assert!([127, 128, 129, 255, 256, 999].into_iter().all(|i| delta.get(i) == sorted[i]));
+        assert!(delta.size() == Stats::analyze(&sorted).estimate(Encoding::Delta, 8).unwrap());
+        let runs = (0..1000).map(|i| (i / 100) as u8).collect::<Vec<_>>();
+        check(&runs, &[0, 5, 9, 10, 255]);
+        let extremes = [u64::MAX, 0, u64::MAX, 1];
+        check(&extremes, &[0, 1, u64::MAX]);
+        let extremes = [i64::MIN, i64::MAX, 0, i64::MIN];
+        check(&extremes, &[i64::MIN, 0, i64::MAX]);
+        check::<i16>(&[], &[0]);
+        check::<i16>(&[7], &[6, 7, 8]);
+        let packed = BitPacked::pack([1u64, 0, 5, 7, 3].into_iter(), 3);
+        assert!(packed.unpack() == [1, 0, 5, 7, 3] && packed.size() == 8);
+    }
+
+    #[test]
+    fn analyzer() {
+        let mut rng = Xoroshiro128Plus::seed_from_u64(4301);
+        let sorted = (0..CHUNK_ROWS as i64).map(|i| 1_700_000_000 + i * 60).collect::<Vec<_>>();
+        assert!(EncodedChunk::encode(&sorted).encoding() == Encoding::Delta);
+        let status = (0..CHUNK_ROWS).map(|i| (i / 4096) as i32).collect::<Vec<_>>();
+        assert!(EncodedChunk::encode(&status).encoding() == Encoding::RunLength);
+        let ages = (0..CHUNK_ROWS).map(|_| rng.gen_range(18..90u64)).collect::<Vec<_>>();
+        assert!(EncodedChunk::encode(&ages).encoding() == Encoding::FrameOfReference);
+        let noise = (0..CHUNK_ROWS).map(|_| rng.gen::<u16>()).collect::<Vec<_>>();
+        assert!(EncodedChunk::encode(&noise).encoding() == Encoding::Plain);
+        // chunks pick their own encoding, and the column decodes back in order
+        let values = [&sorted[..1000], &ages.iter().map(|x| *x as i64).collect::<Vec<_>>()[..1000]].concat();
+        let column = EncodedColumn::encode_chunks(&values, 1000);
+        assert!(column.chunks.iter().map(|c| c.encoding()).eq([Encoding::Delta, Encoding::FrameOfReference]));
+        assert!(column.size() * 4 < values.len() * 8);
+        assert!(I64::vector_cast(column.decode().as_ref()) == values);
+        assert!(column.scan().map(|c| c.len()).eq([1000, 1000]));
+        let hits = column.compare_scalar(CmpOp::Lt, 50);
+        let expect = kernel_primitive::compare_scalar(&values, 50, CmpOp::Lt);
+        assert!(Bool::vector_cast(hits.as_ref()).iter().eq(Bool::vector_cast(expect.as_ref()).iter()));
+    }
+}
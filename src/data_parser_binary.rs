@@ -0,0 +1,238 @@
+use crate::data_schema::*;
+use crate::data_buffer::*;
+use crate::data_value::Value;
+use crate::util_bytes::MemoryError;
+
+//                                     //
+// Implementation of binary blob type  //
+//                                     //
+
+// blob shares the layout of str (data in column 0, offsets with a leading zero in column 1), but it is not utf-8
+impl Primitive for Blob {}
+
+impl DSchemaParser<{Tag::Blob as u8}> for Blob {
+    fn decode<'a>(_: DSchemaRef<'a>) -> DSchemaEnum<'a> {
+        DSchemaEnum::Blob
+    }
+    fn encode<'a>(children: &[DSchemaRef<'a>]) -> DSchema {
+        assert!(children.is_empty());
+        DSchema::from_primitive::<Blob>()
+    }
+    fn scalar_layout<'a>(_: DSchemaRef<'a>) -> ScalarLayout {
+        let m = std::alloc::Layout::new::<&[u8]>();
+        ScalarLayout {
+            size: m.size(),
+            align: m.align()
+        }
+    }
+    fn dbg(_: DSchemaRef<'_>, fmt: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(fmt, "{:?}", Tag::Blob)
+    }
+    fn num_columns<'a>(_: DSchemaRef<'a>) -> usize {
+        2
+    }
+    fn validate(schema: DSchemaRef<'_>, _: usize) -> Result<(), DSchemaError> {
+        if schema.len() != 0 { Err(DSchemaError::TrailingBytes(schema.len()))? }
+        Ok(())
+    }
+}
+
+impl BufferParser<{Tag::Blob as u8}> for Blob {
+    type ScalarRef<'a> = &'a [u8];
+    type VectorRef<'a> = FlatBlob<'a>;
+    fn vector_cast<'a>(buffer: VBufRef<'a>) -> Self::VectorRef<'a> {
+        assert!(buffer.buffer.len() == 2);
+        let (columns, range) = (buffer.buffer, buffer.offset..buffer.offset+buffer.len);
+        // an untouched column has no leading zero yet, offsets are absolute so a view only narrows them
+        let offset: &[u64] = if columns[1].len() == 0 { &[0] } else { bytemuck::cast_slice(columns[1].slice(..)) };
+        FlatBlob {
+            offset: &offset[range.start..=range.end],
+            buffer: columns[0].slice(..),
+        }
+    }
+    fn vector_push<'a>(buffer: VBufMut<'a>, elem: &[u8]) -> Result<(), MemoryError> {
+        assert!(buffer.buffer.len() == 2);
+        if buffer.buffer[1].len() == 0 { buffer.buffer[1].try_extend(&0u64.to_ne_bytes())? }
+        buffer.buffer[0].try_extend(elem)?;
+        buffer.buffer[1].try_extend(&(buffer.buffer[0].len() as u64).to_ne_bytes())
+    }
+    fn vector_tail(buffer: VBufMut<'_>, elem: SBufRef<'_>) -> Result<(), MemoryError> {
+        Self::vector_push(buffer, Self::scalar_cast(elem))
+    }
+    fn vector_load(buffer: VBufRef<'_>, i: usize, elem: SBufMut<'_>) {
+        Self::scalar_write(elem, Self::vector_cast(buffer).get(i))
+    }
+    fn vector_get(buffer: VBufRef<'_>, i: usize) -> Value<'_> {
+        Value::Blob(Self::vector_cast(buffer).get(i))
+    }
+    fn vector_put(buffer: VBufMut<'_>, elem: &Value<'_>) -> Result<(), MemoryError> {
+        let Value::Blob(x) = elem else { panic!("cannot push {elem:?} to {:?}", buffer.schema) };
+        Self::vector_push(buffer, x)
+    }
+}
+
+impl SBufParser<{Tag::Blob as u8}> for Blob {
+    type ScalarRef<'a> = &'a [u8];
+    fn scalar_cast<'a>(buf: SBufRef<'a>) -> Self::ScalarRef<'a> {
+        buf.get_heap()
+    }
+    fn scalar_write(mut buf: SBufMut<'_>, elem: Self::ScalarRef<'_>) {
+        buf.put_heap(elem);
+    }
+}
+
+pub struct FlatBlob<'a> {
+    offset: &'a [u64],
+    buffer: &'a [u8],
+}
+
+impl<'a> FlatBlob<'a> {
+    pub fn len(&self) -> usize {
+        self.offset.len() - 1
+    }
+    pub fn get(&self, i: usize) -> &'a [u8] {
+        &self.buffer[self.offset[i] as usize..self.offset[i+1] as usize]
+    }
+    pub fn iter(&self) -> impl Iterator<Item = &'a [u8]> + '_ {
+        (0..self.len()).map(|i| self.get(i))
+    }
+}
+
+//                                             //
+// Implementation of fixed-size binary type    //
+//                                             //
+
+// the width (u32) is stored before the tag, values are stored back to back in one column
+impl DSchemaParser<{Tag::FixedBinary as u8}> for FixedBinary {
+    fn decode<'a>(schema: DSchemaRef<'a>) -> DSchemaEnum<'a> {
+        DSchemaEnum::FixedBinary(schema.u32())
+    }
+    fn encode<'a>(children: &[DSchemaRef<'a>]) -> DSchema {
+        panic!("fixed binary needs a width, use FixedBinary::with(width) instead");
+    }
+    fn scalar_layout<'a>(schema: DSchemaRef<'a>) -> ScalarLayout {
+        ScalarLayout{size: schema.u32() as usize, align: 1}
+    }
+    fn dbg(schema: DSchemaRef<'_>, fmt: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(fmt, "{:?}({})", Tag::FixedBinary, schema.u32())
+    }
+    fn num_columns<'a>(_: DSchemaRef<'a>) -> usize {
+        1
+    }
+    fn validate(schema: DSchemaRef<'_>, at: usize) -> Result<(), DSchemaError> {
+        if schema.len() < 4 { Err(DSchemaError::Truncated(at))? }
+        if schema.len() > 4 { Err(DSchemaError::TrailingBytes(schema.len() - 4))? }
+        if schema.u32() == 0 { Err(DSchemaError::BadParameter { tag: Tag::FixedBinary, at })? }
+        Ok(())
+    }
+}
+
+impl FixedBinary {
+    /// Schema of binary(width), or `None` if the width is zero
+    pub fn with(width: u32) -> Option<DSchema> {
+        if width == 0 { return None }
+        let mut schema = DSchema::empty();
+        schema.put(width);
+        schema.put(Tag::FixedBinary as u8);
+        Some(schema)
+    }
+}
+
+impl BufferParser<{Tag::FixedBinary as u8}> for FixedBinary {
+    type ScalarRef<'a> = &'a [u8];
+    type VectorRef<'a> = FlatFixedBinary<'a>;
+    fn vector_cast<'a>(buffer: VBufRef<'a>) -> Self::VectorRef<'a> {
+        let width = buffer.schema.cut(1).u32() as usize;
+        FlatFixedBinary {
+            width,
+            buffer: &buffer.buffer[buffer.buffer.len()-1].slice(..)[buffer.offset*width..(buffer.offset+buffer.len)*width],
+        }
+    }
+    fn vector_push<'a>(buffer: VBufMut<'a>, elem: &[u8]) -> Result<(), MemoryError> {
+        let width = buffer.schema.cut(1).u32() as usize;
+        assert!(elem.len() == width, "binary({width}) cannot hold {} bytes", elem.len());
+        buffer.buffer[buffer.buffer.len()-1].try_extend(elem)
+    }
+    fn vector_tail(buffer: VBufMut<'_>, elem: SBufRef<'_>) -> Result<(), MemoryError> {
+        Self::vector_push(buffer, Self::scalar_cast(elem))
+    }
+    fn vector_load(buffer: VBufRef<'_>, i: usize, elem: SBufMut<'_>) {
+        Self::scalar_write(elem, Self::vector_cast(buffer).get(i))
+    }
+    fn vector_get(buffer: VBufRef<'_>, i: usize) -> Value<'_> {
+        Value::FixedBinary(Self::vector_cast(buffer).get(i))
+    }
+    fn vector_put(buffer: VBufMut<'_>, elem: &Value<'_>) -> Result<(), MemoryError> {
+        let Value::FixedBinary(x) = elem else { panic!("cannot push {elem:?} to {:?}", buffer.schema) };
+        Self::vector_push(buffer, x)
+    }
+}
+
+impl SBufParser<{Tag::FixedBinary as u8}> for FixedBinary {
+    type ScalarRef<'a> = &'a [u8];
+    fn scalar_cast<'a>(buf: SBufRef<'a>) -> Self::ScalarRef<'a> {
+        buf.buffer
+    }
+    fn scalar_write(buf: SBufMut<'_>, elem: Self::ScalarRef<'_>) {
+        assert!(elem.len() == buf.buffer.len(), "binary({}) cannot hold {} bytes", buf.buffer.len(), elem.len());
+        buf.buffer.copy_from_slice(elem);
+    }
+}
+
+pub struct FlatFixedBinary<'a> {
+    width: usize,
+    buffer: &'a [u8],
+}
+
+impl<'a> FlatFixedBinary<'a> {
+    pub fn width(&self) -> usize {
+        self.width
+    }
+    pub fn len(&self) -> usize {
+        self.buffer.len() / self.width
+    }
+    pub fn get(&self, i: usize) -> &'a [u8] {
+        &self.buffer[i * self.width..(i + 1) * self.width]
+    }
+    pub fn iter(&self) -> impl Iterator<Item = &'a [u8]> + '_ {
+        self.buffer.chunks_exact(self.width)
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn push_cast() {
+        let mut columns = VBuf::new(Blob::encode(&[]));
+        let items: [&[u8]; 3] = [&[0xff, 0xfe], &[], &[0, 159, 146, 150]];
+        for x in items {
+            columns.push(&Value::Blob(x));
+        }
+        let flat = Blob::vector_cast(columns.as_ref());
+        assert!(flat.iter().eq(items.into_iter()));
+        assert!(Blob::vector_cast(columns.as_ref().slice(1..)).iter().eq(items[1..].iter().copied()));
+        let uuid = FixedBinary::with(16).unwrap();
+        assert!(format!("{:?}", uuid.as_ref()) == "FixedBinary(16)");
+        let mut columns = VBuf::new(uuid);
+        for i in 0..10u8 {
+            columns.push(&Value::FixedBinary(&[i; 16]));
+        }
+        let flat = FixedBinary::vector_cast(columns.as_ref());
+        assert!(flat.len() == 10 && flat.get(3) == [3; 16]);
+        let flat = FixedBinary::vector_cast(columns.as_ref().slice(4..6));
+        assert!(flat.len() == 2 && flat.get(1) == [5; 16]);
+        let corrupted = DSchemaRef::new(&[0, 0, 0, 0, Tag::FixedBinary as u8]);
+        assert!(corrupted.err() == Some(DSchemaError::BadParameter { tag: Tag::FixedBinary, at: 0 }));
+    }
+
+    #[test]
+    #[should_panic]
+    fn fixed_cast_past_the_column() {
+        let mut columns = VBuf::new(FixedBinary::with(4).unwrap());
+        columns.push(&Value::FixedBinary(&[1; 4]));
+        columns.len = 2;
+        FixedBinary::vector_cast(columns.as_ref());
+    }
+}
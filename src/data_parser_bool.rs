@@ -0,0 +1,162 @@
+use crate::data_schema::*;
+use crate::data_buffer::*;
+use crate::data_value::Value;
+use crate::util_bytes::{MemoryError, MemoryPool};
+use std::sync::Arc;
+
+/*                                        */
+/* Implementation of (bit-packed) boolean */
+/*                                        */
+
+impl Primitive for Bool {}
+impl DSchemaParser<{Tag::Bool as u8}> for Bool {
+    #[inline(always)]
+    fn decode<'a>(_: DSchemaRef<'a>) -> DSchemaEnum<'a> {
+        DSchemaEnum::Bool
+    }
+    fn encode<'a>(children: &[DSchemaRef<'a>]) -> DSchema {
+        assert!(children.is_empty());
+        DSchema::from_primitive::<Bool>()
+    }
+    fn scalar_layout<'a>(_: DSchemaRef<'a>) -> ScalarLayout {
+        ScalarLayout{size: 1, align: 1}
+    }
+    fn dbg(_: DSchemaRef<'_>, fmt: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(fmt, "{:?}", Tag::Bool)
+    }
+    fn num_columns<'a>(_: DSchemaRef<'a>) -> usize {
+        1
+    }
+    fn validate(schema: DSchemaRef<'_>, _: usize) -> Result<(), DSchemaError> {
+        if schema.len() != 0 { Err(DSchemaError::TrailingBytes(schema.len()))? }
+        Ok(())
+    }
+}
+
+// the column holds the number of bits (u64) followed by the bits, the least significant bit comes first
+impl BufferParser<{Tag::Bool as u8}> for Bool {
+    type ScalarRef<'a> = &'a bool;
+    type VectorRef<'a> = FlatBool<'a>;
+    fn vector_cast<'a>(buffer: VBufRef<'a>) -> Self::VectorRef<'a> {
+        let column = &buffer.buffer[buffer.buffer.len()-1];
+        if column.len() == 0 { return FlatBool { start: 0, len: 0, bits: &[] }.slice(buffer.offset..buffer.offset+buffer.len) }
+        FlatBool {
+            start: 0,
+            len: bytemuck::pod_read_unaligned::<u64>(column.slice(..8)) as usize,
+            bits: column.slice(8..),
+        }.slice(buffer.offset..buffer.offset+buffer.len)
+    }
+    fn vector_push<'a>(buffer: VBufMut<'a>, elem: Self::ScalarRef<'a>) -> Result<(), MemoryError> {
+        let column = &mut buffer.buffer[buffer.buffer.len()-1];
+        if column.len() == 0 { column.try_extend(&0u64.to_ne_bytes())? }
+        let len = bytemuck::pod_read_unaligned::<u64>(column.slice(..8)) as usize;
+        // the reserve copies shared bytes, so the write below doesn't
+        column.try_reserve(1)?;
+        if len.is_multiple_of(8) { column.push(0) }
+        let bytes = column.slice_mut(..);
+        bytes[8 + len / 8] |= (*elem as u8) << (len % 8);
+        bytes[..8].copy_from_slice(&(len as u64 + 1).to_ne_bytes());
+        Ok(())
+    }
+    fn vector_tail(buffer: VBufMut<'_>, elem: SBufRef<'_>) -> Result<(), MemoryError> {
+        Self::vector_push(buffer, &Self::scalar_cast(elem))
+    }
+    fn vector_load(buffer: VBufRef<'_>, i: usize, elem: SBufMut<'_>) {
+        Self::scalar_write(elem, Self::vector_cast(buffer).get(i))
+    }
+    fn vector_get(buffer: VBufRef<'_>, i: usize) -> Value<'_> {
+        Value::Bool(Self::vector_cast(buffer).get(i))
+    }
+    fn vector_put(buffer: VBufMut<'_>, elem: &Value<'_>) -> Result<(), MemoryError> {
+        let Value::Bool(x) = elem else { panic!("cannot push {elem:?} to {:?}", buffer.schema) };
+        Self::vector_push(buffer, x)
+    }
+}
+
+impl Bool {
+    /// Pack bits into a bool column, 8 bits at a time
+    pub fn column(bits: impl IntoIterator<Item = bool>) -> VBuf {
+        Bool::pack(bits, VBuf::new(Bool::encode(&[]))).unwrap_or_else(|e| panic!("{e}"))
+    }
+    /// Pack bits into a bool column allocated in `pool`
+    pub fn try_column_in(bits: impl IntoIterator<Item = bool>, pool: &Arc<MemoryPool>) -> Result<VBuf, MemoryError> {
+        Bool::pack(bits, VBuf::try_new_in(Bool::encode(&[]), pool)?)
+    }
+    fn pack(bits: impl IntoIterator<Item = bool>, mut column: VBuf) -> Result<VBuf, MemoryError> {
+        let (mut bytes, mut len) = (vec![], 0usize);
+        for b in bits {
+            if len.is_multiple_of(8) { bytes.push(0u8) }
+            bytes[len / 8] |= (b as u8) << (len % 8);
+            len += 1;
+        }
+        if len == 0 { return Ok(column) }
+        column.buffer[0].try_extend(&(len as u64).to_ne_bytes())?;
+        column.buffer[0].try_extend(&bytes)?;
+        column.len = len;
+        Ok(column)
+    }
+}
+
+// a boolean scalar takes a whole byte
+impl SBufParser<{Tag::Bool as u8}> for Bool {
+    type ScalarRef<'a> = bool;
+    fn scalar_cast<'a>(buf: SBufRef<'a>) -> Self::ScalarRef<'a> {
+        buf.buffer[0] != 0
+    }
+    fn scalar_write(buf: SBufMut<'_>, elem: Self::ScalarRef<'_>) {
+        buf.buffer[0] = elem as u8;
+    }
+}
+
+// bits of a slice may not start at a byte boundary, so the first bit is kept in `start`
+pub struct FlatBool<'a> {
+    start: usize,
+    len: usize,
+    bits: &'a [u8],
+}
+
+impl<'a> FlatBool<'a> {
+    pub fn len(&self) -> usize {
+        self.len
+    }
+    pub fn is_empty(&self) -> bool {
+        self.len == 0
+    }
+    pub fn get(&self, i: usize) -> bool {
+        assert!(i < self.len, "index {i} out of range 0..{}", self.len);
+        let i = self.start + i;
+        self.bits[i / 8] >> (i % 8) & 1 == 1
+    }
+    /// Bits in the range, without copying
+    pub fn slice(&self, range: impl std::ops::RangeBounds<usize>) -> FlatBool<'a> {
+        let range = resolve_range(range, self.len);
+        let start = self.start + range.start;
+        FlatBool { start: start % 8, len: range.len(), bits: &self.bits[start / 8..] }
+    }
+    pub fn iter(&self) -> impl Iterator<Item = bool> + '_ {
+        (0..self.len).map(|i| self.get(i))
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn push_cast() {
+        let mut columns = VBuf::new(Bool::encode(&[]));
+        let bits = (0..100).map(|i| i % 3 == 0).collect::<Vec<_>>();
+        for b in &bits {
+            columns.push(&Value::Bool(*b));
+        }
+        let flat = Bool::vector_cast(columns.as_ref());
+        assert!(flat.len() == 100);
+        assert!(flat.iter().eq(bits.iter().copied()));
+        assert!(columns.buffer[0].len() == 8 + 13);
+        let view = Bool::vector_cast(columns.as_ref().slice(13..90));
+        assert!(view.iter().eq(bits[13..90].iter().copied()));
+        assert!(view.slice(5..).iter().eq(bits[18..90].iter().copied()));
+        let packed = Bool::column(bits.iter().copied());
+        assert!(packed.len() == 100 && packed.buffer[0].slice(..) == columns.buffer[0].slice(..));
+    }
+}
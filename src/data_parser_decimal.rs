@@ -0,0 +1,132 @@
+use crate::data_schema::*;
+use crate::data_buffer::*;
+use crate::data_value::Value;
+use crate::util_bytes::MemoryError;
+
+/*                                        */
+/* Implementation of fixed-point decimal  */
+/*                                        */
+
+// a decimal(p, s) is stored as i128 scaled by 10^s, the schema holds p and s before the tag
+pub const DECIMAL_MAX_PRECISION: u8 = 38;
+
+impl DSchemaParser<{Tag::Decimal as u8}> for Decimal {
+    #[inline(always)]
+    fn decode<'a>(schema: DSchemaRef<'a>) -> DSchemaEnum<'a> {
+        let &[precision, scale] = schema.bytes() else { unreachable!() };
+        DSchemaEnum::Decimal(precision, scale)
+    }
+    fn encode<'a>(children: &[DSchemaRef<'a>]) -> DSchema {
+        panic!("decimal needs precision and scale, use Decimal::with(precision, scale) instead");
+    }
+    fn scalar_layout<'a>(_: DSchemaRef<'a>) -> ScalarLayout {
+        let m = std::alloc::Layout::new::<i128>();
+        ScalarLayout {
+            size: m.size(),
+            align: m.align()
+        }
+    }
+    fn dbg(schema: DSchemaRef<'_>, fmt: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        let DSchemaEnum::Decimal(precision, scale) = Self::decode(schema) else { unreachable!() };
+        write!(fmt, "{:?}({precision}, {scale})", Tag::Decimal)
+    }
+    fn num_columns<'a>(_: DSchemaRef<'a>) -> usize {
+        1
+    }
+    fn validate(schema: DSchemaRef<'_>, at: usize) -> Result<(), DSchemaError> {
+        if schema.len() < 2 { Err(DSchemaError::Truncated(at))? }
+        if schema.len() > 2 { Err(DSchemaError::TrailingBytes(schema.len() - 2))? }
+        let &[precision, scale] = schema.bytes() else { unreachable!() };
+        if precision == 0 || precision > DECIMAL_MAX_PRECISION || scale > precision {
+            Err(DSchemaError::BadParameter { tag: Tag::Decimal, at })?
+        }
+        Ok(())
+    }
+}
+
+impl Decimal {
+    /// Schema of decimal(precision, scale), or `None` if `0 < precision <= 38` or `scale <= precision` doesn't hold
+    pub fn with(precision: u8, scale: u8) -> Option<DSchema> {
+        if precision == 0 || precision > DECIMAL_MAX_PRECISION || scale > precision { return None }
+        let mut schema = DSchema::empty();
+        schema.put_bytes(&[precision, scale, Tag::Decimal as u8]);
+        Some(schema)
+    }
+    /// Parse a decimal literal like `-12.34` into its scaled integer
+    pub fn parse(s: &str, precision: u8, scale: u8) -> Option<i128> {
+        if scale > precision { return None }
+        let (sign, s) = match s.strip_prefix('-') { Some(s) => (-1, s), None => (1, s) };
+        let (int, frac) = s.split_once('.').unwrap_or((s, ""));
+        let digit = |s: &str| s.bytes().all(|x| x.is_ascii_digit());
+        if int.is_empty() || !digit(int) || !digit(frac) || frac.len() > scale as usize { return None }
+        if int.trim_start_matches('0').len() > (precision - scale) as usize { return None }
+        let int = int.parse::<i128>().ok()?;
+        let frac = if frac.is_empty() { 0 } else { frac.parse::<i128>().ok()? * 10i128.pow((scale as usize - frac.len()) as u32) };
+        Some(sign * (int * 10i128.pow(scale as u32) + frac))
+    }
+    /// Format a scaled integer as decimal literal
+    pub fn format(v: i128, scale: u8) -> String {
+        let p = 10i128.pow(scale as u32);
+        let sign = if v < 0 { "-" } else { "" };
+        let (int, frac) = (v.unsigned_abs() / p as u128, v.unsigned_abs() % p as u128);
+        if scale == 0 { format!("{sign}{int}") } else { format!("{sign}{int}.{frac:0w$}", w = scale as usize) }
+    }
+}
+
+impl BufferParser<{Tag::Decimal as u8}> for Decimal {
+    type ScalarRef<'a> = &'a i128;
+    type VectorRef<'a> = &'a [i128];
+    fn vector_cast<'a>(buffer: VBufRef<'a>) -> Self::VectorRef<'a> {
+        // a column that was never written has no aligned allocation to cast
+        let column = buffer.buffer[buffer.buffer.len()-1].slice(..);
+        let column: &[i128] = if column.is_empty() { &[] } else { bytemuck::cast_slice(column) };
+        &column[buffer.offset..buffer.offset+buffer.len]
+    }
+    fn vector_push<'a>(buffer: VBufMut<'a>, elem: Self::ScalarRef<'a>) -> Result<(), MemoryError> {
+        buffer.buffer[buffer.buffer.len()-1].try_extend(&elem.to_ne_bytes())
+    }
+    fn vector_tail(buffer: VBufMut<'_>, elem: SBufRef<'_>) -> Result<(), MemoryError> {
+        buffer.buffer[buffer.buffer.len()-1].try_extend(&elem.buffer[..16])
+    }
+    fn vector_load(buffer: VBufRef<'_>, i: usize, elem: SBufMut<'_>) {
+        elem.buffer[..16].copy_from_slice(&Self::vector_cast(buffer)[i].to_ne_bytes());
+    }
+    fn vector_get(buffer: VBufRef<'_>, i: usize) -> Value<'_> {
+        Value::Decimal(Self::vector_cast(buffer)[i])
+    }
+    fn vector_put(buffer: VBufMut<'_>, elem: &Value<'_>) -> Result<(), MemoryError> {
+        let Value::Decimal(x) = elem else { panic!("cannot push {elem:?} to {:?}", buffer.schema) };
+        Self::vector_push(buffer, x)
+    }
+}
+
+impl SBufParser<{Tag::Decimal as u8}> for Decimal {
+    type ScalarRef<'a> = &'a i128;
+    fn scalar_cast<'a>(buf: SBufRef<'a>) -> Self::ScalarRef<'a> {
+        bytemuck::from_bytes(&buf.buffer[..16])
+    }
+    fn scalar_write(buf: SBufMut<'_>, elem: Self::ScalarRef<'_>) {
+        buf.buffer[..16].copy_from_slice(&elem.to_ne_bytes());
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn parse_format() {
+        assert!(Decimal::parse("-12.34", 10, 3) == Some(-12340));
+        assert!(Decimal::parse("12.3456", 10, 3).is_none());
+        assert!(Decimal::parse("123456789", 10, 3).is_none());
+        assert!(Decimal::format(-12340, 3) == "-12.340");
+        assert!(Decimal::format(5, 2) == "0.05");
+        let schema = Decimal::with(10, 3).unwrap();
+        assert!(format!("{:?}", schema.as_ref()) == "Decimal(10, 3)");
+        assert!(schema.as_ref().validate().is_ok());
+        assert!(Decimal::with(39, 3).is_none());
+        assert!(Decimal::parse("1.5", 2, 3).is_none());
+        let corrupted = DSchemaRef::new(&[2, 3, Tag::Decimal as u8]);
+        assert!(corrupted.err() == Some(DSchemaError::BadParameter { tag: Tag::Decimal, at: 0 }));
+    }
+}
@@ -0,0 +1,304 @@
+use std::collections::HashMap;
+use crate::data_schema::*;
+use crate::data_buffer::*;
+use crate::data_value::Value;
+use crate::data_parser_string::{FlatStr, FlatStrBuilder};
+use crate::util_bytes::{Bytes, MemoryError};
+
+//                                           //
+// Implementation of dictionary-encoded str  //
+//                                           //
+
+// the code width in bytes (1, 2 or 4) is stored before the tag
+impl DSchemaParser<{Tag::DictStr as u8}> for DictStr {
+    fn decode<'a>(schema: DSchemaRef<'a>) -> DSchemaEnum<'a> {
+        DSchemaEnum::DictStr(schema.bytes()[0])
+    }
+    fn encode<'a>(children: &[DSchemaRef<'a>]) -> DSchema {
+        panic!("dictionary needs a code width, use DictStr::with(width) instead");
+    }
+    fn scalar_layout<'a>(_: DSchemaRef<'a>) -> ScalarLayout {
+        let m = std::alloc::Layout::new::<&str>();
+        ScalarLayout {
+            size: m.size(),
+            align: m.align()
+        }
+    }
+    fn dbg(schema: DSchemaRef<'_>, fmt: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(fmt, "{:?}(u{})", Tag::DictStr, schema.bytes()[0] as usize * 8)
+    }
+    fn num_columns<'a>(_: DSchemaRef<'a>) -> usize {
+        3
+    }
+    fn validate(schema: DSchemaRef<'_>, at: usize) -> Result<(), DSchemaError> {
+        if schema.len() < 1 { Err(DSchemaError::Truncated(at))? }
+        if schema.len() > 1 { Err(DSchemaError::TrailingBytes(schema.len() - 1))? }
+        if ![1, 2, 4].contains(&schema.bytes()[0]) { Err(DSchemaError::BadParameter { tag: Tag::DictStr, at })? }
+        Ok(())
+    }
+}
+
+impl DictStr {
+    /// Schema of a dictionary with codes of `width` bytes, or `None` if the width is not 1, 2 or 4
+    pub fn with(width: u8) -> Option<DSchema> {
+        if ![1, 2, 4].contains(&width) { return None }
+        let mut schema = DSchema::empty();
+        schema.put_bytes(&[width, Tag::DictStr as u8]);
+        Some(schema)
+    }
+    /// Encode strings with the narrowest codes that fit their distinct values
+    pub fn column<'a>(strings: impl IntoIterator<Item = &'a str>) -> VBuf {
+        strings.into_iter().collect::<DictStrBuilder>().finish()
+    }
+    /// Narrowest code width for a dictionary of `entries` strings
+    pub fn width_for(entries: usize) -> u8 {
+        match entries {
+            0..=0x100 => 1,
+            0x101..=0x10000 => 2,
+            _ => 4,
+        }
+    }
+    /// Number of strings a dictionary with codes of `width` bytes holds
+    pub fn capacity(width: u8) -> usize {
+        1 << (width as usize * 8).min(32)
+    }
+    /// Add a value to the dictionary without checking if it is there, and return its code
+    pub fn insert(buffer: VBufMut<'_>, elem: &str) -> Result<u32, MemoryError> {
+        let width = buffer.schema.cut(1).bytes()[0];
+        let code = Self::vector_cast(VBufRef { buffer: buffer.buffer, schema: buffer.schema, offset: 0, len: 0 }).dictionary().len();
+        assert!(code < Self::capacity(width), "dictionary of {:?} is full", buffer.schema);
+        Str::vector_push(VBufMut { buffer: &mut buffer.buffer[..2], schema: buffer.schema }, elem)?;
+        Ok(code as u32)
+    }
+    /// Append a row by its code
+    pub fn push_code(buffer: VBufMut<'_>, code: u32) -> Result<(), MemoryError> {
+        push_code(&mut buffer.buffer[2], buffer.schema.cut(1).bytes()[0], code)
+    }
+}
+
+fn push_code(column: &mut Bytes, width: u8, code: u32) -> Result<(), MemoryError> {
+    match width {
+        1 => column.try_extend(&(code as u8).to_ne_bytes()),
+        2 => column.try_extend(&(code as u16).to_ne_bytes()),
+        _ => column.try_extend(&code.to_ne_bytes()),
+    }
+}
+
+// columns 0 and 1 hold the dictionary with the layout of a str column, column 2 holds the codes,
+// a view narrows the codes and always keeps the whole dictionary
+impl BufferParser<{Tag::DictStr as u8}> for DictStr {
+    type ScalarRef<'a> = &'a str;
+    type VectorRef<'a> = FlatDict<'a>;
+    fn vector_cast<'a>(buffer: VBufRef<'a>) -> Self::VectorRef<'a> {
+        assert!(buffer.buffer.len() == 3);
+        let dict = VBufRef { buffer: &buffer.buffer[..2], len: 0, offset: 0, ..buffer };
+        let entries = (buffer.buffer[1].len() / 8).saturating_sub(1);
+        let dict = Str::vector_cast(VBufRef { len: entries, ..dict });
+        let range = buffer.offset..buffer.offset+buffer.len;
+        let codes = buffer.buffer[2].slice(..);
+        let codes = match buffer.schema.cut(1).bytes()[0] {
+            // an untouched column is not aligned for wider codes
+            _ if codes.is_empty() => FlatCodes::U8(&codes[range]),
+            1 => FlatCodes::U8(&codes[range]),
+            2 => FlatCodes::U16(&bytemuck::cast_slice(codes)[range]),
+            _ => FlatCodes::U32(&bytemuck::cast_slice(codes)[range]),
+        };
+        FlatDict { dict, codes }
+    }
+    // finding the code scans the dictionary, bulk loads should go through DictStrBuilder
+    fn vector_push<'a>(mut buffer: VBufMut<'a>, elem: &str) -> Result<(), MemoryError> {
+        let dict = Self::vector_cast(VBufRef { buffer: buffer.buffer, schema: buffer.schema, offset: 0, len: 0 });
+        let code = match dict.lookup(elem) {
+            Some(code) => code,
+            None => Self::insert(buffer.reborrow(), elem)?,
+        };
+        Self::push_code(buffer, code)
+    }
+    fn vector_tail(buffer: VBufMut<'_>, elem: SBufRef<'_>) -> Result<(), MemoryError> {
+        Self::vector_push(buffer, Self::scalar_cast(elem))
+    }
+    fn vector_load(buffer: VBufRef<'_>, i: usize, elem: SBufMut<'_>) {
+        Self::scalar_write(elem, Self::vector_cast(buffer).get(i))
+    }
+    fn vector_get(buffer: VBufRef<'_>, i: usize) -> Value<'_> {
+        Value::Str(Self::vector_cast(buffer).get(i))
+    }
+    fn vector_put(buffer: VBufMut<'_>, elem: &Value<'_>) -> Result<(), MemoryError> {
+        let Value::Str(x) = elem else { panic!("cannot push {elem:?} to {:?}", buffer.schema) };
+        Self::vector_push(buffer, x)
+    }
+}
+
+impl SBufParser<{Tag::DictStr as u8}> for DictStr {
+    type ScalarRef<'a> = &'a str;
+    fn scalar_cast<'a>(buf: SBufRef<'a>) -> Self::ScalarRef<'a> {
+        // a row holds the decoded string, just like str
+        unsafe { std::str::from_utf8_unchecked(buf.get_heap()) }
+    }
+    fn scalar_write(mut buf: SBufMut<'_>, elem: Self::ScalarRef<'_>) {
+        buf.put_heap(elem.as_bytes());
+    }
+}
+
+/// Codes of a dictionary-encoded column, in their stored width
+#[derive(Clone, Copy, Debug)]
+pub enum FlatCodes<'a> {
+    U8(&'a [u8]),
+    U16(&'a [u16]),
+    U32(&'a [u32]),
+}
+
+/// Run an expression on the codes slice of any width
+#[macro_export]
+macro_rules! WithCodes {($codes: expr, |$c: ident| $body: expr) => {
+    match $codes {
+        $crate::data_parser_dict::FlatCodes::U8($c) => $body,
+        $crate::data_parser_dict::FlatCodes::U16($c) => $body,
+        // bodies widen codes with `as u32`, which is a no-op here
+        #[allow(clippy::unnecessary_cast)]
+        $crate::data_parser_dict::FlatCodes::U32($c) => $body,
+    }
+};}
+
+impl<'a> FlatCodes<'a> {
+    pub fn len(&self) -> usize {
+        crate::WithCodes!(*self, |c| c.len())
+    }
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+    pub fn get(&self, i: usize) -> u32 {
+        crate::WithCodes!(*self, |c| c[i] as u32)
+    }
+}
+
+/// A column of dictionary-encoded strings
+///
+/// Kernels can work on [`FlatDict::codes`] and touch each distinct string once through [`FlatDict::dictionary`].
+#[derive(Clone, Copy)]
+pub struct FlatDict<'a> {
+    dict: FlatStr<'a>,
+    codes: FlatCodes<'a>,
+}
+
+impl<'a> FlatDict<'a> {
+    pub fn len(&self) -> usize {
+        self.codes.len()
+    }
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+    /// The i-th string, panics if out of bound
+    pub fn get(&self, i: usize) -> &'a str {
+        self.dict.get(self.codes.get(i) as usize)
+    }
+    pub fn iter(&self) -> impl Iterator<Item = &'a str> + '_ {
+        (0..self.len()).map(|i| self.get(i))
+    }
+    /// The distinct strings, including the ones not referenced by this view
+    pub fn dictionary(&self) -> FlatStr<'a> {
+        self.dict
+    }
+    pub fn codes(&self) -> FlatCodes<'a> {
+        self.codes
+    }
+    /// Code of a string, if it is in the dictionary
+    pub fn lookup(&self, elem: &str) -> Option<u32> {
+        self.dict.iter().position(|x| x == elem).map(|i| i as u32)
+    }
+    /// Decode into a plain str column
+    pub fn decode(&self) -> VBuf {
+        self.iter().collect::<FlatStrBuilder>().finish()
+    }
+}
+
+/// Builds a dictionary-encoded column, a hash index finds the code of each string
+pub struct DictStrBuilder {
+    dict: FlatStrBuilder,
+    index: HashMap<String, u32>,
+    codes: Vec<u32>,
+}
+
+impl DictStrBuilder {
+    pub fn new() -> Self {
+        DictStrBuilder { dict: FlatStrBuilder::new(), index: HashMap::new(), codes: vec![] }
+    }
+    pub fn push(&mut self, elem: &str) {
+        let code = match self.index.get(elem) {
+            Some(code) => *code,
+            None => {
+                let code = self.index.len() as u32;
+                self.dict.push(elem);
+                self.index.insert(elem.to_string(), code);
+                code
+            }
+        };
+        self.codes.push(code);
+    }
+    /// Turn the strings into a vector buffer, with the narrowest codes that fit the dictionary
+    pub fn finish(self) -> VBuf {
+        let width = DictStr::width_for(self.index.len());
+        let mut column = self.dict.finish();
+        let mut code_column = Bytes::with_capacity(self.codes.len() * width as usize);
+        for code in &self.codes { push_code(&mut code_column, width, *code).expect("codes fit in the reserved capacity") }
+        column.buffer.push(code_column);
+        column.schema = DictStr::with(width).unwrap();
+        column.len = self.codes.len();
+        column
+    }
+}
+
+impl<'a> FromIterator<&'a str> for DictStrBuilder {
+    fn from_iter<T: IntoIterator<Item = &'a str>>(iter: T) -> Self {
+        let mut builder = DictStrBuilder::new();
+        for x in iter { builder.push(x) }
+        builder
+    }
+}
+
+impl<'a> std::fmt::Debug for FlatDict<'a> {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_list().entries(self.iter()).finish()
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn push_cast() {
+        let items = ["de", "fr", "de", "", "us", "fr", "de"];
+        let mut columns = VBuf::new(DictStr::with(1).unwrap());
+        assert!(format!("{:?}", columns.schema.as_ref()) == "DictStr(u8)");
+        assert!(DictStr::vector_cast(columns.as_ref()).is_empty());
+        for x in items { columns.push(&Value::Str(x)) }
+        let flat = DictStr::vector_cast(columns.as_ref());
+        assert!(flat.iter().eq(items));
+        assert!(flat.dictionary().iter().eq(["de", "fr", "", "us"]));
+        assert!((0..items.len()).map(|i| flat.codes().get(i)).eq([0, 1, 0, 2, 3, 1, 0]));
+        let view = DictStr::vector_cast(columns.as_ref().slice(4..));
+        assert!(view.iter().eq(items[4..].iter().copied()) && view.dictionary().len() == 4);
+        assert!(columns.as_ref().slice(1..).get(3) == Value::Str("us"));
+        assert!(Str::vector_cast(flat.decode().as_ref()).iter().eq(items));
+        // rows go through the decoded string
+        let row = columns.row(4);
+        columns.push_row(row.as_ref());
+        assert!(DictStr::vector_cast(columns.as_ref()).codes().get(7) == 3);
+        // bulk encoding picks the narrowest code
+        let strings = (0..300).map(|i| (i % 257).to_string()).collect::<Vec<_>>();
+        let column = DictStr::column(strings.iter().map(|s| s.as_str()));
+        assert!(column.schema.as_ref() == DictStr::with(2).unwrap().as_ref());
+        assert!(DictStr::vector_cast(column.as_ref()).iter().eq(strings.iter().map(|s| s.as_str())));
+        assert!(DictStr::column(["a", "b", "a"]).schema.as_ref() == DictStr::with(1).unwrap().as_ref());
+        let corrupted = DSchemaRef::new(&[3, Tag::DictStr as u8]);
+        assert!(corrupted.err() == Some(DSchemaError::BadParameter { tag: Tag::DictStr, at: 0 }));
+    }
+
+    #[test]
+    #[should_panic]
+    fn full_dictionary() {
+        let mut columns = VBuf::new(DictStr::with(1).unwrap());
+        for i in 0..257 { columns.push(&Value::Str(&i.to_string())) }
+    }
+}
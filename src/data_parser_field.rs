@@ -0,0 +1,99 @@
+use crate::data_schema::*;
+use crate::data_buffer::*;
+use crate::data_value::Value;
+use crate::util_bytes::MemoryError;
+
+/*                              */
+/* Implementation of field type */
+/*                              */
+
+// a field wraps its child schema with a name, a record is a (right-nested) pair of fields
+impl DSchemaParser<{Tag::Field as u8}> for Field {
+    #[inline(always)]
+    fn decode<'a>(schema: DSchemaRef<'a>) -> DSchemaEnum<'a> {
+        // (c, n): the length of child schema and the length of name
+        let n = schema.u16() as usize;
+        let schema = schema.cut(2);
+        let c = schema.u16() as usize;
+        let name = std::str::from_utf8(schema.slice(c..c+n).bytes()).unwrap_or_default();
+        DSchemaEnum::Field(name, schema.slice(0..c))
+    }
+    fn encode<'a>(children: &[DSchemaRef]) -> DSchema {
+        panic!("field needs a name, use Field::named(name, child) instead");
+    }
+    fn scalar_layout<'a>(schema: DSchemaRef) -> ScalarLayout {
+        let DSchemaEnum::Field(_, child) = Self::decode(schema) else { unreachable!() };
+        child.scalar_layout()
+    }
+    fn dbg(schema: DSchemaRef<'_>, fmt: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        let DSchemaEnum::Field(name, child) = Self::decode(schema) else { unreachable!() };
+        write!(fmt, "{name}: {child:?}")
+    }
+    fn num_columns<'a>(schema: DSchemaRef) -> usize {
+        let DSchemaEnum::Field(_, child) = Self::decode(schema) else { unreachable!() };
+        child.num_columns()
+    }
+    fn validate(schema: DSchemaRef<'_>, at: usize) -> Result<(), DSchemaError> {
+        // child, name, padding to 2, length of child (u16), length of name (u16)
+        if schema.len() < 4 { Err(DSchemaError::Truncated(at))? }
+        let n = schema.u16() as usize;
+        let c = schema.cut(2).u16() as usize;
+        let body = schema.len() - 4;
+        if (c + n + 1) & !1 != body { Err(DSchemaError::OutOfRange { offset: c + n, len: body, at })? }
+        if std::str::from_utf8(schema.slice(c..c+n).bytes()).is_err() { Err(DSchemaError::BadName { at: at + c })? }
+        schema.slice(0..c).validate_at(at)
+    }
+}
+
+impl SBufParser<{Tag::Field as u8}> for Field {
+    type ScalarRef<'a> = SBufRef<'a>;
+    fn scalar_cast<'a>(buf: SBufRef<'a>) -> Self::ScalarRef<'a> {
+        buf.field()
+    }
+    fn scalar_write(mut buf: SBufMut<'_>, elem: Self::ScalarRef<'_>) {
+        buf.field().assign(elem);
+    }
+}
+
+impl BufferParser<{Tag::Field as u8}> for Field {
+    type ScalarRef<'a> = SBufRef<'a>;
+    type VectorRef<'a> = VBufRef<'a>;
+    fn vector_cast<'a>(buffer: VBufRef<'a>) -> Self::VectorRef<'a> {
+        buffer.field()
+    }
+    fn vector_push<'a>(buffer: VBufMut<'a>, elem: Self::ScalarRef<'a>) -> Result<(), MemoryError> {
+        buffer.field().try_push_row(elem)
+    }
+    fn vector_tail(buffer: VBufMut<'_>, elem: SBufRef<'_>) -> Result<(), MemoryError> {
+        buffer.field().try_push_row(elem.field())
+    }
+    fn vector_load(buffer: VBufRef<'_>, i: usize, mut elem: SBufMut<'_>) {
+        buffer.field().load_row(i, elem.field())
+    }
+    fn vector_get(buffer: VBufRef<'_>, i: usize) -> Value<'_> {
+        let DSchemaEnum::Field(name, _) = buffer.schema.decode() else { unreachable!() };
+        Value::field(name, buffer.field().get(i))
+    }
+    fn vector_put(buffer: VBufMut<'_>, elem: &Value<'_>) -> Result<(), MemoryError> {
+        let Value::Field(_, x) = elem else { panic!("cannot push {elem:?} to {:?}", buffer.schema) };
+        buffer.field().try_push(x)
+    }
+}
+
+impl Field {
+    pub fn named(name: &str, child: DSchemaRef) -> DSchema {
+        Field::with(name, child).expect("field schema or name is too large for u16 offsets")
+    }
+    /// Schema of `name: child`, or `None` if the child or the name doesn't fit in u16 offsets
+    pub fn with(name: &str, child: DSchemaRef) -> Option<DSchema> {
+        let c = u16::try_from(child.len()).ok()?;
+        let n = u16::try_from(name.len()).ok()?;
+        let mut schema = DSchema::empty();
+        schema.join(child);
+        schema.put_bytes(name.as_bytes());
+        schema.put(c);
+        schema.put(n);
+        schema.put(Tag::Field as u8);
+        Some(schema)
+    }
+}
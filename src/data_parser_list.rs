@@ -0,0 +1,211 @@
+use crate::data_schema::*;
+use crate::data_buffer::*;
+use crate::data_value::Value;
+use crate::util_bytes::{Bytes, MemoryError};
+
+//                             //
+// Implementation of list type //
+//                             //
+
+// the element schema is stored before the tag
+impl DSchemaParser<{Tag::List as u8}> for List {
+    fn decode<'a>(schema: DSchemaRef<'a>) -> DSchemaEnum<'a> {
+        DSchemaEnum::List(schema)
+    }
+    fn encode<'a>(children: &[DSchemaRef<'a>]) -> DSchema {
+        assert!(children.len() == 1);
+        let mut schema = DSchema::from_ref(children[0]);
+        schema.put(Tag::List as u8);
+        schema
+    }
+    fn scalar_layout<'a>(_: DSchemaRef<'a>) -> ScalarLayout {
+        let m = std::alloc::Layout::new::<&[u8]>();
+        ScalarLayout {
+            size: m.size(),
+            align: m.align()
+        }
+    }
+    fn dbg(schema: DSchemaRef<'_>, fmt: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(fmt, "{:?}({schema:?})", Tag::List)
+    }
+    fn num_columns<'a>(schema: DSchemaRef<'a>) -> usize {
+        schema.num_columns() + 1
+    }
+    fn validate(schema: DSchemaRef<'_>, at: usize) -> Result<(), DSchemaError> {
+        if schema.len() == 0 { Err(DSchemaError::Truncated(at))? }
+        schema.validate_at(at)
+    }
+}
+
+impl List {
+    /// Columns of the elements, and the offsets column with its leading zero
+    pub fn columns_mut(buffer: VBufMut<'_>) -> Result<(VBufMut<'_>, &mut Bytes), MemoryError> {
+        let DSchemaEnum::List(child) = buffer.schema.decode() else { panic!("{:?} is not a list", buffer.schema) };
+        let (values, ends) = buffer.buffer.split_at_mut(buffer.buffer.len() - 1);
+        if ends[0].len() == 0 { ends[0].try_extend(&0u64.to_ne_bytes())? }
+        Ok((VBufMut { buffer: values, schema: child }, &mut ends[0]))
+    }
+    /// Close lists of `lens` elements each, after their elements are pushed
+    pub fn push_lens(ends: &mut Bytes, lens: impl IntoIterator<Item = usize>) -> Result<(), MemoryError> {
+        let mut last = bytemuck::pod_read_unaligned::<u64>(ends.slice(ends.len() - 8..));
+        for len in lens {
+            last += len as u64;
+            ends.try_extend(&last.to_ne_bytes())?;
+        }
+        Ok(())
+    }
+}
+
+// the element columns come first, the last column holds offsets (u64) that start with a leading zero,
+// so the i-th list is rows offset[i]..offset[i+1] of the element columns
+impl BufferParser<{Tag::List as u8}> for List {
+    type ScalarRef<'a> = VBufRef<'a>;
+    type VectorRef<'a> = FlatList<'a>;
+    fn vector_cast<'a>(buffer: VBufRef<'a>) -> Self::VectorRef<'a> {
+        let DSchemaEnum::List(child) = buffer.schema.decode() else { panic!("{:?} is not a list", buffer.schema) };
+        let (values, ends) = buffer.buffer.split_at(buffer.buffer.len() - 1);
+        // an untouched column has no leading zero yet, offsets are absolute so a view only narrows them
+        let offset: &[u64] = if ends[0].len() == 0 { &[0] } else { bytemuck::cast_slice(ends[0].slice(..)) };
+        let column = VBufRef { buffer: values, schema: child, offset: 0, len: offset[offset.len() - 1] as usize };
+        FlatList { offset: &offset[buffer.offset..=buffer.offset+buffer.len], column }
+    }
+    fn vector_push<'a>(buffer: VBufMut<'a>, elem: VBufRef<'a>) -> Result<(), MemoryError> {
+        let (values, ends) = List::columns_mut(buffer)?;
+        crate::kernel_select::extend_into(elem, values)?;
+        List::push_lens(ends, [elem.len])
+    }
+    fn vector_tail(buffer: VBufMut<'_>, elem: SBufRef<'_>) -> Result<(), MemoryError> {
+        let (mut values, ends) = List::columns_mut(buffer)?;
+        let elems = elem.list();
+        let len = elems.len();
+        for x in elems { values.reborrow().try_push_row(x)? }
+        List::push_lens(ends, [len])
+    }
+    fn vector_load(buffer: VBufRef<'_>, i: usize, mut elem: SBufMut<'_>) {
+        let list = Self::vector_cast(buffer).get(i);
+        // each element is loaded into a row of its own, then copied into the heap of the list
+        let rows = (0..list.len).map(|j| {
+            let mut row = SBuf::new(DSchema::from_ref(list.schema));
+            list.load_row(j, row.as_mut());
+            row
+        }).collect::<Vec<_>>();
+        elem.put_list(rows.iter().map(|row| row.as_ref()));
+    }
+    fn vector_get(buffer: VBufRef<'_>, i: usize) -> Value<'_> {
+        let list = Self::vector_cast(buffer).get(i);
+        Value::List((0..list.len).map(|j| list.get(j)).collect())
+    }
+    fn vector_put(buffer: VBufMut<'_>, elem: &Value<'_>) -> Result<(), MemoryError> {
+        let Value::List(xs) = elem else { panic!("cannot push {elem:?} to {:?}", buffer.schema) };
+        let (mut values, ends) = List::columns_mut(buffer)?;
+        for x in xs { values.reborrow().try_push(x)? }
+        List::push_lens(ends, [xs.len()])
+    }
+}
+
+// the scalar of a list points to its elements, which are rows of the element schema in the heap
+impl SBufParser<{Tag::List as u8}> for List {
+    type ScalarRef<'a> = Vec<SBufRef<'a>>;
+    fn scalar_cast<'a>(buf: SBufRef<'a>) -> Self::ScalarRef<'a> {
+        buf.list().collect()
+    }
+    fn scalar_write(mut buf: SBufMut<'_>, elem: Self::ScalarRef<'_>) {
+        buf.put_list(elem.into_iter());
+    }
+}
+
+/// A column of lists
+///
+/// Offsets are absolute rows of the element columns, so a slice only narrows `offset` and never copies.
+#[derive(Clone, Copy)]
+pub struct FlatList<'a> {
+    offset: &'a [u64],
+    column: VBufRef<'a>,
+}
+
+impl<'a> FlatList<'a> {
+    pub fn len(&self) -> usize {
+        self.offset.len() - 1
+    }
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+    /// Rows of the element columns that the i-th list covers
+    pub fn range(&self, i: usize) -> std::ops::Range<usize> {
+        self.offset[i] as usize..self.offset[i+1] as usize
+    }
+    /// Elements of the i-th list, panics if out of bound
+    pub fn get(&self, i: usize) -> VBufRef<'a> {
+        self.column.slice(self.range(i))
+    }
+    pub fn iter(&self) -> impl Iterator<Item = VBufRef<'a>> + '_ {
+        (0..self.len()).map(|i| self.get(i))
+    }
+    /// Elements of all lists in the view, one after another
+    pub fn values(&self) -> VBufRef<'a> {
+        self.column.slice(self.offset[0] as usize..self.offset[self.len()] as usize)
+    }
+    /// The whole element columns, which the offsets point into
+    pub fn column(&self) -> VBufRef<'a> {
+        self.column
+    }
+    /// Offsets of the lists in the view, the last one ends the last list
+    pub fn offsets(&self) -> &'a [u64] {
+        self.offset
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn push_cast() {
+        let mut columns = VBuf::new(List::encode(&[Str::encode(&[]).as_ref()]));
+        assert!(format!("{:?}", columns.schema.as_ref()) == "List(Str)");
+        assert!(List::vector_cast(columns.as_ref()).is_empty());
+        let lists = [vec!["a", "bc"], vec![], vec!["", "d", "ef"]];
+        for x in &lists { columns.push(&Value::List(x.iter().map(|s| Value::Str(s)).collect())) }
+        let flat = List::vector_cast(columns.as_ref());
+        assert!(flat.len() == 3 && flat.offsets() == [0, 2, 2, 5]);
+        for (i, x) in lists.iter().enumerate() {
+            assert!(Str::vector_cast(flat.get(i)).iter().eq(x.iter().copied()));
+        }
+        // a view narrows the offsets, the elements stay where they are
+        let view = List::vector_cast(columns.as_ref().slice(1..));
+        assert!(view.range(1) == (2..5) && Str::vector_cast(view.values()).iter().eq(["", "d", "ef"]));
+        assert!(columns.as_ref().slice(2..).get(0) == Value::List(vec![Value::Str(""), Value::Str("d"), Value::Str("ef")]));
+        // push a view of elements as a list
+        let mut elements = VBuf::new(Str::encode(&[]));
+        for x in ["c", "d", "ef"] { elements.push(&Value::Str(x)) }
+        List::vector_push(columns.as_mut(), elements.as_ref().slice(1..)).unwrap();
+        List::vector_push(columns.as_mut(), elements.as_ref().slice(..0)).unwrap();
+        columns.len += 2;
+        assert!(columns.get(3) == Value::List(vec![Value::Str("d"), Value::Str("ef")]));
+        assert!(columns.get(4) == Value::List(vec![]));
+    }
+
+    #[test]
+    fn nested_rows() {
+        // list of (a: i32, b: list of i64), transposed through rows
+        let inner = List::encode(&[I64::encode(&[]).as_ref()]);
+        let element = Pair::encode(&[Field::named("a", I32::encode(&[]).as_ref()).as_ref(), Field::named("b", inner.as_ref()).as_ref()]);
+        let schema = List::encode(&[element.as_ref()]);
+        assert!(schema.as_ref().validate().is_ok() && schema.as_ref().num_columns() == 4);
+        let value = |i: usize| Value::List((0..i % 4).map(|j| Value::pair(
+            Value::field("a", Value::I32((i * 10 + j) as i32)),
+            Value::field("b", Value::List((0..j).map(|k| Value::I64(k as i64 - i as i64)).collect())),
+        )).collect());
+        let mut columns = VBuf::new(schema);
+        for i in 0..20 { columns.push(&value(i)) }
+        for i in 0..20 {
+            let row = columns.row(i);
+            assert!(List::scalar_cast(row.as_ref()).len() == i % 4);
+            columns.push_row(row.as_ref());
+        }
+        for i in 0..40 { assert!(columns.get(i) == value(i % 20), "{:?} != {:?}", columns.get(i), value(i % 20)) }
+        let back = DSchema::deserialize(&columns.schema.serialize()).unwrap();
+        assert!(back.as_ref() == columns.schema.as_ref());
+        assert!(format!("{:?}", back.as_ref()) == "List(Pair(a: I32, b: List(I64)))");
+    }
+}
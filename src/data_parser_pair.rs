@@ -1,4 +1,4 @@
-use crate::{data_parser_primitive::FlatNilRef, data_schema::*, data_buffer::*};
+use crate::{data_schema::*, data_buffer::*, data_value::Value, util_bytes::MemoryError};
 
 /*                             */
 /* Implementation of pair type */
@@ -21,26 +21,10 @@ impl DSchemaParser<{Tag::Pair as u8}> for Pair {
     }
     fn encode<'a>(children: &[DSchemaRef]) -> DSchema {
         assert!(children.len() == 2);
-        let a = children[0].len();
-        let b = children[1].len();
-        let l0 = children[0].scalar_layout();
-        let l1 = children[1].scalar_layout();
-        let m = ScalarLayout{
-            size: (l0.size + (1 << l1.align) - 1) & !((1 << l1.align) - 1) + l1.size, 
-            align: l0.align.max(l1.align)
-        };
-        let m = m.size * 256 + m.align;
-        let mut schema = DSchema::empty();
-        schema.join(children[0]);
-        schema.join(children[1]);
-        schema.put(m as u64);
-        schema.put(b as u16 + a as u16);
-        schema.put(a as u16);
-        schema.put(Tag::Pair as u8);
-        schema
+        Pair::with(children[0], children[1]).expect("pair schema is too large for u16 offsets")
     }
     fn scalar_layout<'a>(schema: DSchemaRef) -> ScalarLayout {
-        let m = schema.cut(8).u64();
+        let m = schema.cut(4).u64();
         ScalarLayout{size: m as usize / 256, align: m as usize % 256}
     }
     fn dbg(schema: DSchemaRef<'_>, fmt: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
@@ -51,12 +35,87 @@ impl DSchemaParser<{Tag::Pair as u8}> for Pair {
         let DSchemaEnum::Pair(_, a, b) = Self::decode(schema) else { unreachable!() };
         a.num_columns() + b.num_columns()
     }
+    fn validate(schema: DSchemaRef<'_>, at: usize) -> Result<(), DSchemaError> {
+        // children, padding to 8, layout (u64), offset b (u16), offset a (u16)
+        if schema.len() < 12 { Err(DSchemaError::Truncated(at))? }
+        let a = schema.u16() as usize;
+        let b = schema.cut(2).u16() as usize;
+        let body = schema.len() - 12;
+        if a > b { Err(DSchemaError::OutOfRange { offset: a, len: b, at })? }
+        if (b + 7) & !7 != body { Err(DSchemaError::OutOfRange { offset: b, len: body, at })? }
+        let DSchemaEnum::Pair(m, snd, fst) = Self::decode(schema) else { unreachable!() };
+        fst.validate_at(at)?;
+        snd.validate_at(at + a)?;
+        let expect = fst.scalar_layout().then(snd.scalar_layout());
+        if m != expect { Err(DSchemaError::BadLayout { found: m, expect, at })? }
+        Ok(())
+    }
 }
 
-// TODO: columnar & scalar implementation (with SmallVec?)
+impl Pair {
+    /// Schema of pair(fst, snd), or `None` if the children don't fit in u16 offsets
+    pub fn with(fst: DSchemaRef, snd: DSchemaRef) -> Option<DSchema> {
+        let a = u16::try_from(fst.len()).ok()?;
+        let b = u16::try_from(fst.len() + snd.len()).ok()?;
+        let m = fst.scalar_layout().then(snd.scalar_layout());
+        let m = m.size * 256 + m.align;
+        let mut schema = DSchema::empty();
+        schema.join(fst);
+        schema.join(snd);
+        schema.put(m as u64);
+        schema.put(b);
+        schema.put(a);
+        schema.put(Tag::Pair as u8);
+        Some(schema)
+    }
+}
+
+// the scalar of a pair is its two elements, write them with SBufMut::fst and SBufMut::snd
+impl SBufParser<{Tag::Pair as u8}> for Pair {
+    type ScalarRef<'a> = (SBufRef<'a>, SBufRef<'a>);
+    fn scalar_cast<'a>(buf: SBufRef<'a>) -> Self::ScalarRef<'a> {
+        buf.pair()
+    }
+    fn scalar_write(mut buf: SBufMut<'_>, (fst, snd): Self::ScalarRef<'_>) {
+        buf.fst().assign(fst);
+        buf.snd().assign(snd);
+    }
+}
+
+// the columns of a pair are the columns of its first element followed by the columns of its second element
 pub struct FlatPairRef<'a>(pub VBufRef<'a>, pub VBufRef<'a>);
 
 impl BufferParser<{Tag::Pair as u8}> for Pair {
-    type ScalarRef<'a> = ();
-    type VectorRef<'a> = FlatNilRef;
+    type ScalarRef<'a> = (SBufRef<'a>, SBufRef<'a>);
+    type VectorRef<'a> = FlatPairRef<'a>;
+    fn vector_cast<'a>(buffer: VBufRef<'a>) -> Self::VectorRef<'a> {
+        let (fst, snd) = buffer.pair();
+        FlatPairRef(fst, snd)
+    }
+    fn vector_push<'a>(buffer: VBufMut<'a>, (fst, snd): Self::ScalarRef<'a>) -> Result<(), MemoryError> {
+        let (a, b) = buffer.pair();
+        a.try_push_row(fst)?;
+        b.try_push_row(snd)
+    }
+    fn vector_tail(buffer: VBufMut<'_>, elem: SBufRef<'_>) -> Result<(), MemoryError> {
+        let (a, b) = buffer.pair();
+        let (fst, snd) = elem.pair();
+        a.try_push_row(fst)?;
+        b.try_push_row(snd)
+    }
+    fn vector_load(buffer: VBufRef<'_>, i: usize, mut elem: SBufMut<'_>) {
+        let (a, b) = buffer.pair();
+        a.load_row(i, elem.fst());
+        b.load_row(i, elem.snd());
+    }
+    fn vector_get(buffer: VBufRef<'_>, i: usize) -> Value<'_> {
+        let (a, b) = buffer.pair();
+        Value::pair(a.get(i), b.get(i))
+    }
+    fn vector_put(buffer: VBufMut<'_>, elem: &Value<'_>) -> Result<(), MemoryError> {
+        let Value::Pair(fst, snd) = elem else { panic!("cannot push {elem:?} to {:?}", buffer.schema) };
+        let (a, b) = buffer.pair();
+        a.try_push(fst)?;
+        b.try_push(snd)
+    }
 }
\ No newline at end of file
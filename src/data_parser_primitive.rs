@@ -1,5 +1,8 @@
 use crate::data_schema::*;
 use crate::data_buffer::*;
+use crate::data_value::Value;
+use crate::util_bytes::MemoryError;
+use crate::util_datetime::MonthDayMicros;
 
 /*                                       */
 /* Implementation of constant-sized type */
@@ -29,15 +32,44 @@ macro_rules! ImplPrimitiveDataParser {($($X: ident: $Y: ty, )*) => {$(
         fn num_columns<'a>(_: DSchemaRef<'a>) -> usize {
             1
         }
+        fn validate(schema: DSchemaRef<'_>, _: usize) -> Result<(), DSchemaError> {
+            if schema.len() != 0 { Err(DSchemaError::TrailingBytes(schema.len()))? }
+            Ok(())
+        }
     }
     impl BufferParser<{Tag::$X as u8}> for $X {
         type ScalarRef<'a> = &'a $Y;
         type VectorRef<'a> = &'a [$Y];
         fn vector_cast<'a>(buffer: VBufRef<'a>) -> Self::VectorRef<'a> {
-            bytemuck::cast_slice(buffer.buffer[buffer.buffer.len()-1].slice(..))
+            // a column that was never written has no aligned allocation to cast
+            let column = buffer.buffer[buffer.buffer.len()-1].slice(..);
+            let column: &[$Y] = if column.is_empty() { &[] } else { bytemuck::cast_slice(column) };
+            &column[buffer.offset..buffer.offset+buffer.len]
+        }
+        fn vector_push<'a>(buffer: VBufMut<'a>, elem: Self::ScalarRef<'a>) -> Result<(), MemoryError> {
+            buffer.buffer[buffer.buffer.len()-1].try_extend(bytemuck::bytes_of(elem))
+        }
+        fn vector_tail(buffer: VBufMut<'_>, elem: SBufRef<'_>) -> Result<(), MemoryError> {
+            buffer.buffer[buffer.buffer.len()-1].try_extend(&elem.buffer[..std::mem::size_of::<$Y>()])
+        }
+        fn vector_load(buffer: VBufRef<'_>, i: usize, elem: SBufMut<'_>) {
+            elem.buffer[..std::mem::size_of::<$Y>()].copy_from_slice(bytemuck::bytes_of(&Self::vector_cast(buffer)[i]));
         }
-        fn vector_push<'a>(buffer: VBufMut<'a>, elem: Self::ScalarRef<'a>) {
-            buffer.buffer[buffer.buffer.len()-1].extend(&elem.to_ne_bytes());
+        fn vector_get(buffer: VBufRef<'_>, i: usize) -> Value<'_> {
+            Value::$X(Self::vector_cast(buffer)[i])
+        }
+        fn vector_put(buffer: VBufMut<'_>, elem: &Value<'_>) -> Result<(), MemoryError> {
+            let Value::$X(x) = elem else { panic!("cannot push {elem:?} to {:?}", buffer.schema) };
+            Self::vector_push(buffer, x)
+        }
+    }
+    impl SBufParser<{Tag::$X as u8}> for $X {
+        type ScalarRef<'a> = &'a $Y;
+        fn scalar_cast<'a>(buf: SBufRef<'a>) -> Self::ScalarRef<'a> {
+            bytemuck::from_bytes(&buf.buffer[..std::mem::size_of::<$Y>()])
+        }
+        fn scalar_write(buf: SBufMut<'_>, elem: Self::ScalarRef<'_>) {
+            buf.buffer[..std::mem::size_of::<$Y>()].copy_from_slice(bytemuck::bytes_of(elem));
         }
     }
 )*};}
@@ -46,6 +78,10 @@ ImplPrimitiveDataParser! {
     I64: i64, I32: i32, I16: i16, I8: i8,
     U64: u64, U32: u32, U16: u16, U8: u8,
     F64: f64, F32: f32,
+    // days since epoch, microseconds since midnight, and microseconds since epoch
+    Date: i32, Time: i64, 
+    Timestamp: i64, TimestampTz: i64,
+    Interval: MonthDayMicros,
 }
 
 /*                                   */
@@ -71,6 +107,10 @@ impl DSchemaParser<{Tag::Nil as u8}> for Nil {
     fn num_columns<'a>(schema: DSchemaRef) -> usize {
         1
     }
+    fn validate(schema: DSchemaRef<'_>, _: usize) -> Result<(), DSchemaError> {
+        if schema.len() != 0 { Err(DSchemaError::TrailingBytes(schema.len()))? }
+        Ok(())
+    }
 }
 
 pub struct FlatNilRef(u64);
@@ -78,13 +118,33 @@ impl BufferParser<{Tag::Nil as u8}> for Nil {
     type ScalarRef<'a> = ();
     type VectorRef<'a> = FlatNilRef;
     fn vector_cast<'a>(buffer: VBufRef<'a>) -> Self::VectorRef<'a> {
-        FlatNilRef(buffer.buffer[0].as_u64() as u64)
+        assert!(buffer.offset + buffer.len <= buffer.buffer[0].count(), "{} rows past the {} counted", buffer.offset + buffer.len, buffer.buffer[0].count());
+        FlatNilRef(buffer.len as u64)
+    }
+    fn vector_push<'a>(buffer: VBufMut<'a>, _: Self::ScalarRef<'a>) -> Result<(), MemoryError> {
+        buffer.buffer[0].add_count(1);
+        Ok(())
+    }
+    fn vector_tail(buffer: VBufMut<'_>, _: SBufRef<'_>) -> Result<(), MemoryError> {
+        buffer.buffer[0].add_count(1);
+        Ok(())
+    }
+    fn vector_load(_: VBufRef<'_>, _: usize, _: SBufMut<'_>) {}
+    fn vector_get(_: VBufRef<'_>, _: usize) -> Value<'_> {
+        Value::Nil
     }
-    fn vector_push<'a>(buffer: VBufMut<'a>, _: Self::ScalarRef<'a>) {
-        buffer.buffer[0].add(1);
+    fn vector_put(buffer: VBufMut<'_>, elem: &Value<'_>) -> Result<(), MemoryError> {
+        let Value::Nil = elem else { panic!("cannot push {elem:?} to {:?}", buffer.schema) };
+        Self::vector_push(buffer, ())
     }
 }
 
+impl SBufParser<{Tag::Nil as u8}> for Nil {
+    type ScalarRef<'a> = ();
+    fn scalar_cast<'a>(_: SBufRef<'a>) -> Self::ScalarRef<'a> {}
+    fn scalar_write(_: SBufMut<'_>, _: Self::ScalarRef<'_>) {}
+}
+
 /*                            */
 /* Implementation of pad type */
 /*                            */
@@ -92,22 +152,43 @@ impl BufferParser<{Tag::Nil as u8}> for Nil {
 impl BufferParser<{Tag::Pad as u8}> for Pad {
     type ScalarRef<'a> = ();
     type VectorRef<'a> = FlatNilRef;
+    fn vector_cast<'a>(_: VBufRef<'a>) -> Self::VectorRef<'a> {
+        FlatNilRef(0)
+    }
+    fn vector_push<'a>(_: VBufMut<'a>, _: Self::ScalarRef<'a>) -> Result<(), MemoryError> {
+        unreachable!("pad never appears in a valid schema")
+    }
+    fn vector_tail(_: VBufMut<'_>, _: SBufRef<'_>) -> Result<(), MemoryError> {
+        unreachable!("pad never appears in a valid schema")
+    }
+    fn vector_load(_: VBufRef<'_>, _: usize, _: SBufMut<'_>) {}
+    fn vector_get(_: VBufRef<'_>, _: usize) -> Value<'_> {
+        unreachable!("pad never appears in a valid schema")
+    }
+    fn vector_put(_: VBufMut<'_>, _: &Value<'_>) -> Result<(), MemoryError> {
+        unreachable!("pad never appears in a valid schema")
+    }
 }
 
+// 'pad' is a filler that never appears in a valid schema, 
+// validation rejects it so that the other functions are never called on it
 impl DSchemaParser<{Tag::Pad as u8}> for Pad {
-    fn decode<'a>(schema: DSchemaRef<'a>) -> DSchemaEnum<'a> {
-        schema.decode()
+    fn decode<'a>(_: DSchemaRef<'a>) -> DSchemaEnum<'a> {
+        unreachable!("pad never appears in a valid schema")
     }
-    fn encode<'a>(children: &[DSchemaRef<'a>]) -> DSchema {
-        panic!("you should never construct a schema with type 'pad'");
+    fn encode<'a>(_: &[DSchemaRef<'a>]) -> DSchema {
+        panic!("pad never appears in a valid schema")
     }
     fn scalar_layout<'a>(schema: DSchemaRef<'a>) -> ScalarLayout {
-        panic!("you should never construct a schema with type 'pad', so there function will never be called");
+        ScalarLayout{size: 0, align: 0}
     }
     fn dbg(_: DSchemaRef<'_>, fmt: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
-        panic!("you should never construct a schema with type 'pad', so there function will never be called");
+        write!(fmt, "{:?}", Tag::Pad)
     }
     fn num_columns<'a>(schema: DSchemaRef) -> usize {
-        panic!("you should never construct a schema with type 'pad'");
+        0
+    }
+    fn validate(schema: DSchemaRef<'_>, at: usize) -> Result<(), DSchemaError> {
+        Err(DSchemaError::UnknownTag { tag: Tag::Pad as u8, at: at + schema.len() })
     }
 }
\ No newline at end of file
@@ -1,5 +1,8 @@
 use crate::data_schema::*;
 use crate::data_buffer::*;
+use crate::data_value::Value;
+use crate::util_bytes::{Bytes, MemoryError, MemoryPool};
+use std::sync::Arc;
 
 //                            //
 // Implementation of str type //
@@ -22,31 +25,236 @@ impl DSchemaParser<{Tag::Str as u8}> for Str {
             align: m.align()
         }
     }
+    fn dbg(_: DSchemaRef<'_>, fmt: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(fmt, "{:?}", Tag::Str)
+    }
     fn num_columns<'a>(_: DSchemaRef<'a>) -> usize {
         2
     }
+    fn validate(schema: DSchemaRef<'_>, _: usize) -> Result<(), DSchemaError> {
+        if schema.len() != 0 { Err(DSchemaError::TrailingBytes(schema.len()))? }
+        Ok(())
+    }
 }
 
+// column 0 holds the bytes of all strings, column 1 holds offsets (u64) that start with a leading zero,
+// so the i-th string is buffer[offset[i]..offset[i+1]]
 impl BufferParser<{Tag::Str as u8}> for Str {
     type ScalarRef<'a> = &'a str;
     type VectorRef<'a> = FlatStr<'a>;
     fn vector_cast<'a>(buffer: VBufRef<'a>) -> Self::VectorRef<'a> {
         assert!(buffer.buffer.len() == 2);
-        let buffer = buffer.buffer;
+        let (columns, range) = (buffer.buffer, buffer.offset..buffer.offset+buffer.len);
+        // an untouched column has no leading zero yet
+        if columns[1].len() == 0 { return FlatStr { offset: &[0], buffer: "" }.slice(range) }
         FlatStr {
-            offset: bytemuck::cast_slice(buffer[1].slice(..)),
-            buffer: unsafe { std::str::from_utf8_unchecked(buffer[0].slice(..)) },
-        }
+            offset: bytemuck::cast_slice(columns[1].slice(..)),
+            buffer: unsafe { std::str::from_utf8_unchecked(columns[0].slice(..)) },
+        }.slice(range)
     }
-    fn vector_push<'a>(buffer: VBufMut<'a>, elem: &str) {
+    fn vector_push<'a>(buffer: VBufMut<'a>, elem: &str) -> Result<(), MemoryError> {
         assert!(buffer.buffer.len() == 2);
-        buffer.buffer[0].extend(elem.as_bytes());
-        buffer.buffer[1].extend(&(buffer.buffer[0].len() as u64).to_ne_bytes());
+        if buffer.buffer[1].len() == 0 { buffer.buffer[1].try_extend(&0u64.to_ne_bytes())? }
+        buffer.buffer[0].try_extend(elem.as_bytes())?;
+        buffer.buffer[1].try_extend(&(buffer.buffer[0].len() as u64).to_ne_bytes())
+    }
+    fn vector_tail(buffer: VBufMut<'_>, elem: SBufRef<'_>) -> Result<(), MemoryError> {
+        Self::vector_push(buffer, Self::scalar_cast(elem))
+    }
+    fn vector_load(buffer: VBufRef<'_>, i: usize, elem: SBufMut<'_>) {
+        Self::scalar_write(elem, Self::vector_cast(buffer).get(i))
+    }
+    fn vector_get(buffer: VBufRef<'_>, i: usize) -> Value<'_> {
+        Value::Str(Self::vector_cast(buffer).get(i))
+    }
+    fn vector_put(buffer: VBufMut<'_>, elem: &Value<'_>) -> Result<(), MemoryError> {
+        let Value::Str(x) = elem else { panic!("cannot push {elem:?} to {:?}", buffer.schema) };
+        Self::vector_push(buffer, x)
     }
 }
 
+impl SBufParser<{Tag::Str as u8}> for Str {
+    type ScalarRef<'a> = &'a str;
+    fn scalar_cast<'a>(buf: SBufRef<'a>) -> Self::ScalarRef<'a> {
+        // heap data of a str slot is only written from &str
+        unsafe { std::str::from_utf8_unchecked(buf.get_heap()) }
+    }
+    fn scalar_write(mut buf: SBufMut<'_>, elem: Self::ScalarRef<'_>) {
+        buf.put_heap(elem.as_bytes());
+    }
+}
+
+/// A column of strings
+/// 
+/// Offsets are absolute positions in `buffer`, so a slice only narrows `offset` and never copies. 
+#[derive(Clone, Copy)]
 pub struct FlatStr<'a> {
     offset: &'a [u64],
     buffer: &'a str,
 }
 
+impl<'a> FlatStr<'a> {
+    pub fn len(&self) -> usize {
+        self.offset.len() - 1
+    }
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+    /// The i-th string, panics if out of bound
+    pub fn get(&self, i: usize) -> &'a str {
+        &self.buffer[self.offset[i] as usize..self.offset[i+1] as usize]
+    }
+    pub fn iter(&self) -> FlatStrIter<'a> {
+        FlatStrIter { flat: *self }
+    }
+    /// Strings in the range, without copying
+    pub fn slice(&self, range: impl std::ops::RangeBounds<usize>) -> FlatStr<'a> {
+        let range = resolve_range(range, self.len());
+        FlatStr { offset: &self.offset[range.start..=range.end], buffer: self.buffer }
+    }
+    /// Offsets into [`FlatStr::values`], `len() + 1` of them
+    pub fn offsets(&self) -> &'a [u64] {
+        self.offset
+    }
+    /// Bytes of all strings in the underlying column, including the ones sliced away
+    pub fn values(&self) -> &'a str {
+        self.buffer
+    }
+}
+
+impl<'a> IntoIterator for FlatStr<'a> {
+    type Item = &'a str;
+    type IntoIter = FlatStrIter<'a>;
+    fn into_iter(self) -> Self::IntoIter {
+        self.iter()
+    }
+}
+
+impl<'a> std::fmt::Debug for FlatStr<'a> {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_list().entries(self.iter()).finish()
+    }
+}
+
+pub struct FlatStrIter<'a> {
+    flat: FlatStr<'a>,
+}
+
+impl<'a> Iterator for FlatStrIter<'a> {
+    type Item = &'a str;
+    fn next(&mut self) -> Option<Self::Item> {
+        if self.flat.is_empty() { return None }
+        let item = self.flat.get(0);
+        self.flat.offset = &self.flat.offset[1..];
+        Some(item)
+    }
+    fn size_hint(&self) -> (usize, Option<usize>) {
+        (self.flat.len(), Some(self.flat.len()))
+    }
+}
+
+impl<'a> DoubleEndedIterator for FlatStrIter<'a> {
+    fn next_back(&mut self) -> Option<Self::Item> {
+        if self.flat.is_empty() { return None }
+        let item = self.flat.get(self.flat.len() - 1);
+        self.flat.offset = &self.flat.offset[..self.flat.len()];
+        Some(item)
+    }
+}
+
+impl<'a> ExactSizeIterator for FlatStrIter<'a> {}
+
+/// Build a string column from scratch
+pub struct FlatStrBuilder {
+    buffer: Bytes,
+    offset: Bytes,
+}
+
+impl FlatStrBuilder {
+    pub fn new() -> Self {
+        let mut offset = Bytes::new();
+        offset.extend(&0u64.to_ne_bytes());
+        FlatStrBuilder { buffer: Bytes::new(), offset }
+    }
+    /// A builder with room for `len` strings of `bytes` bytes in total
+    pub fn with_capacity(len: usize, bytes: usize) -> Self {
+        let mut offset = Bytes::with_capacity((len + 1) * 8);
+        offset.extend(&0u64.to_ne_bytes());
+        FlatStrBuilder { buffer: Bytes::with_capacity(bytes), offset }
+    }
+    /// A builder with room for `len` strings of `bytes` bytes in total, allocated in `pool`
+    pub fn try_with_capacity_in(len: usize, bytes: usize, pool: &Arc<MemoryPool>) -> Result<Self, MemoryError> {
+        let mut offset = Bytes::try_with_capacity_in((len + 1) * 8, pool)?;
+        offset.try_extend(&0u64.to_ne_bytes())?;
+        Ok(FlatStrBuilder { buffer: Bytes::try_with_capacity_in(bytes, pool)?, offset })
+    }
+    /// Panics if the pool of the builder is out of memory, see [`FlatStrBuilder::try_push`]
+    pub fn push(&mut self, elem: &str) {
+        self.try_push(elem).unwrap_or_else(|e| panic!("{e}"))
+    }
+    /// Append a string, a failure leaves the builder as it was
+    pub fn try_push(&mut self, elem: &str) -> Result<(), MemoryError> {
+        self.offset.try_reserve(8)?;
+        self.buffer.try_extend(elem.as_bytes())?;
+        self.offset.try_extend(&(self.buffer.len() as u64).to_ne_bytes())
+    }
+    pub fn as_flat(&self) -> FlatStr<'_> {
+        FlatStr {
+            offset: bytemuck::cast_slice(self.offset.slice(..)),
+            buffer: unsafe { std::str::from_utf8_unchecked(self.buffer.slice(..)) },
+        }
+    }
+    /// Turn the strings into a vector buffer of str
+    pub fn finish(self) -> VBuf {
+        let len = self.offset.len() / 8 - 1;
+        VBuf { schema: Str::encode(&[]), buffer: vec![self.buffer, self.offset], len }
+    }
+}
+
+impl<'a> Extend<&'a str> for FlatStrBuilder {
+    fn extend<T: IntoIterator<Item = &'a str>>(&mut self, iter: T) {
+        for elem in iter { self.push(elem) }
+    }
+}
+
+impl<'a> FromIterator<&'a str> for FlatStrBuilder {
+    fn from_iter<T: IntoIterator<Item = &'a str>>(iter: T) -> Self {
+        let mut builder = FlatStrBuilder::new();
+        builder.extend(iter);
+        builder
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn flat_str() {
+        let items = ["hello", "", "world", "\u{1F600}", "muadb"];
+        let mut columns = VBuf::new(Str::encode(&[]));
+        assert!(Str::vector_cast(columns.as_ref()).is_empty());
+        for x in items {
+            columns.push(&Value::Str(x));
+        }
+        let flat = Str::vector_cast(columns.as_ref());
+        assert!(flat.len() == items.len());
+        assert!(flat.offsets()[0] == 0);
+        assert!(flat.iter().eq(items));
+        assert!(flat.iter().rev().eq(items.into_iter().rev()));
+        assert!((0..items.len()).all(|i| flat.get(i) == items[i]));
+        let slice = flat.slice(1..4);
+        assert!(slice.iter().eq(items[1..4].iter().copied()));
+        assert!(slice.slice(2..).iter().eq(["\u{1F600}"]));
+        assert!(flat.slice(..0).is_empty());
+        assert!(format!("{:?}", flat.slice(3..)) == format!("{:?}", &items[3..]));
+        let (head, tail) = columns.as_ref().split_at(2);
+        assert!(Str::vector_cast(head).iter().eq(items[..2].iter().copied()));
+        assert!(Str::vector_cast(tail.slice(1..)).iter().eq(items[3..].iter().copied()));
+        assert!(tail.get(0) == Value::Str("world"));
+        let built = items.into_iter().collect::<FlatStrBuilder>();
+        assert!(built.as_flat().iter().eq(flat.iter()));
+        let built = built.finish();
+        assert!(Str::vector_cast(built.as_ref()).iter().eq(items));
+    }
+}
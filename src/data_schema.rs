@@ -8,8 +8,11 @@ macro_rules! Fill {
         $Macro! {
             I64, I32, I16, I8,
             U64, U32, U16, U8,
-            F32, F64, Nil, Str,
-            Pair, Pad, 
+            F32, F64, Nil, Str, 
+            Blob, FixedBinary, DictStr, 
+            Bool, Decimal, Date, Time, 
+            Timestamp, TimestampTz, Interval, 
+            List, Field, Pair, Pad, 
         }
         // Str, Pair, List, Union, Pad, 
     };
@@ -17,9 +20,12 @@ macro_rules! Fill {
 
 pub trait Primitive {}
 
+/// An owned schema, built only by the `encode`/`with` constructors of each type or by deserializing, 
+/// so it is always valid and [`DSchemaRef`] can decode it without checks. 
+#[derive(Clone)]
 pub struct DSchema(Bytes);
 impl DSchema {
-    pub fn empty() -> DSchema {
+    pub(crate) fn empty() -> DSchema {
         DSchema(Bytes::new())
     }
     pub fn from_primitive<T: Primitive + NumOf>() -> DSchema {
@@ -27,13 +33,21 @@ impl DSchema {
         schema.push(T::NUM);
         DSchema(schema)
     }
+    pub fn from_ref(schema: DSchemaRef<'_>) -> DSchema {
+        let mut this = DSchema::empty();
+        this.join(schema);
+        this
+    }
     pub fn as_ref(&self) -> DSchemaRef<'_> {
         DSchemaRef(self.0.slice(..))
     }
-    pub fn join(&mut self, schema: DSchemaRef) {
+    pub(crate) fn join(&mut self, schema: DSchemaRef) {
         self.0.extend(schema.0)
     }
-    pub fn put<T: NoUninit>(&mut self, v: T) {
+    pub(crate) fn put_bytes(&mut self, bytes: &[u8]) {
+        self.0.extend(bytes)
+    }
+    pub(crate) fn put<T: NoUninit>(&mut self, v: T) {
         self.0.pad(std::mem::size_of::<T>());
         self.0.extend(bytemuck::bytes_of(&v));
     }
@@ -44,32 +58,80 @@ pub enum DSchemaEnum<'a> {
     I64, I32, I16, I8,
     U64, U32, U16, U8,
     Nil, F32, F64, Str, 
+    Bool, Date, Time, Timestamp, TimestampTz, Interval,
+    Decimal(u8 /* precision */, u8 /* scale */),
+    Blob, FixedBinary(u32 /* width */),
+    DictStr(u8 /* code width */),
     List(DSchemaRef<'a>),
     Enum(u32, &'a [u16], DSchemaRef<'a>),
     Pair(ScalarLayout, DSchemaRef<'a>, DSchemaRef<'a>),
+    Field(&'a str, DSchemaRef<'a>),
 }
 
 impl<'a> std::fmt::Debug for DSchemaRef<'a> {
     fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        // debug printing should work even on a corrupted schema
+        if let Err(e) = self.validate() { return write!(f, "Invalid({e})") }
         macro_rules! Match {($($X: ident, )*) => {
             match self.tag() {
                 $($X::NUM => $X::dbg(self.cut(1), f), )*
-                _ => unreachable!()
+                _ => unreachable!("schema is validated on construction")
             }
         };}
         crate::Fill!{Match{<Here>}}
     }
 }
 
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
 pub struct ScalarLayout{pub size: usize, pub align: usize}
 
+impl ScalarLayout {
+    /// Layout of `next` placed after `self` in a row, like a `#[repr(C)]` struct with two fields. 
+    pub fn then(self, next: ScalarLayout) -> ScalarLayout {
+        // alignment of zero-sized types is recorded as 0, treat it as 1
+        let align = self.align.max(next.align).max(1);
+        let size = self.offset_of(next) + next.size;
+        ScalarLayout{
+            size: (size + align - 1) & !(align - 1), 
+            align
+        }
+    }
+    /// Offset of `next` placed after `self` in a row
+    pub fn offset_of(self, next: ScalarLayout) -> usize {
+        let align = next.align.max(1);
+        (self.size + align - 1) & !(align - 1)
+    }
+}
+
 impl<'a> DSchemaRef<'a> {
+    /// Check untrusted bytes (e.g. loaded from a catalog) before interpreting them as a schema
+    pub fn new(bytes: &'a [u8]) -> Result<DSchemaRef<'a>, DSchemaError> {
+        let schema = DSchemaRef(bytes);
+        schema.validate()?;
+        Ok(schema)
+    }
+    /// Walk the schema and check tags, payload lengths and offsets. 
+    /// Other methods on [`DSchemaRef`] assume the schema is valid. 
+    pub fn validate(self) -> Result<(), DSchemaError> {
+        self.validate_at(0)
+    }
+    /// Validate a sub-schema that starts at byte `at` of the whole schema
+    pub fn validate_at(self, at: usize) -> Result<(), DSchemaError> {
+        let Some(&tag) = self.0.last() else { Err(DSchemaError::Truncated(at))? };
+        macro_rules! Match {($($X: ident, )*) => {
+            match Tag::try_from(tag) {
+                $(Ok(Tag::$X) => $X::validate(self.cut(1), at), )*
+                Err(tag) => Err(DSchemaError::UnknownTag { tag, at: at + self.len() - 1 }),
+            }
+        };}
+        crate::Fill!{Match{<Here>}}
+    }
     pub fn decode(self) -> DSchemaEnum<'a> {
         // declare a branch for each variant
         macro_rules! Match {($($X: ident, )*) => {
             match self.tag() {
                 $($X::NUM => $X::decode(self.cut(1)), )*
-                _ => unreachable!()
+                _ => unreachable!("schema is validated on construction")
             }
         };}
         crate::Fill!{Match{<Here>}}
@@ -77,12 +139,15 @@ impl<'a> DSchemaRef<'a> {
     pub fn len(self) -> usize {
         return self.0.len()
     }
+    pub fn bytes(self) -> &'a [u8] {
+        self.0
+    }
     pub fn num_columns(&self) -> usize {
         // declare a branch for each variant
         macro_rules! Match {($($X: ident, )*) => {
             match self.tag() {
                 $($X::NUM => $X::num_columns(self.cut(1)), )*
-                _ => unreachable!()
+                _ => unreachable!("schema is validated on construction")
             }
         };}
         crate::Fill!{Match{<Here>}}
@@ -92,13 +157,13 @@ impl<'a> DSchemaRef<'a> {
         macro_rules! Match {($($X: ident, )*) => {
             match self.tag() {
                 $($X::NUM => $X::scalar_layout(self.cut(1)), )*
-                _ => unreachable!()
+                _ => unreachable!("schema is validated on construction")
             }
         };}
         crate::Fill!{Match{<Here>}}
     }
     pub fn tag(self) -> u8 {
-        *self.0.last().unwrap()
+        *self.0.last().expect("schema is validated on construction")
     }
     pub fn u16(self) -> u16 {
         u16::from_ne_bytes(unsafe{self.0[self.0.len()-2..].try_into().unwrap_unchecked()})
@@ -109,45 +174,63 @@ impl<'a> DSchemaRef<'a> {
     pub fn u64(self) -> u64 {
         u64::from_ne_bytes(unsafe{self.0[self.0.len()-8..].try_into().unwrap_unchecked()})
     }
-    pub fn cut(self, l: usize) -> Self {
+    pub(crate) fn cut(self, l: usize) -> Self {
         DSchemaRef(&self.0[..self.0.len()-l])
     }
-    pub fn slice(self, range: std::ops::Range<usize>) -> DSchemaRef<'a> {
+    pub(crate) fn slice(self, range: std::ops::Range<usize>) -> DSchemaRef<'a> {
         DSchemaRef(&self.0[range])
     }
+    /// Find the sub-schema at a dotted path of field names (e.g. `s.b`), 
+    /// and the range of leaf columns it occupies in a [`crate::data_buffer::VBuf`] of this schema. 
+    pub fn lookup(self, path: &str) -> Option<(DSchemaRef<'a>, std::ops::Range<usize>)> {
+        // search a field through the pairs of a record, return its column offset
+        fn field<'a>(schema: DSchemaRef<'a>, name: &str) -> Option<(usize, DSchemaRef<'a>)> {
+            match schema.decode() {
+                DSchemaEnum::Field(n, child) => (n == name).then_some((0, child)),
+                DSchemaEnum::Pair(_, snd, fst) => field(fst, name).or_else(|| {
+                    let (offset, child) = field(snd, name)?;
+                    Some((offset + fst.num_columns(), child))
+                }),
+                _ => None,
+            }
+        }
+        let mut schema = self;
+        let mut start = 0;
+        for name in path.split('.') {
+            let (offset, child) = field(schema, name)?;
+            start += offset;
+            schema = child;
+        }
+        Some((schema, start..start+schema.num_columns()))
+    }
 }
 
 /// [`DSchemaParser`] decode schema to an enumeration
+/// 
+/// Except for `validate`, the methods receive a schema that is already validated (with the tag byte cut). 
 pub trait DSchemaParser<const TAG: u8> {
-    fn decode<'a>(schema: DSchemaRef<'a>) -> DSchemaEnum<'a> { 
-        todo!("DSchemaParser::decode for {:?}", Tag::from(TAG));
-    }
-    fn encode<'a>(children: &[DSchemaRef]) -> DSchema { 
-        todo!("DSchemaParser::encode for {:?}", Tag::from(TAG));
-    }
-    fn scalar_layout<'a>(schema: DSchemaRef) -> ScalarLayout {
-        todo!("DSchemaParser::scalar_layout for {:?}", Tag::from(TAG));
-    }
-    fn dbg(schema: DSchemaRef<'_>, fmt: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
-        todo!("DSchemaParser::dbg for {:?}", Tag::from(TAG));
-    }
-    fn num_columns<'a>(schema: DSchemaRef) -> usize {
-        todo!("DSchemaParser::num_columns for {:?}", Tag::from(TAG));
-    }
+    fn decode<'a>(schema: DSchemaRef<'a>) -> DSchemaEnum<'a>;
+    fn encode<'a>(children: &[DSchemaRef]) -> DSchema;
+    fn scalar_layout<'a>(schema: DSchemaRef) -> ScalarLayout;
+    fn dbg(schema: DSchemaRef<'_>, fmt: &mut std::fmt::Formatter<'_>) -> std::fmt::Result;
+    fn num_columns<'a>(schema: DSchemaRef) -> usize;
+    /// Check the schema payload (with the tag byte cut), which starts at byte `at` of the whole schema
+    fn validate(schema: DSchemaRef<'_>, at: usize) -> Result<(), DSchemaError>;
 }
 
 pub trait NumOf { const NUM: u8; }
 macro_rules! DeclareTagEnum {
     ($($Tag: ident, )*) => {
         DeclareTagEnum! {<Progression Mode> $($Tag, )* }
-        #[derive(Clone, Copy, Debug)]
+        #[derive(Clone, Copy, Debug, PartialEq, Eq)]
         #[repr(u8)]
         pub enum Tag { $($Tag = $Tag::NUM, )* }
-        impl From<u8> for Tag {
-            fn from(value: u8) -> Tag {
+        impl TryFrom<u8> for Tag {
+            type Error = u8;
+            fn try_from(value: u8) -> Result<Tag, u8> {
                 match value {
-                    $($Tag::NUM => Tag::$Tag, )*
-                    _ => unreachable!()
+                    $($Tag::NUM => Ok(Tag::$Tag), )*
+                    _ => Err(value)
                 }
             }
         }
@@ -156,17 +239,177 @@ macro_rules! DeclareTagEnum {
     (<Progression Mode> $X: ident, $Y: ident, $($Tag: ident, )*) => {
         pub struct $X;
         impl NumOf for $X { const NUM: u8 = $Y::NUM + 1; }
-        impl Into<u8> for $X { fn into(self) -> u8 { return $X::NUM; } }
+        impl From<$X> for u8 { fn from(_: $X) -> u8 { $X::NUM } }
         DeclareTagEnum!{<Progression Mode> $Y, $($Tag, )* }
     };
     (<Progression Mode> $X: ident, ) => {
         pub struct $X;
-        impl Into<u8> for $X { fn into(self) -> u8 { return $X::NUM; } }
+        impl From<$X> for u8 { fn from(_: $X) -> u8 { $X::NUM } }
         impl NumOf for $X { const NUM: u8 = 0; }
     }
 }
 crate::Fill!{DeclareTagEnum{<Here>}}
 
+/*                                         */
+/* Portable on-disk encoding of the schema */
+/*                                         */
+
+/// Errors raised when a serialized (or corrupted) schema cannot be decoded
+#[derive(Debug, Clone, Copy, PartialEq, Eq, thiserror::Error)]
+pub enum DSchemaError {
+    #[error("schema doesn't start with magic bytes {:?}", DSCHEMA_MAGIC)]
+    BadMagic,
+    #[error("schema version {0} is not supported (current version is {DSCHEMA_VERSION})")]
+    UnsupportedVersion(u16),
+    #[error("unknown type tag {tag} at byte {at}")]
+    UnknownTag { tag: u8, at: usize },
+    #[error("schema is truncated at byte {0}")]
+    Truncated(usize),
+    #[error("schema has {0} trailing bytes")]
+    TrailingBytes(usize),
+    #[error("schema is nested deeper than {DSCHEMA_MAX_DEPTH}")]
+    TooDeep,
+    #[error("offset {offset} is out of range 0..{len} at byte {at}")]
+    OutOfRange { offset: usize, len: usize, at: usize },
+    #[error("parameter of {tag:?} at byte {at} is not valid")]
+    BadParameter { tag: Tag, at: usize },
+    #[error("field name at byte {at} is not utf-8")]
+    BadName { at: usize },
+    #[error("schema at byte {at} doesn't fit in u16 offsets")]
+    TooLarge { at: usize },
+    #[error("scalar layout {found:?} doesn't match the layout {expect:?} of children at byte {at}")]
+    BadLayout { found: ScalarLayout, expect: ScalarLayout, at: usize },
+}
+
+pub const DSCHEMA_MAGIC: [u8; 4] = *b"MUAS";
+pub const DSCHEMA_VERSION: u16 = 1;
+pub const DSCHEMA_MAX_DEPTH: usize = 256;
+
+impl Tag {
+    /// Tag number in serialized schema. 
+    /// Unlike `Tag as u8`, it doesn't depend on the order of types in [`Fill!`], so never change an existing number. 
+    pub fn stable(self) -> Option<u8> {
+        match self {
+            Tag::I64 => Some(1), Tag::I32 => Some(2), Tag::I16 => Some(3), Tag::I8 => Some(4),
+            Tag::U64 => Some(5), Tag::U32 => Some(6), Tag::U16 => Some(7), Tag::U8 => Some(8),
+            Tag::F32 => Some(9), Tag::F64 => Some(10), Tag::Nil => Some(11), Tag::Str => Some(12),
+            Tag::Pair => Some(13), Tag::Field => Some(14), 
+            Tag::Bool => Some(15), Tag::Decimal => Some(16), Tag::Date => Some(17), Tag::Time => Some(18),
+            Tag::Timestamp => Some(19), Tag::TimestampTz => Some(20), Tag::Interval => Some(21), 
+            Tag::Blob => Some(22), Tag::FixedBinary => Some(23), 
+            Tag::DictStr => Some(24), Tag::List => Some(25), 
+            Tag::Pad => None,
+        }
+    }
+    pub fn from_stable(stable: u8) -> Option<Tag> {
+        macro_rules! Match {($($X: ident, )*) => {
+            [$(Tag::$X, )*].into_iter().find(|tag| tag.stable() == Some(stable))
+        };}
+        crate::Fill!{Match{<Here>}}
+    }
+}
+
+impl DSchema {
+    /// Serialize schema into a portable format: 
+    /// `MAGIC VERSION(u16, little endian) NODE`, where each node is written in pre-order as its stable tag followed by its children. 
+    pub fn serialize(&self) -> Vec<u8> {
+        fn node(schema: DSchemaRef, out: &mut Vec<u8>) {
+            let tag = Tag::try_from(schema.tag()).ok().and_then(Tag::stable);
+            out.push(tag.expect("in-memory schema contains a non-serializable tag"));
+            match schema.decode() {
+                DSchemaEnum::Pair(_, b, a) => {
+                    node(a, out);
+                    node(b, out);
+                }
+                DSchemaEnum::List(child) => node(child, out),
+                DSchemaEnum::Field(name, child) => {
+                    out.extend((name.len() as u16).to_le_bytes());
+                    out.extend(name.as_bytes());
+                    node(child, out);
+                }
+                DSchemaEnum::Decimal(precision, scale) => {
+                    out.extend([precision, scale]);
+                }
+                DSchemaEnum::FixedBinary(width) => {
+                    out.extend(width.to_le_bytes());
+                }
+                DSchemaEnum::DictStr(width) => {
+                    out.push(width);
+                }
+                _ => {}
+            }
+        }
+        let mut out = Vec::with_capacity(DSCHEMA_MAGIC.len() + 2 + self.as_ref().len());
+        out.extend(DSCHEMA_MAGIC);
+        out.extend(DSCHEMA_VERSION.to_le_bytes());
+        node(self.as_ref(), &mut out);
+        out
+    }
+    /// Deserialize schema written by [`DSchema::serialize`], possibly on another machine or build. 
+    pub fn deserialize(bytes: &[u8]) -> Result<DSchema, DSchemaError> {
+        fn node(bytes: &[u8], at: &mut usize, depth: usize) -> Result<DSchema, DSchemaError> {
+            if depth > DSCHEMA_MAX_DEPTH { Err(DSchemaError::TooDeep)? }
+            let &stable = bytes.get(*at).ok_or(DSchemaError::Truncated(*at))?;
+            let tag = Tag::from_stable(stable).ok_or(DSchemaError::UnknownTag { tag: stable, at: *at })?;
+            let start = *at;
+            *at += 1;
+            macro_rules! Match {($($X: ident, )*) => {
+                match tag {
+                    Tag::Pair => {
+                        let a = node(bytes, at, depth + 1)?;
+                        let b = node(bytes, at, depth + 1)?;
+                        Pair::with(a.as_ref(), b.as_ref()).ok_or(DSchemaError::TooLarge { at: start })
+                    }
+                    Tag::List => {
+                        let child = node(bytes, at, depth + 1)?;
+                        Ok(List::encode(&[child.as_ref()]))
+                    }
+                    Tag::Field => {
+                        let n = bytes.get(*at..*at+2).ok_or(DSchemaError::Truncated(bytes.len()))?;
+                        let n = u16::from_le_bytes([n[0], n[1]]) as usize;
+                        let name = bytes.get(*at+2..*at+2+n).ok_or(DSchemaError::Truncated(bytes.len()))?;
+                        let name = std::str::from_utf8(name).map_err(|_| DSchemaError::BadName { at: *at+2 })?;
+                        *at += 2 + n;
+                        let child = node(bytes, at, depth + 1)?;
+                        Field::with(name, child.as_ref()).ok_or(DSchemaError::TooLarge { at: start })
+                    }
+                    Tag::Decimal => {
+                        let &[precision, scale] = bytes.get(*at..*at+2).ok_or(DSchemaError::Truncated(bytes.len()))? else { unreachable!() };
+                        let schema = Decimal::with(precision, scale).ok_or(DSchemaError::BadParameter { tag: Tag::Decimal, at: *at })?;
+                        *at += 2;
+                        Ok(schema)
+                    }
+                    Tag::FixedBinary => {
+                        let width = bytes.get(*at..*at+4).ok_or(DSchemaError::Truncated(bytes.len()))?;
+                        let width = u32::from_le_bytes([width[0], width[1], width[2], width[3]]);
+                        let schema = FixedBinary::with(width).ok_or(DSchemaError::BadParameter { tag: Tag::FixedBinary, at: *at })?;
+                        *at += 4;
+                        Ok(schema)
+                    }
+                    Tag::DictStr => {
+                        let &width = bytes.get(*at).ok_or(DSchemaError::Truncated(bytes.len()))?;
+                        let schema = DictStr::with(width).ok_or(DSchemaError::BadParameter { tag: Tag::DictStr, at: *at })?;
+                        *at += 1;
+                        Ok(schema)
+                    }
+                    $(Tag::$X => Ok($X::encode(&[])), )*
+                    Tag::Pad => unreachable!("pad has no stable tag"),
+                }
+            };}
+            Match! { I64, I32, I16, I8, U64, U32, U16, U8, F32, F64, Nil, Str, Blob, Bool, Date, Time, Timestamp, TimestampTz, Interval, }
+        }
+        let head = DSCHEMA_MAGIC.len();
+        if bytes.len() < head || bytes[..head] != DSCHEMA_MAGIC { Err(DSchemaError::BadMagic)? }
+        let version = bytes.get(head..head+2).ok_or(DSchemaError::Truncated(bytes.len()))?;
+        let version = u16::from_le_bytes([version[0], version[1]]);
+        if version != DSCHEMA_VERSION { Err(DSchemaError::UnsupportedVersion(version))? }
+        let mut at = head + 2;
+        let schema = node(bytes, &mut at, 0)?;
+        if at != bytes.len() { Err(DSchemaError::TrailingBytes(bytes.len() - at))? }
+        Ok(schema)
+    }
+}
+
 #[cfg(test)]
 mod test {
     use super::*;
@@ -187,4 +430,94 @@ mod test {
         let pair = Pair::encode(&[Pair::encode(&[i32, i64]).as_ref(), f64]);
         assert!("Pair(Pair(I32, I64), F64)" == format!("{:?}", pair.as_ref()));
     }
+
+    #[test]
+    fn validate() {
+        let i32 = I32::encode(&[]);
+        let str = Str::encode(&[]);
+        let pair = Pair::encode(&[i32.as_ref(), str.as_ref()]);
+        let bytes = pair.as_ref().0;
+        assert!(pair.as_ref().validate().is_ok());
+        assert!(DSchemaRef::new(&[]).err() == Some(DSchemaError::Truncated(0)));
+        assert!(DSchemaRef::new(&[200]).err() == Some(DSchemaError::UnknownTag { tag: 200, at: 0 }));
+        assert!(DSchemaRef::new(&[Pad::NUM]).err() == Some(DSchemaError::UnknownTag { tag: Pad::NUM, at: 0 }));
+        assert!(DSchemaRef::new(&[I32::NUM, I32::NUM]).err() == Some(DSchemaError::TrailingBytes(1)));
+        // truncated pair payload
+        assert!(DSchemaRef::new(&bytes[bytes.len()-5..]).err() == Some(DSchemaError::Truncated(0)));
+        // corrupted offset of the second child
+        let mut corrupted = bytes.to_vec();
+        corrupted[bytes.len()-5] = 0xff;
+        assert!(matches!(DSchemaRef::new(&corrupted), Err(DSchemaError::OutOfRange { .. })));
+        // corrupted tag of the first child
+        let mut corrupted = bytes.to_vec();
+        corrupted[0] = 200;
+        assert!(DSchemaRef::new(&corrupted).err() == Some(DSchemaError::UnknownTag { tag: 200, at: 0 }));
+        assert!(format!("{:?}", DSchemaRef(&corrupted)) == "Invalid(unknown type tag 200 at byte 0)");
+        // corrupted layout
+        let mut corrupted = bytes.to_vec();
+        corrupted[8] ^= 1;
+        assert!(matches!(DSchemaRef::new(&corrupted), Err(DSchemaError::BadLayout { .. })));
+    }
+
+    #[test]
+    #[should_panic]
+    fn encode_pad() {
+        Pad::encode(&[]);
+    }
+
+    #[test]
+    fn lookup() {
+        let i32 = I32::encode(&[]);
+        let i64 = I64::encode(&[]);
+        let str = Str::encode(&[]);
+        let b = Pair::encode(&[Field::named("a", i64.as_ref()).as_ref(), Field::named("b", str.as_ref()).as_ref()]);
+        let schema = Pair::encode(&[Field::named("a", i32.as_ref()).as_ref(), Field::named("s", b.as_ref()).as_ref()]);
+        let s = schema.as_ref();
+        assert!(s.validate().is_ok());
+        assert!(format!("{s:?}") == "Pair(a: I32, s: Pair(a: I64, b: Str))");
+        assert!(s.lookup("a").map(|x| x.1) == Some(0..1));
+        assert!(s.lookup("s").map(|x| x.1) == Some(1..4));
+        assert!(s.lookup("s.a").map(|x| x.1) == Some(1..2));
+        assert!(s.lookup("s.b").map(|x| (format!("{:?}", x.0), x.1)) == Some(("Str".into(), 2..4)));
+        assert!(s.lookup("s.c").is_none());
+        assert!(s.lookup("b").is_none());
+        let back = DSchema::deserialize(&schema.serialize()).unwrap();
+        assert!(back.as_ref() == s);
+    }
+
+    #[test]
+    fn serialize_deserialize() {
+        let str = Str::encode(&[]);
+        let u8 = U8::encode(&[]);
+        let nil = Nil::encode(&[]);
+        let pair = Pair::encode(&[str.as_ref(), Pair::encode(&[u8.as_ref(), nil.as_ref()]).as_ref()]);
+        let bytes = pair.serialize();
+        assert!(bytes == [b'M', b'U', b'A', b'S', 1, 0, 13, 12, 13, 8, 11]);
+        let back = DSchema::deserialize(&bytes).unwrap();
+        assert!(back.as_ref() == pair.as_ref());
+        assert!(DSchema::deserialize(&bytes[..8]).err() == Some(DSchemaError::Truncated(8)));
+        assert!(DSchema::deserialize(&[&bytes[..], &[0]].concat()).err() == Some(DSchemaError::TrailingBytes(1)));
+        assert!(DSchema::deserialize(&[&bytes[..6], &[200]].concat()).err() == Some(DSchemaError::UnknownTag { tag: 200, at: 6 }));
+        assert!(DSchema::deserialize(&[&bytes[..4], &[2, 0, 12]].concat()).err() == Some(DSchemaError::UnsupportedVersion(2)));
+        assert!(DSchema::deserialize(b"MUA").err() == Some(DSchemaError::BadMagic));
+        let decimal = Pair::encode(&[Decimal::with(12, 2).unwrap().as_ref(), TimestampTz::encode(&[]).as_ref()]);
+        let bytes = decimal.serialize();
+        assert!(bytes[6..] == [13, 16, 12, 2, 20]);
+        assert!(DSchema::deserialize(&bytes).unwrap().as_ref() == decimal.as_ref());
+        assert!(DSchema::deserialize(&[&bytes[..6], &[16, 2, 3]].concat()).err() == Some(DSchemaError::BadParameter { tag: Tag::Decimal, at: 7 }));
+        let binary = Pair::encode(&[Blob::encode(&[]).as_ref(), FixedBinary::with(16).unwrap().as_ref()]);
+        let bytes = binary.serialize();
+        assert!(bytes[6..] == [13, 22, 23, 16, 0, 0, 0]);
+        assert!(DSchema::deserialize(&bytes).unwrap().as_ref() == binary.as_ref());
+        assert!(DSchema::deserialize(&[&bytes[..6], &[23, 0, 0, 0, 0]].concat()).err() == Some(DSchemaError::BadParameter { tag: Tag::FixedBinary, at: 7 }));
+        let dict = DictStr::with(2).unwrap();
+        assert!(dict.serialize()[6..] == [24, 2]);
+        assert!(DSchema::deserialize(&dict.serialize()).unwrap().as_ref() == dict.as_ref());
+        assert!(DSchema::deserialize(&[&bytes[..6], &[24, 3]].concat()).err() == Some(DSchemaError::BadParameter { tag: Tag::DictStr, at: 7 }));
+        // each field fits, but the pair of them doesn't
+        let field = [&[14u8][..], &40000u16.to_le_bytes(), &[b'x'; 40000], &[1]].concat();
+        let bytes = [&bytes[..6], &[13], &field, &field].concat();
+        assert!(DSchema::deserialize(&[&bytes[..6], &field].concat()).is_ok());
+        assert!(DSchema::deserialize(&bytes).err() == Some(DSchemaError::TooLarge { at: 6 }));
+    }
 }
\ No newline at end of file
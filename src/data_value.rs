@@ -0,0 +1,75 @@
+use crate::util_datetime::MonthDayMicros;
+
+/// Dynamically typed value, mirrors [`crate::data_schema::DSchemaEnum`]
+///
+/// Variable-sized values borrow from the buffer they are read from.
+/// Decimal is kept as a scaled integer, the scale lives in the schema.
+#[derive(Debug, Clone, PartialEq)]
+pub enum Value<'a> {
+    I64(i64), I32(i32), I16(i16), I8(i8),
+    U64(u64), U32(u32), U16(u16), U8(u8),
+    Nil, F32(f32), F64(f64), Str(&'a str),
+    Bool(bool), Date(i32), Time(i64), Timestamp(i64), TimestampTz(i64), Interval(MonthDayMicros),
+    Decimal(i128),
+    Blob(&'a [u8]), FixedBinary(&'a [u8]),
+    List(Vec<Value<'a>>),
+    Pair(Box<Value<'a>>, Box<Value<'a>>),
+    Field(&'a str, Box<Value<'a>>),
+}
+
+impl<'a> Value<'a> {
+    pub fn pair(fst: Value<'a>, snd: Value<'a>) -> Value<'a> {
+        Value::Pair(Box::new(fst), Box::new(snd))
+    }
+    pub fn field(name: &'a str, child: Value<'a>) -> Value<'a> {
+        Value::Field(name, Box::new(child))
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::data_schema::*;
+    use crate::data_buffer::*;
+
+    #[test]
+    fn nested_pairs() {
+        // (a: i32, (s: str, (p: (bool, nil), (d: decimal(10, 2), f: binary(2)))))
+        let p = Pair::encode(&[Bool::encode(&[]).as_ref(), Nil::encode(&[]).as_ref()]);
+        let d = Pair::encode(&[Field::named("d", Decimal::with(10, 2).unwrap().as_ref()).as_ref(), Field::named("f", FixedBinary::with(2).unwrap().as_ref()).as_ref()]);
+        let p = Pair::encode(&[Field::named("p", p.as_ref()).as_ref(), d.as_ref()]);
+        let s = Pair::encode(&[Field::named("s", Str::encode(&[]).as_ref()).as_ref(), p.as_ref()]);
+        let schema = Pair::encode(&[Field::named("a", I32::encode(&[]).as_ref()).as_ref(), s.as_ref()]);
+        let strings = (0..50).map(|i| format!("#{i}")).collect::<Vec<_>>();
+        let value = |i: usize| Value::pair(
+            Value::field("a", Value::I32(i as i32)),
+            Value::pair(
+                Value::field("s", Value::Str(&strings[i])),
+                Value::pair(
+                    Value::field("p", Value::pair(Value::Bool(i.is_multiple_of(2)), Value::Nil)),
+                    Value::pair(
+                        Value::field("d", Value::Decimal(i as i128 * 3)),
+                        Value::field("f", Value::FixedBinary(&strings[i].as_bytes()[..2])),
+                    ),
+                ),
+            ),
+        );
+        let mut columns = VBuf::new(schema);
+        for i in 0..50 { columns.push(&value(i)) }
+        for i in 0..50 { assert!(columns.get(i) == value(i), "{:?} != {:?}", columns.get(i), value(i)) }
+        // the columns agree with the typed view
+        let (a, _) = columns.as_ref().pair();
+        assert!(I32::vector_cast(a.field()).iter().copied().eq(0..50));
+        // values pushed through a row come back the same
+        let row = columns.row(7);
+        columns.push_row(row.as_ref());
+        assert!(columns.get(50) == value(7));
+    }
+
+    #[test]
+    #[should_panic]
+    fn mismatched_push() {
+        let mut columns = VBuf::new(I64::encode(&[]));
+        columns.push(&Value::I32(1));
+    }
+}
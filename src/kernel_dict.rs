@@ -0,0 +1,133 @@
+use crate::data_schema::*;
+use crate::data_buffer::*;
+use crate::data_parser_dict::FlatDict;
+
+/*                                       */
+/* Kernels on dictionary-encoded strings */
+/*                                       */
+
+// Predicates run once per distinct string and then test the codes of each row,
+// so a filter on a low-cardinality column costs a lookup per row instead of a string comparison.
+
+// test each row against a table with one entry per code
+fn select_codes(src: FlatDict<'_>, keep: &[bool]) -> VBuf {
+    crate::WithCodes!(src.codes(), |c| Bool::column(c.iter().map(|x| keep[*x as usize])))
+}
+
+/// Rows equal to `value`
+pub fn eq(src: FlatDict<'_>, value: &str) -> VBuf {
+    let Some(code) = src.lookup(value) else { return Bool::column((0..src.len()).map(|_| false)) };
+    crate::WithCodes!(src.codes(), |c| Bool::column(c.iter().map(|x| *x as u32 == code)))
+}
+
+/// Rows not equal to `value`
+pub fn ne(src: FlatDict<'_>, value: &str) -> VBuf {
+    let Some(code) = src.lookup(value) else { return Bool::column((0..src.len()).map(|_| true)) };
+    crate::WithCodes!(src.codes(), |c| Bool::column(c.iter().map(|x| *x as u32 != code)))
+}
+
+/// Rows equal to any of `values`
+pub fn is_in(src: FlatDict<'_>, values: &[&str]) -> VBuf {
+    matches(src, |x| values.contains(&x))
+}
+
+/// Rows whose string satisfies the predicate, which runs once per distinct string
+pub fn matches(src: FlatDict<'_>, f: impl Fn(&str) -> bool) -> VBuf {
+    let keep = src.dictionary().iter().map(f).collect::<Vec<_>>();
+    select_codes(src, &keep)
+}
+
+/// Groups of equal strings
+///
+/// Group ids are dense and numbered in order of first appearance, `keys` holds the string of each group.
+pub struct DictGroups {
+    pub ids: Vec<u32>,
+    pub keys: VBuf,
+    pub counts: Vec<u64>,
+}
+
+/// Group rows by their code, without comparing or hashing strings
+pub fn group_by(src: FlatDict<'_>) -> DictGroups {
+    // the dictionary may hold strings that no row of the view uses, so codes are renumbered
+    let mut group = vec![u32::MAX; src.dictionary().len()];
+    let (mut codes, mut counts) = (vec![], vec![]);
+    let ids = crate::WithCodes!(src.codes(), |c| c.iter().map(|x| {
+        let x = *x as usize;
+        if group[x] == u32::MAX {
+            group[x] = codes.len() as u32;
+            codes.push(x as u32);
+            counts.push(0);
+        }
+        counts[group[x] as usize] += 1;
+        group[x]
+    }).collect::<Vec<_>>());
+    let dict = src.dictionary();
+    let keys = codes.iter().map(|x| dict.get(*x as usize)).collect::<crate::data_parser_string::FlatStrBuilder>().finish();
+    DictGroups { ids, keys, counts }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::kernel_string::LikePattern;
+    use crate::data_value::Value;
+
+    fn bools(column: &VBuf) -> Vec<bool> {
+        Bool::vector_cast(column.as_ref()).iter().collect()
+    }
+
+    #[test]
+    fn filter_group() {
+        let status = ["ok", "failed", "ok", "pending", "ok", "failed"];
+        let column = DictStr::column(status);
+        let flat = DictStr::vector_cast(column.as_ref());
+        assert!(bools(&eq(flat, "ok")) == [true, false, true, false, true, false]);
+        assert!(bools(&eq(flat, "missing")) == [false; 6]);
+        assert!(bools(&ne(flat, "failed")) == [true, false, true, true, true, false]);
+        assert!(bools(&is_in(flat, &["failed", "pending"])) == [false, true, false, true, false, true]);
+        let like = LikePattern::new("%ai%");
+        assert!(bools(&matches(flat, |x| like.matches(x))) == [false, true, false, false, false, true]);
+        // a view only groups the codes it holds
+        let groups = group_by(DictStr::vector_cast(column.as_ref().slice(1..4)));
+        assert!(groups.ids == [0, 1, 2] && groups.counts == [1, 1, 1]);
+        assert!(Str::vector_cast(groups.keys.as_ref()).iter().eq(["failed", "ok", "pending"]));
+        let groups = group_by(flat);
+        assert!(groups.ids == [0, 1, 0, 2, 0, 1] && groups.counts == [3, 2, 1]);
+        assert!(Str::vector_cast(groups.keys.as_ref()).iter().eq(["ok", "failed", "pending"]));
+    }
+
+    #[test]
+    fn select_across_dictionaries() {
+        use crate::kernel_select::{concat, take};
+        let (a, b) = (DictStr::column(["de", "fr", "de"]), DictStr::column(["us", "de", "us"]));
+        // codes of b are remapped to the dictionary of a
+        let all = concat(&[a.as_ref(), b.as_ref().slice(1..), a.as_ref()]);
+        let flat = DictStr::vector_cast(all.as_ref());
+        assert!(flat.iter().eq(["de", "fr", "de", "de", "us", "de", "fr", "de"]));
+        assert!(flat.dictionary().iter().eq(["de", "fr", "us"]));
+        let taken = take(all.as_ref(), &[4, 0, 4]);
+        assert!(DictStr::vector_cast(taken.as_ref()).iter().eq(["us", "de", "us"]));
+        // hashing and sorting see the decoded strings
+        let plain = Str::vector_cast(flat.decode().as_ref()).iter().collect::<crate::data_parser_string::FlatStrBuilder>().finish();
+        assert!(crate::kernel_hash::hash_columns(&[all.as_ref()]) == crate::kernel_hash::hash_columns(&[plain.as_ref()]));
+        let spec = [crate::kernel_sort::SortSpec::asc()];
+        assert!(crate::kernel_sort::sort_indices_stable(&[all.as_ref()], &spec) == crate::kernel_sort::sort_indices_stable(&[plain.as_ref()], &spec));
+        // merged dictionaries that overflow u8 codes widen them, also inside pairs
+        let strings = (0..400).map(|i| i.to_string()).collect::<Vec<_>>();
+        let (x, y) = (DictStr::column(strings[..200].iter().map(|s| s.as_str())), DictStr::column(strings[150..].iter().map(|s| s.as_str())));
+        assert!(x.schema.as_ref() == DictStr::with(1).unwrap().as_ref() && y.schema.as_ref() == x.schema.as_ref());
+        let all = concat(&[x.as_ref(), y.as_ref()]);
+        assert!(all.schema.as_ref() == DictStr::with(2).unwrap().as_ref());
+        let flat = DictStr::vector_cast(all.as_ref());
+        assert!(flat.dictionary().len() == 400 && flat.iter().eq(strings[..200].iter().chain(&strings[150..]).map(|s| s.as_str())));
+        let pairs = |x: &VBuf| {
+            let mut pair = VBuf::new(Pair::encode(&[I32::encode(&[]).as_ref(), x.schema.as_ref()]));
+            for i in 0..x.len { pair.push(&Value::pair(Value::I32(i as i32), x.get(i))) }
+            pair
+        };
+        let all = concat(&[pairs(&x).as_ref(), pairs(&y).as_ref()]);
+        assert!(all.get(399) == Value::pair(Value::I32(199), Value::Str("349")));
+        // the same dictionary twice still fits
+        assert!(concat(&[x.as_ref(), x.as_ref()]).schema.as_ref() == x.schema.as_ref());
+    }
+}
@@ -0,0 +1,292 @@
+use crate::data_schema::*;
+use crate::data_buffer::*;
+use crate::util_datetime::MonthDayMicros;
+
+/*                                 */
+/* Hashing kernels for each column */
+/*                                 */
+
+// Hashes are combined row-wise: each column folds its value into the running hash of the row,
+// so hashing (a, b) column by column equals hashing the pair (a, b).
+// Values that compare equal hash equal: integers are widened to 64 bits, floats to f64 with
+// -0.0 as 0.0 and a single NaN, and a view hashes the same as a copy of its rows.
+
+const SEED: u64 = 0x243f_6a88_85a3_08d3;
+const K: u64 = 0x9e37_79b9_7f4a_7c15;
+// nil carries no value, but still marks the row
+const NIL: u64 = 0x1319_8a2e_0370_7344;
+
+// murmur3 finalizer
+#[inline(always)]
+fn fmix(mut h: u64) -> u64 {
+    h ^= h >> 33;
+    h = h.wrapping_mul(0xff51_afd7_ed55_8ccd);
+    h ^= h >> 33;
+    h = h.wrapping_mul(0xc4ce_b9fe_1a85_ec53);
+    h ^ (h >> 33)
+}
+
+/// Fold a 64-bit word into a running hash
+#[inline(always)]
+pub fn combine(seed: u64, x: u64) -> u64 {
+    fmix(seed.rotate_left(27).wrapping_mul(K) ^ x)
+}
+
+/// Fold variable-sized bytes into a running hash, the length is folded too
+pub fn combine_bytes(seed: u64, bytes: &[u8]) -> u64 {
+    let chunks = bytes.chunks_exact(8);
+    let rest = chunks.remainder();
+    let mut h = seed ^ (bytes.len() as u64).wrapping_mul(K);
+    for chunk in chunks {
+        h = h.rotate_left(27).wrapping_mul(K) ^ u64::from_le_bytes(chunk.try_into().unwrap());
+    }
+    let mut tail = [0u8; 8];
+    tail[..rest.len()].copy_from_slice(rest);
+    fmix(h.rotate_left(27).wrapping_mul(K) ^ u64::from_le_bytes(tail))
+}
+
+/// Fold each row of a column into the running hash of the row
+pub trait HashKernel<const TAG: u8> {
+    fn hash_into(src: VBufRef<'_>, seeds: &mut [u64]);
+}
+
+/// Fold each row of `src` into `seeds`, dispatched on the runtime schema
+///
+/// Call it on several columns with the same seeds to hash rows across columns.
+pub fn hash_column(src: VBufRef<'_>, seeds: &mut [u64]) {
+    assert!(src.len == seeds.len(), "cannot hash {} rows into {} seeds", src.len, seeds.len());
+    macro_rules! Match {($($X: ident, )*) => {
+        match src.schema.tag() {
+            $($X::NUM => $X::hash_into(src, seeds), )*
+            _ => unreachable!()
+        }
+    };}
+    crate::Fill!{Match{<Here>}}
+}
+
+/// Hash rows across the columns, which must have the same number of rows
+pub fn hash_columns(srcs: &[VBufRef<'_>]) -> Vec<u64> {
+    let Some(first) = srcs.first() else { return vec![] };
+    let mut seeds = vec![SEED; first.len];
+    for src in srcs { hash_column(*src, &mut seeds) }
+    seeds
+}
+
+/*                                    */
+/* Implementation for each data type  */
+/*                                    */
+
+macro_rules! ImplIntegerHashKernel {($($X: ident: $Y: ty => $W: ty, )*) => {$(
+    impl HashKernel<{Tag::$X as u8}> for $X {
+        fn hash_into(src: VBufRef<'_>, seeds: &mut [u64]) {
+            for (h, x) in seeds.iter_mut().zip($X::vector_cast(src)) {
+                *h = combine(*h, *x as $W as u64);
+            }
+        }
+    }
+)*};}
+
+ImplIntegerHashKernel! {
+    I64: i64 => i64, I32: i32 => i64, I16: i16 => i64, I8: i8 => i64,
+    U64: u64 => u64, U32: u32 => u64, U16: u16 => u64, U8: u8 => u64,
+    Date: i32 => i64, Time: i64 => i64,
+    Timestamp: i64 => i64, TimestampTz: i64 => i64,
+}
+
+// -0.0 equals 0.0 and all NaNs are the same NaN
+#[inline(always)]
+fn canonical(x: f64) -> u64 {
+    if x == 0.0 { 0 } else if x.is_nan() { f64::NAN.to_bits() } else { x.to_bits() }
+}
+
+impl HashKernel<{Tag::F64 as u8}> for F64 {
+    fn hash_into(src: VBufRef<'_>, seeds: &mut [u64]) {
+        for (h, x) in seeds.iter_mut().zip(F64::vector_cast(src)) {
+            *h = combine(*h, canonical(*x));
+        }
+    }
+}
+
+impl HashKernel<{Tag::F32 as u8}> for F32 {
+    fn hash_into(src: VBufRef<'_>, seeds: &mut [u64]) {
+        for (h, x) in seeds.iter_mut().zip(F32::vector_cast(src)) {
+            *h = combine(*h, canonical(*x as f64));
+        }
+    }
+}
+
+impl HashKernel<{Tag::Decimal as u8}> for Decimal {
+    fn hash_into(src: VBufRef<'_>, seeds: &mut [u64]) {
+        for (h, x) in seeds.iter_mut().zip(Decimal::vector_cast(src)) {
+            *h = combine(combine(*h, *x as u64), (*x >> 64) as u64);
+        }
+    }
+}
+
+impl HashKernel<{Tag::Interval as u8}> for Interval {
+    fn hash_into(src: VBufRef<'_>, seeds: &mut [u64]) {
+        for (h, MonthDayMicros { months, days, micros }) in seeds.iter_mut().zip(Interval::vector_cast(src)) {
+            *h = combine(combine(*h, (*months as u32 as u64) << 32 | *days as u32 as u64), *micros as u64);
+        }
+    }
+}
+
+impl HashKernel<{Tag::Bool as u8}> for Bool {
+    fn hash_into(src: VBufRef<'_>, seeds: &mut [u64]) {
+        for (h, x) in seeds.iter_mut().zip(Bool::vector_cast(src).iter()) {
+            *h = combine(*h, x as u64);
+        }
+    }
+}
+
+impl HashKernel<{Tag::Nil as u8}> for Nil {
+    fn hash_into(_: VBufRef<'_>, seeds: &mut [u64]) {
+        for h in seeds.iter_mut() { *h = combine(*h, NIL) }
+    }
+}
+
+impl HashKernel<{Tag::Pad as u8}> for Pad {
+    fn hash_into(_: VBufRef<'_>, _: &mut [u64]) {
+        unreachable!("pad never appears in a valid schema")
+    }
+}
+
+impl HashKernel<{Tag::Str as u8}> for Str {
+    fn hash_into(src: VBufRef<'_>, seeds: &mut [u64]) {
+        for (h, x) in seeds.iter_mut().zip(Str::vector_cast(src).iter()) {
+            *h = combine_bytes(*h, x.as_bytes());
+        }
+    }
+}
+
+// a dictionary hashes like the decoded str
+impl HashKernel<{Tag::DictStr as u8}> for DictStr {
+    fn hash_into(src: VBufRef<'_>, seeds: &mut [u64]) {
+        for (h, x) in seeds.iter_mut().zip(DictStr::vector_cast(src).iter()) {
+            *h = combine_bytes(*h, x.as_bytes());
+        }
+    }
+}
+
+impl HashKernel<{Tag::Blob as u8}> for Blob {
+    fn hash_into(src: VBufRef<'_>, seeds: &mut [u64]) {
+        for (h, x) in seeds.iter_mut().zip(Blob::vector_cast(src).iter()) {
+            *h = combine_bytes(*h, x);
+        }
+    }
+}
+
+impl HashKernel<{Tag::FixedBinary as u8}> for FixedBinary {
+    fn hash_into(src: VBufRef<'_>, seeds: &mut [u64]) {
+        for (h, x) in seeds.iter_mut().zip(FixedBinary::vector_cast(src).iter()) {
+            *h = combine_bytes(*h, x);
+        }
+    }
+}
+
+// a list folds its length, then the hash of each element
+impl HashKernel<{Tag::List as u8}> for List {
+    fn hash_into(src: VBufRef<'_>, seeds: &mut [u64]) {
+        let src = List::vector_cast(src);
+        let values = src.values();
+        let mut hashes = vec![SEED; values.len];
+        hash_column(values, &mut hashes);
+        let base = src.offsets()[0] as usize;
+        for (i, h) in seeds.iter_mut().enumerate() {
+            let range = src.range(i);
+            *h = combine(*h, range.len() as u64);
+            for x in &hashes[range.start - base..range.end - base] { *h = combine(*h, *x) }
+        }
+    }
+}
+
+impl HashKernel<{Tag::Field as u8}> for Field {
+    fn hash_into(src: VBufRef<'_>, seeds: &mut [u64]) {
+        hash_column(src.field(), seeds)
+    }
+}
+
+impl HashKernel<{Tag::Pair as u8}> for Pair {
+    fn hash_into(src: VBufRef<'_>, seeds: &mut [u64]) {
+        let (a, b) = src.pair();
+        hash_column(a, seeds);
+        hash_column(b, seeds);
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::data_value::Value;
+
+    fn column(schema: DSchema, values: &[Value<'_>]) -> VBuf {
+        let mut column = VBuf::new(schema);
+        for v in values { column.push(v) }
+        column
+    }
+
+    #[test]
+    fn equal_values() {
+        // integers of any width, and floats of any width
+        let a = column(I8::encode(&[]), &[Value::I8(-1), Value::I8(7)]);
+        let b = column(I64::encode(&[]), &[Value::I64(-1), Value::I64(7)]);
+        assert!(hash_columns(&[a.as_ref()]) == hash_columns(&[b.as_ref()]));
+        let a = column(F64::encode(&[]), &[Value::F64(-0.0), Value::F64(f64::NAN), Value::F64(0.5)]);
+        let b = column(F32::encode(&[]), &[Value::F32(0.0), Value::F32(-f32::NAN), Value::F32(0.5)]);
+        assert!(hash_columns(&[a.as_ref()]) == hash_columns(&[b.as_ref()]));
+        // a view hashes the same as a copy of its rows
+        let words = ["", "a", "hello world", "hello world!", "a"];
+        let s = column(Str::encode(&[]), &words.map(Value::Str));
+        let t = column(Str::encode(&[]), &words[3..].iter().map(|w| Value::Str(w)).collect::<Vec<_>>());
+        let h = hash_columns(&[s.as_ref()]);
+        assert!(hash_columns(&[s.as_ref().slice(3..)]) == hash_columns(&[t.as_ref()]));
+        assert!(h[1] == h[4] && h[0] != h[1] && h[2] != h[3]);
+        // blob of the same bytes hashes like a str
+        let b = column(Blob::encode(&[]), &words.map(|w| Value::Blob(w.as_bytes())));
+        assert!(hash_columns(&[b.as_ref()]) == h);
+    }
+
+    #[test]
+    fn rows_across_columns() {
+        // hashing a pair is hashing its columns one by one
+        let schema = Pair::encode(&[I32::encode(&[]).as_ref(), Pair::encode(&[Str::encode(&[]).as_ref(), Nil::encode(&[]).as_ref()]).as_ref()]);
+        let strings = (0..100).map(|i| format!("{}", i % 10)).collect::<Vec<_>>();
+        let rows = (0..100).map(|i| Value::pair(Value::I32(i / 10), Value::pair(Value::Str(&strings[i as usize]), Value::Nil))).collect::<Vec<_>>();
+        let pairs = column(schema, &rows);
+        let a = column(I32::encode(&[]), &(0..100).map(|i| Value::I32(i / 10)).collect::<Vec<_>>());
+        let b = column(Str::encode(&[]), &strings.iter().map(|s| Value::Str(s)).collect::<Vec<_>>());
+        let n = column(Nil::encode(&[]), &vec![Value::Nil; 100]);
+        let h = hash_columns(&[pairs.as_ref()]);
+        assert!(h == hash_columns(&[a.as_ref(), b.as_ref(), n.as_ref()]));
+        // all rows are distinct, and order of columns matters
+        let mut distinct = h.clone();
+        distinct.sort();
+        distinct.dedup();
+        assert!(distinct.len() == 100);
+        assert!(hash_columns(&[b.as_ref(), a.as_ref(), n.as_ref()]) != h);
+    }
+
+    #[test]
+    fn no_collision() {
+        let values = (0..100000).map(Value::I64).collect::<Vec<_>>();
+        let mut h = hash_columns(&[column(I64::encode(&[]), &values).as_ref()]);
+        h.sort();
+        h.dedup();
+        assert!(h.len() == 100000);
+        // low bits are used for partitioning, they should spread evenly
+        let mut buckets = [0usize; 16];
+        for x in &h { buckets[*x as usize % 16] += 1 }
+        assert!(buckets.iter().all(|b| (5500..7000).contains(b)), "{buckets:?}");
+    }
+
+    #[test]
+    fn lists() {
+        let list = |xs: &[i64]| Value::List(xs.iter().map(|x| Value::I64(*x)).collect());
+        let schema = || List::encode(&[I64::encode(&[]).as_ref()]);
+        let a = column(schema(), &[list(&[1, 2]), list(&[]), list(&[1]), list(&[2]), list(&[1, 2])]);
+        let b = column(schema(), &[list(&[1]), list(&[2]), list(&[1, 2])]);
+        let h = hash_columns(&[a.as_ref()]);
+        assert!(hash_columns(&[a.as_ref().slice(2..)]) == hash_columns(&[b.as_ref()]));
+        assert!(h[0] == h[4] && h[0] != h[2] && h[1] != h[2] && h[2] != h[3]);
+    }
+}
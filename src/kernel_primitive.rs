@@ -0,0 +1,370 @@
+use crate::data_schema::*;
+use crate::data_buffer::*;
+use crate::util_bytes::{MemoryError, MemoryPool};
+use std::sync::Arc;
+use thiserror::Error;
+
+/*                                                      */
+/* Comparison, arithmetic and reduction kernels         */
+/*                                                      */
+
+// Kernels work on the `&[T]` of a primitive column (see `vector_cast`), the slices come from
+// `Bytes` aligned to 16. Loops run over fixed-size chunks (or zipped slices) with no early exit and no branch
+// on values, so that they auto-vectorize on stable rust.
+
+const LANES: usize = 64;
+
+/// Native type of a primitive column
+pub trait Native: bytemuck::Pod + PartialOrd + Send + Sync {
+    /// Tag of the column that holds this type
+    type Tag: Primitive + NumOf;
+    /// Accumulator of sum, wide enough that summing a column never overflows
+    type Acc: Copy + std::ops::Add<Output = Self::Acc>;
+    const ZERO: Self;
+    /// Identity of min and max
+    const MIN: Self;
+    const MAX: Self;
+    /// Integer division by zero is an error, float division by zero is infinity
+    const INTEGER: bool;
+    fn widen(self) -> Self::Acc;
+    /// Wrapped result and whether it overflows
+    fn add(self, rhs: Self) -> (Self, bool);
+    fn sub(self, rhs: Self) -> (Self, bool);
+    fn mul(self, rhs: Self) -> (Self, bool);
+    /// The divisor is never zero for integers
+    fn div(self, rhs: Self) -> (Self, bool);
+}
+
+macro_rules! ImplNativeInteger {($($X: ident: $Y: ty => $A: ty, )*) => {$(
+    impl Native for $Y {
+        type Tag = $X;
+        type Acc = $A;
+        const ZERO: $Y = 0;
+        const MIN: $Y = <$Y>::MIN;
+        const MAX: $Y = <$Y>::MAX;
+        const INTEGER: bool = true;
+        #[inline(always)]
+        fn widen(self) -> $A { self as $A }
+        #[inline(always)]
+        fn add(self, rhs: $Y) -> ($Y, bool) { self.overflowing_add(rhs) }
+        #[inline(always)]
+        fn sub(self, rhs: $Y) -> ($Y, bool) { self.overflowing_sub(rhs) }
+        #[inline(always)]
+        fn mul(self, rhs: $Y) -> ($Y, bool) { self.overflowing_mul(rhs) }
+        #[inline(always)]
+        fn div(self, rhs: $Y) -> ($Y, bool) { self.overflowing_div(rhs) }
+    }
+)*};}
+
+ImplNativeInteger! {
+    I64: i64 => i128, I32: i32 => i128, I16: i16 => i128, I8: i8 => i128,
+    U64: u64 => u128, U32: u32 => u128, U16: u16 => u128, U8: u8 => u128,
+}
+
+macro_rules! ImplNativeFloat {($($X: ident: $Y: ty, )*) => {$(
+    impl Native for $Y {
+        type Tag = $X;
+        type Acc = f64;
+        const ZERO: $Y = 0.0;
+        const MIN: $Y = <$Y>::NEG_INFINITY;
+        const MAX: $Y = <$Y>::INFINITY;
+        const INTEGER: bool = false;
+        #[inline(always)]
+        fn widen(self) -> f64 { self as f64 }
+        #[inline(always)]
+        fn add(self, rhs: $Y) -> ($Y, bool) { (self + rhs, false) }
+        #[inline(always)]
+        fn sub(self, rhs: $Y) -> ($Y, bool) { (self - rhs, false) }
+        #[inline(always)]
+        fn mul(self, rhs: $Y) -> ($Y, bool) { (self * rhs, false) }
+        #[inline(always)]
+        fn div(self, rhs: $Y) -> ($Y, bool) { (self / rhs, false) }
+    }
+)*};}
+
+ImplNativeFloat! {
+    F64: f64, F32: f32,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum CmpOp { Eq, Ne, Lt, Le, Gt, Ge }
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ArithOp { Add, Sub, Mul, Div }
+
+#[derive(Debug, Error, PartialEq, Eq)]
+pub enum ArithError {
+    #[error("arithmetic overflow")]
+    Overflow,
+    #[error("division by zero")]
+    DivideByZero,
+    #[error("columns have different lengths: {0} and {1}")]
+    LengthMismatch(usize, usize),
+    #[error(transparent)]
+    Memory(#[from] MemoryError),
+}
+
+// the other operand of a binary kernel
+#[derive(Clone, Copy)]
+enum Rhs<'a, T> { Column(&'a [T]), Scalar(T) }
+
+/*                    */
+/* Comparison kernels */
+/*                    */
+
+// pack the predicate on each row into a bool column, 64 rows per word
+fn pack<T: Native>(a: &[T], b: Rhs<'_, T>, f: impl Fn(T, T) -> bool) -> VBuf {
+    fn word<T: Native>(x: &[T], y: impl Iterator<Item = T>, f: &impl Fn(T, T) -> bool) -> u64 {
+        x.iter().zip(y).enumerate().fold(0, |w, (l, (x, y))| w | (f(*x, y) as u64) << l)
+    }
+    let mut words = Vec::with_capacity(a.len().div_ceil(LANES));
+    for (k, chunk) in a.chunks(LANES).enumerate() {
+        let w = match b {
+            Rhs::Column(b) => word(chunk, b[k * LANES..][..chunk.len()].iter().copied(), &f),
+            Rhs::Scalar(b) => word(chunk, std::iter::repeat(b), &f),
+        };
+        words.push(w.to_le());
+    }
+    let mut out = VBuf::new(Bool::encode(&[]));
+    if a.is_empty() { return out }
+    let column = &mut out.buffer[0];
+    column.extend(&(a.len() as u64).to_ne_bytes());
+    column.extend(&bytemuck::cast_slice::<u64, u8>(&words)[..a.len().div_ceil(8)]);
+    out.len = a.len();
+    out
+}
+
+// each operator gets its own copy of the kernel
+macro_rules! DispatchCmp {($op: expr, $f: ident => $kernel: expr) => {
+    match $op {
+        CmpOp::Eq => { let $f = |x: T, y: T| x == y; $kernel }
+        CmpOp::Ne => { let $f = |x: T, y: T| x != y; $kernel }
+        CmpOp::Lt => { let $f = |x: T, y: T| x < y; $kernel }
+        CmpOp::Le => { let $f = |x: T, y: T| x <= y; $kernel }
+        CmpOp::Gt => { let $f = |x: T, y: T| x > y; $kernel }
+        CmpOp::Ge => { let $f = |x: T, y: T| x >= y; $kernel }
+    }
+};}
+
+/// Compare two columns row by row into a bool column
+pub fn compare<T: Native>(a: &[T], b: &[T], op: CmpOp) -> Result<VBuf, ArithError> {
+    if a.len() != b.len() { Err(ArithError::LengthMismatch(a.len(), b.len()))? }
+    Ok(DispatchCmp!(op, f => pack(a, Rhs::Column(b), f)))
+}
+
+/// Compare each row of a column with a constant into a bool column
+pub fn compare_scalar<T: Native>(a: &[T], b: T, op: CmpOp) -> VBuf {
+    DispatchCmp!(op, f => pack(a, Rhs::Scalar(b), f))
+}
+
+/*                    */
+/* Arithmetic kernels */
+/*                    */
+
+// apply the operation on each row, overflow of any row fails the whole column,
+// the column is allocated in `pool`, or by the global allocator without one
+fn apply<T: Native>(a: &[T], b: Rhs<'_, T>, f: impl Fn(T, T) -> (T, bool), pool: Option<&Arc<MemoryPool>>) -> Result<VBuf, ArithError> {
+    let mut overflow = false;
+    let mut g = |x: &T, y: T| { let (z, o) = f(*x, y); overflow |= o; z };
+    let out = match b {
+        Rhs::Column(b) => a.iter().zip(b).map(|(x, y)| g(x, *y)).collect::<Vec<T>>(),
+        Rhs::Scalar(b) => a.iter().map(|x| g(x, b)).collect::<Vec<T>>(),
+    };
+    if overflow { Err(ArithError::Overflow)? }
+    let schema = DSchema::from_primitive::<T::Tag>();
+    let mut column = match pool { Some(pool) => VBuf::try_new_in(schema, pool)?, None => VBuf::new(schema) };
+    column.buffer[0].try_extend(bytemuck::cast_slice(&out))?;
+    column.len = a.len();
+    Ok(column)
+}
+
+macro_rules! DispatchArith {($op: expr, $f: ident => $kernel: expr) => {
+    match $op {
+        ArithOp::Add => { let $f = T::add; $kernel }
+        ArithOp::Sub => { let $f = T::sub; $kernel }
+        ArithOp::Mul => { let $f = T::mul; $kernel }
+        ArithOp::Div => { let $f = T::div; $kernel }
+    }
+};}
+
+/// Combine two columns row by row into a column of the same type
+pub fn arith<T: Native>(a: &[T], b: &[T], op: ArithOp) -> Result<VBuf, ArithError> {
+    arith_to(a, b, op, None)
+}
+
+/// Combine two columns row by row into a column allocated in `pool`
+pub fn try_arith_in<T: Native>(a: &[T], b: &[T], op: ArithOp, pool: &Arc<MemoryPool>) -> Result<VBuf, ArithError> {
+    arith_to(a, b, op, Some(pool))
+}
+
+fn arith_to<T: Native>(a: &[T], b: &[T], op: ArithOp, pool: Option<&Arc<MemoryPool>>) -> Result<VBuf, ArithError> {
+    if a.len() != b.len() { Err(ArithError::LengthMismatch(a.len(), b.len()))? }
+    if op == ArithOp::Div && T::INTEGER && b.contains(&T::ZERO) { Err(ArithError::DivideByZero)? }
+    DispatchArith!(op, f => apply(a, Rhs::Column(b), f, pool))
+}
+
+/// Combine each row of a column with a constant into a column of the same type
+pub fn arith_scalar<T: Native>(a: &[T], b: T, op: ArithOp) -> Result<VBuf, ArithError> {
+    arith_scalar_to(a, b, op, None)
+}
+
+/// Combine each row of a column with a constant into a column allocated in `pool`
+pub fn try_arith_scalar_in<T: Native>(a: &[T], b: T, op: ArithOp, pool: &Arc<MemoryPool>) -> Result<VBuf, ArithError> {
+    arith_scalar_to(a, b, op, Some(pool))
+}
+
+fn arith_scalar_to<T: Native>(a: &[T], b: T, op: ArithOp, pool: Option<&Arc<MemoryPool>>) -> Result<VBuf, ArithError> {
+    if op == ArithOp::Div && T::INTEGER && b == T::ZERO { Err(ArithError::DivideByZero)? }
+    DispatchArith!(op, f => apply(a, Rhs::Scalar(b), f, pool))
+}
+
+/*                   */
+/* Reduction kernels */
+/*                   */
+
+// fold lanes independently, then fold the lanes
+fn fold<T: Native>(a: &[T], init: T, f: impl Fn(T, T) -> T) -> T {
+    let mut acc = [init; LANES];
+    let chunks = a.chunks_exact(LANES);
+    let rest = chunks.remainder();
+    for chunk in chunks {
+        for l in 0..LANES { acc[l] = f(acc[l], chunk[l]) }
+    }
+    for (l, x) in rest.iter().enumerate() { acc[l] = f(acc[l], *x) }
+    acc.into_iter().fold(init, f)
+}
+
+// NaN is the only value that doesn't compare with itself, for integers this stops at the first element
+fn has_number<T: Native>(a: &[T]) -> bool {
+    a.iter().any(|x| x.partial_cmp(x).is_some())
+}
+
+/// The smallest value, NaN is skipped, `None` for a column that is empty or all NaN
+pub fn min<T: Native>(a: &[T]) -> Option<T> {
+    if !has_number(a) { return None }
+    Some(fold(a, T::MAX, |m, x| if x < m { x } else { m }))
+}
+
+/// The largest value, NaN is skipped, `None` for a column that is empty or all NaN
+pub fn max<T: Native>(a: &[T]) -> Option<T> {
+    if !has_number(a) { return None }
+    Some(fold(a, T::MIN, |m, x| if x > m { x } else { m }))
+}
+
+/// Sum in the accumulator type, which is wide enough to never overflow
+pub fn sum<T: Native>(a: &[T]) -> T::Acc {
+    let zero = T::ZERO.widen();
+    let mut acc = [zero; LANES];
+    let chunks = a.chunks_exact(LANES);
+    let rest = chunks.remainder();
+    for chunk in chunks {
+        for l in 0..LANES { acc[l] = acc[l] + chunk[l].widen() }
+    }
+    for (l, x) in rest.iter().enumerate() { acc[l] = acc[l] + x.widen() }
+    acc.into_iter().fold(zero, |x, y| x + y)
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use rand::*;
+    use rand_xoshiro::*;
+
+    fn column<T: Native>(values: &[T]) -> VBuf {
+        let mut column = VBuf::new(DSchema::from_primitive::<T::Tag>());
+        column.buffer[0].extend(bytemuck::cast_slice(values));
+        column.len = values.len();
+        column
+    }
+
+    #[test]
+    fn compare_columns() {
+        let mut rng = Xoroshiro128Plus::seed_from_u64(3344);
+        let a = (0..1000).map(|_| rng.gen_range(-5..5)).collect::<Vec<i32>>();
+        let b = (0..1000).map(|_| rng.gen_range(-5..5)).collect::<Vec<i32>>();
+        let ops = [
+            (CmpOp::Eq, i32::eq as fn(&_, &_) -> _), (CmpOp::Ne, i32::ne),
+            (CmpOp::Lt, i32::lt), (CmpOp::Le, i32::le),
+            (CmpOp::Gt, i32::gt), (CmpOp::Ge, i32::ge),
+        ];
+        for (op, f) in ops {
+            let bits = compare(&a, &b, op).unwrap();
+            assert!(bits.len() == 1000);
+            assert!(Bool::vector_cast(bits.as_ref()).iter().eq(a.iter().zip(&b).map(|(x, y)| f(x, y))));
+            let bits = compare_scalar(&a, 0, op);
+            assert!(Bool::vector_cast(bits.as_ref()).iter().eq(a.iter().map(|x| f(x, &0))));
+        }
+        // the bitmap is a bool column, so it can be extended
+        let mut bits = compare_scalar(&[1.0f64, f64::NAN, 3.0], 2.0, CmpOp::Lt);
+        bits.push(&crate::data_value::Value::Bool(true));
+        assert!(Bool::vector_cast(bits.as_ref()).iter().eq([true, false, false, true]));
+        assert!(compare(&a, &b[1..], CmpOp::Eq).is_err());
+    }
+
+    #[test]
+    fn arith_columns() {
+        let a = (0..300).collect::<Vec<i64>>();
+        let b = (0..300).map(|x| x * 2 + 1).collect::<Vec<i64>>();
+        let sum = arith(&a, &b, ArithOp::Add).unwrap();
+        assert!(I64::vector_cast(sum.as_ref()).iter().copied().eq((0..300).map(|x| x * 3 + 1)));
+        let quo = arith(&b, &a[..], ArithOp::Div);
+        assert!(quo.err() == Some(ArithError::DivideByZero));
+        let quo = arith_scalar(&b, 2, ArithOp::Div).unwrap();
+        assert!(I64::vector_cast(quo.as_ref()) == a);
+        assert!(arith_scalar(&[1i8, 100, 2], 2, ArithOp::Mul).err() == Some(ArithError::Overflow));
+        assert!(arith_scalar(&[i32::MIN], -1, ArithOp::Div).err() == Some(ArithError::Overflow));
+        assert!(arith_scalar(&[0u8], 1, ArithOp::Sub).err() == Some(ArithError::Overflow));
+        let f = arith_scalar(&[1.0f32, -1.0], 0.0, ArithOp::Div).unwrap();
+        assert!(F32::vector_cast(f.as_ref()) == [f32::INFINITY, f32::NEG_INFINITY]);
+        // the output is allocated in the pool of the caller
+        let pool = MemoryPool::new(4096);
+        let sum = try_arith_in(&a, &b, ArithOp::Add, &pool).unwrap();
+        assert!(sum.buffer[0].pool().is_some() && pool.used() >= 2400);
+        assert!(matches!(try_arith_scalar_in(&a, 1, ArithOp::Add, &pool), Err(ArithError::Memory(_))));
+        drop(sum);
+        assert!(pool.used() == 0);
+    }
+
+    #[test]
+    fn reduce_columns() {
+        let mut rng = Xoroshiro128Plus::seed_from_u64(5566);
+        let a = (0..1000).map(|_| rng.gen::<i64>()).collect::<Vec<_>>();
+        let column = column(&a);
+        let a = I64::vector_cast(column.as_ref());
+        assert!(min(a) == a.iter().copied().min());
+        assert!(max(a) == a.iter().copied().max());
+        assert!(sum(a) == a.iter().map(|x| *x as i128).sum::<i128>());
+        assert!(sum(&[u64::MAX; 3]) == u64::MAX as u128 * 3);
+        assert!(min::<u8>(&[]).is_none());
+        assert!(max(&[1.0, f64::NAN, -3.0]) == Some(1.0));
+        assert!(min(&[1.0, f64::NAN, -3.0]) == Some(-3.0));
+        assert!(min(&[f32::NAN; 5]).is_none() && max(&[f64::NAN; 20]).is_none());
+        assert!(max(&[f64::NAN, f64::NEG_INFINITY]) == Some(f64::NEG_INFINITY));
+        assert!(sum(&[0.5f32; 10]) == 5.0);
+    }
+
+    // cargo test --release bench -- --ignored --nocapture
+    #[test]
+    #[ignore]
+    fn bench() {
+        const N: usize = 1 << 24;
+        let mut rng = Xoroshiro128Plus::seed_from_u64(7788);
+        let a = column(&(0..N).map(|_| rng.gen::<i32>() >> 2).collect::<Vec<_>>());
+        let b = column(&(0..N).map(|_| rng.gen::<i32>() >> 2).collect::<Vec<_>>());
+        let (a, b) = (I32::vector_cast(a.as_ref()), I32::vector_cast(b.as_ref()));
+        assert!((a.as_ptr() as usize).is_multiple_of(16) && (b.as_ptr() as usize).is_multiple_of(16));
+        let measure = |name: &str, bytes: usize, f: &dyn Fn()| {
+            let start = std::time::Instant::now();
+            for _ in 0..10 { f() }
+            let secs = start.elapsed().as_secs_f64() / 10.0;
+            println!("{name:>16}: {:>8.2} GB/s", bytes as f64 / secs / 1e9);
+        };
+        let n = std::mem::size_of_val(a);
+        measure("compare", 2 * n, &|| { std::hint::black_box(compare(a, b, CmpOp::Lt).unwrap()); });
+        measure("compare_scalar", n, &|| { std::hint::black_box(compare_scalar(a, 0, CmpOp::Ge)); });
+        measure("add", 2 * n, &|| { std::hint::black_box(arith(a, b, ArithOp::Add).unwrap()); });
+        measure("mul_scalar", n, &|| { std::hint::black_box(arith_scalar(a, 3, ArithOp::Mul).unwrap()); });
+        measure("min", n, &|| { std::hint::black_box(min(a)); });
+        measure("sum", n, &|| { std::hint::black_box(sum(a)); });
+    }
+}
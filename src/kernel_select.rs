@@ -0,0 +1,523 @@
+use crate::data_schema::*;
+use crate::data_buffer::*;
+use crate::data_value::Value;
+use crate::data_parser_bool::FlatBool;
+use crate::data_parser_dict::FlatDict;
+use crate::data_parser_string::FlatStr;
+use std::collections::HashMap;
+use std::sync::Arc;
+use crate::util_bytes::{MemoryError, MemoryPool};
+use crate::util_datetime::MonthDayMicros;
+
+/*                                           */
+/* Selection kernels: take, filter & concat  */
+/*                                           */
+
+/// Copy selected rows of a vector buffer to the tail of another
+///
+/// Both buffers have the same schema, `dst` is written through [`VBufMut`] so the caller updates row counts.
+pub trait SelectKernel<const TAG: u8> {
+    /// Append rows of `src` at `indices` (relative to the view) to `dst`
+    fn take_into(src: VBufRef<'_>, indices: &[u32], dst: VBufMut<'_>) -> Result<(), MemoryError>;
+    /// Append all rows of `src` to `dst`
+    fn extend_into(src: VBufRef<'_>, dst: VBufMut<'_>) -> Result<(), MemoryError>;
+}
+
+/// Append rows of `src` at `indices` to `dst`, dispatched on the runtime schema
+///
+/// Dictionaries of `dst` may have other code widths, it panics if one of them fills up.
+/// A failure may leave some of the columns of `dst` written.
+pub fn take_into(src: VBufRef<'_>, indices: &[u32], dst: VBufMut<'_>) -> Result<(), MemoryError> {
+    assert!(same_values(src.schema, dst.schema), "cannot take {:?} into {:?}", src.schema, dst.schema);
+    macro_rules! Match {($($X: ident, )*) => {
+        match src.schema.tag() {
+            $($X::NUM => $X::take_into(src, indices, dst), )*
+            _ => unreachable!()
+        }
+    };}
+    crate::Fill!{Match{<Here>}}
+}
+
+/// Append all rows of `src` to `dst`, dispatched on the runtime schema
+///
+/// Dictionaries of `dst` may have other code widths, it panics if one of them fills up.
+/// A failure may leave some of the columns of `dst` written.
+pub fn extend_into(src: VBufRef<'_>, dst: VBufMut<'_>) -> Result<(), MemoryError> {
+    assert!(same_values(src.schema, dst.schema), "cannot extend {:?} with {:?}", dst.schema, src.schema);
+    macro_rules! Match {($($X: ident, )*) => {
+        match src.schema.tag() {
+            $($X::NUM => $X::extend_into(src, dst), )*
+            _ => unreachable!()
+        }
+    };}
+    crate::Fill!{Match{<Here>}}
+}
+
+/// Gather rows at `indices` into a new vector buffer
+///
+/// Panics if the allocation fails, see [`try_take_in`].
+pub fn take(src: VBufRef<'_>, indices: &[u32]) -> VBuf {
+    take_to(src, indices, VBuf::new(DSchema::from_ref(src.schema))).unwrap_or_else(|e| panic!("{e}"))
+}
+
+/// Gather rows at `indices` into a new vector buffer allocated in `pool`
+pub fn try_take_in(src: VBufRef<'_>, indices: &[u32], pool: &Arc<MemoryPool>) -> Result<VBuf, MemoryError> {
+    take_to(src, indices, VBuf::try_new_in(DSchema::from_ref(src.schema), pool)?)
+}
+
+fn take_to(src: VBufRef<'_>, indices: &[u32], mut dst: VBuf) -> Result<VBuf, MemoryError> {
+    take_into(src, indices, dst.as_mut())?;
+    dst.len = indices.len();
+    Ok(dst)
+}
+
+/// Keep rows whose bit is set, the bitmap has one bit per row of `src`
+pub fn filter(src: VBufRef<'_>, bitmap: &FlatBool<'_>) -> VBuf {
+    take(src, &Selection::from_bitmap(bitmap).indices)
+}
+
+/// Keep rows whose bit is set into a new vector buffer allocated in `pool`
+pub fn try_filter_in(src: VBufRef<'_>, bitmap: &FlatBool<'_>, pool: &Arc<MemoryPool>) -> Result<VBuf, MemoryError> {
+    try_take_in(src, &Selection::from_bitmap(bitmap).indices, pool)
+}
+
+/// Rows of all buffers one after another, the buffers must share the same schema
+///
+/// Dictionaries are merged, their codes are widened when the merged dictionary doesn't fit.
+/// Panics if the allocation fails, see [`try_concat_in`].
+pub fn concat(srcs: &[VBufRef<'_>]) -> VBuf {
+    let Some(first) = srcs.first() else { panic!("cannot concat no buffers, the schema is unknown") };
+    concat_to(srcs, VBuf::new(merged_schema(first.schema, srcs))).unwrap_or_else(|e| panic!("{e}"))
+}
+
+/// Rows of all buffers one after another in a new vector buffer allocated in `pool`
+pub fn try_concat_in(srcs: &[VBufRef<'_>], pool: &Arc<MemoryPool>) -> Result<VBuf, MemoryError> {
+    let Some(first) = srcs.first() else { panic!("cannot concat no buffers, the schema is unknown") };
+    concat_to(srcs, VBuf::try_new_in(merged_schema(first.schema, srcs), pool)?)
+}
+
+fn concat_to(srcs: &[VBufRef<'_>], mut dst: VBuf) -> Result<VBuf, MemoryError> {
+    for src in srcs {
+        extend_into(*src, dst.as_mut())?;
+        dst.len += src.len;
+    }
+    Ok(dst)
+}
+
+// schemas that only differ in the code widths of dictionaries hold the same values
+fn same_values(a: DSchemaRef<'_>, b: DSchemaRef<'_>) -> bool {
+    a == b || match (a.decode(), b.decode()) {
+        (DSchemaEnum::DictStr(_), DSchemaEnum::DictStr(_)) => true,
+        (DSchemaEnum::Pair(_, a1, a0), DSchemaEnum::Pair(_, b1, b0)) => same_values(a0, b0) && same_values(a1, b1),
+        (DSchemaEnum::Field(x, a), DSchemaEnum::Field(y, b)) => x == y && same_values(a, b),
+        (DSchemaEnum::List(a), DSchemaEnum::List(b)) => same_values(a, b),
+        _ => false,
+    }
+}
+
+// the schema of `srcs` with codes wide enough for the merged dictionaries,
+// widths only change the dictionary nodes, so the rebuilt parents keep their sizes
+fn merged_schema(schema: DSchemaRef<'_>, srcs: &[VBufRef<'_>]) -> DSchema {
+    match schema.decode() {
+        DSchemaEnum::DictStr(width) => {
+            let dicts = srcs.iter().map(|x| DictStr::vector_cast(*x).dictionary()).collect::<Vec<_>>();
+            // the sum bounds the merged size, count distinct strings only when it doesn't fit
+            let mut entries = dicts.iter().map(|x| x.len()).sum::<usize>();
+            if entries > DictStr::capacity(width) {
+                entries = dicts.iter().flat_map(|x| x.iter()).collect::<std::collections::HashSet<_>>().len();
+            }
+            DictStr::with(width.max(DictStr::width_for(entries))).unwrap()
+        }
+        DSchemaEnum::Pair(_, snd, fst) => {
+            let (a, b) = srcs.iter().map(|x| x.pair()).unzip::<_, _, Vec<_>, Vec<_>>();
+            Pair::encode(&[merged_schema(fst, &a).as_ref(), merged_schema(snd, &b).as_ref()])
+        }
+        DSchemaEnum::Field(name, child) => {
+            Field::named(name, merged_schema(child, &srcs.iter().map(|x| x.field()).collect::<Vec<_>>()).as_ref())
+        }
+        DSchemaEnum::List(child) => {
+            List::encode(&[merged_schema(child, &srcs.iter().map(|x| List::vector_cast(*x).column()).collect::<Vec<_>>()).as_ref()])
+        }
+        _ => DSchema::from_ref(schema),
+    }
+}
+
+/// Selection vector, indices of rows that survive the filters applied so far
+///
+/// Filters only narrow the indices, the rows are copied once on [`Selection::materialize`].
+#[derive(Debug, Clone, PartialEq)]
+pub struct Selection {
+    pub indices: Vec<u32>,
+}
+
+impl Selection {
+    /// Select all rows
+    pub fn all(len: usize) -> Selection {
+        assert!(len <= u32::MAX as usize, "{len} rows cannot be indexed by u32");
+        Selection { indices: (0..len as u32).collect() }
+    }
+    /// Select rows whose bit is set
+    pub fn from_bitmap(bitmap: &FlatBool<'_>) -> Selection {
+        assert!(bitmap.len() <= u32::MAX as usize, "{} rows cannot be indexed by u32", bitmap.len());
+        Selection { indices: (0..bitmap.len() as u32).filter(|i| bitmap.get(*i as usize)).collect() }
+    }
+    pub fn len(&self) -> usize {
+        self.indices.len()
+    }
+    pub fn is_empty(&self) -> bool {
+        self.indices.is_empty()
+    }
+    /// Keep selected rows whose bit is set, the bitmap has one bit per row of the underlying buffer
+    pub fn filter(&mut self, bitmap: &FlatBool<'_>) {
+        self.indices.retain(|i| bitmap.get(*i as usize));
+    }
+    /// Keep selected rows whose bit is set, the bitmap has one bit per selected row
+    pub fn refine(&mut self, bitmap: &FlatBool<'_>) {
+        assert!(bitmap.len() == self.len(), "bitmap of {} bits cannot refine {} rows", bitmap.len(), self.len());
+        let mut k = 0;
+        self.indices.retain(|_| { k += 1; bitmap.get(k - 1) });
+    }
+    /// Keep selected rows that satisfy the predicate on the row index
+    pub fn retain(&mut self, mut f: impl FnMut(usize) -> bool) {
+        self.indices.retain(|i| f(*i as usize));
+    }
+    /// Read the i-th selected row of `src`
+    pub fn get<'a>(&self, src: VBufRef<'a>, i: usize) -> Value<'a> {
+        src.get(self.indices[i] as usize)
+    }
+    /// Copy the selected rows of `src` into a new vector buffer
+    pub fn materialize(&self, src: VBufRef<'_>) -> VBuf {
+        take(src, &self.indices)
+    }
+    /// Copy the selected rows of `src` into a new vector buffer allocated in `pool`
+    pub fn try_materialize_in(&self, src: VBufRef<'_>, pool: &Arc<MemoryPool>) -> Result<VBuf, MemoryError> {
+        try_take_in(src, &self.indices, pool)
+    }
+}
+
+/*                                    */
+/* Implementation for each data type  */
+/*                                    */
+
+macro_rules! ImplPrimitiveSelectKernel {($($X: ident: $Y: ty, )*) => {$(
+    impl SelectKernel<{Tag::$X as u8}> for $X {
+        fn take_into(src: VBufRef<'_>, indices: &[u32], dst: VBufMut<'_>) -> Result<(), MemoryError> {
+            let src = $X::vector_cast(src);
+            let out = indices.iter().map(|i| src[*i as usize]).collect::<Vec<$Y>>();
+            dst.buffer[dst.buffer.len()-1].try_extend(bytemuck::cast_slice(&out))
+        }
+        fn extend_into(src: VBufRef<'_>, dst: VBufMut<'_>) -> Result<(), MemoryError> {
+            dst.buffer[dst.buffer.len()-1].try_extend(bytemuck::cast_slice($X::vector_cast(src)))
+        }
+    }
+)*};}
+
+ImplPrimitiveSelectKernel! {
+    I64: i64, I32: i32, I16: i16, I8: i8,
+    U64: u64, U32: u32, U16: u16, U8: u8,
+    F64: f64, F32: f32,
+    Date: i32, Time: i64,
+    Timestamp: i64, TimestampTz: i64,
+    Interval: MonthDayMicros,
+    Decimal: i128,
+}
+
+impl SelectKernel<{Tag::Nil as u8}> for Nil {
+    fn take_into(_: VBufRef<'_>, indices: &[u32], dst: VBufMut<'_>) -> Result<(), MemoryError> {
+        dst.buffer[0].add_count(indices.len());
+        Ok(())
+    }
+    fn extend_into(src: VBufRef<'_>, dst: VBufMut<'_>) -> Result<(), MemoryError> {
+        dst.buffer[0].add_count(src.len);
+        Ok(())
+    }
+}
+
+impl SelectKernel<{Tag::Pad as u8}> for Pad {
+    fn take_into(_: VBufRef<'_>, _: &[u32], _: VBufMut<'_>) -> Result<(), MemoryError> {
+        unreachable!("pad never appears in a valid schema")
+    }
+    fn extend_into(_: VBufRef<'_>, _: VBufMut<'_>) -> Result<(), MemoryError> {
+        unreachable!("pad never appears in a valid schema")
+    }
+}
+
+impl SelectKernel<{Tag::Bool as u8}> for Bool {
+    fn take_into(src: VBufRef<'_>, indices: &[u32], mut dst: VBufMut<'_>) -> Result<(), MemoryError> {
+        let src = Bool::vector_cast(src);
+        for i in indices { Bool::vector_push(dst.reborrow(), &src.get(*i as usize))? }
+        Ok(())
+    }
+    fn extend_into(src: VBufRef<'_>, mut dst: VBufMut<'_>) -> Result<(), MemoryError> {
+        for b in Bool::vector_cast(src).iter() { Bool::vector_push(dst.reborrow(), &b)? }
+        Ok(())
+    }
+}
+
+// str and blob share the layout: bytes in column 0, offsets with a leading zero in column 1
+fn varlen_offsets<'a>(src: VBufRef<'a>) -> &'a [u64] {
+    let offset: &[u64] = if src.buffer[1].len() == 0 { &[0] } else { bytemuck::cast_slice(src.buffer[1].slice(..)) };
+    &offset[src.offset..=src.offset+src.len]
+}
+
+fn varlen_take_into(src: VBufRef<'_>, indices: &[u32], dst: VBufMut<'_>) -> Result<(), MemoryError> {
+    let offset = varlen_offsets(src);
+    let (data, ends) = dst.buffer.split_at_mut(1);
+    if ends[0].len() == 0 { ends[0].try_extend(&0u64.to_ne_bytes())? }
+    data[0].try_reserve(indices.iter().map(|i| (offset[*i as usize + 1] - offset[*i as usize]) as usize).sum())?;
+    ends[0].try_reserve(indices.len() * 8)?;
+    for i in indices {
+        let i = *i as usize;
+        data[0].try_extend(src.buffer[0].slice(offset[i] as usize..offset[i+1] as usize))?;
+        ends[0].try_extend(&(data[0].len() as u64).to_ne_bytes())?;
+    }
+    Ok(())
+}
+
+fn varlen_extend_into(src: VBufRef<'_>, dst: VBufMut<'_>) -> Result<(), MemoryError> {
+    let offset = varlen_offsets(src);
+    let (data, ends) = dst.buffer.split_at_mut(1);
+    if ends[0].len() == 0 { ends[0].try_extend(&0u64.to_ne_bytes())? }
+    // copy the bytes at once, and shift the offsets to the end of existing bytes
+    let (start, base) = (offset[0], data[0].len() as u64);
+    data[0].try_extend(src.buffer[0].slice(start as usize..offset[src.len] as usize))?;
+    let shifted = offset[1..].iter().map(|o| o - start + base).collect::<Vec<u64>>();
+    ends[0].try_extend(bytemuck::cast_slice(&shifted))
+}
+
+impl SelectKernel<{Tag::Str as u8}> for Str {
+    fn take_into(src: VBufRef<'_>, indices: &[u32], dst: VBufMut<'_>) -> Result<(), MemoryError> {
+        varlen_take_into(src, indices, dst)
+    }
+    fn extend_into(src: VBufRef<'_>, dst: VBufMut<'_>) -> Result<(), MemoryError> {
+        varlen_extend_into(src, dst)
+    }
+}
+
+impl SelectKernel<{Tag::Blob as u8}> for Blob {
+    fn take_into(src: VBufRef<'_>, indices: &[u32], dst: VBufMut<'_>) -> Result<(), MemoryError> {
+        varlen_take_into(src, indices, dst)
+    }
+    fn extend_into(src: VBufRef<'_>, dst: VBufMut<'_>) -> Result<(), MemoryError> {
+        varlen_extend_into(src, dst)
+    }
+}
+
+impl SelectKernel<{Tag::FixedBinary as u8}> for FixedBinary {
+    fn take_into(src: VBufRef<'_>, indices: &[u32], dst: VBufMut<'_>) -> Result<(), MemoryError> {
+        let src = FixedBinary::vector_cast(src);
+        let column = &mut dst.buffer[dst.buffer.len()-1];
+        column.try_reserve(indices.len() * src.width())?;
+        for i in indices { column.try_extend(src.get(*i as usize))? }
+        Ok(())
+    }
+    fn extend_into(src: VBufRef<'_>, dst: VBufMut<'_>) -> Result<(), MemoryError> {
+        let column = &mut dst.buffer[dst.buffer.len()-1];
+        for x in FixedBinary::vector_cast(src).iter() { column.try_extend(x)? }
+        Ok(())
+    }
+}
+
+// codes are copied as is when both sides share the dictionary (or `dst` has none yet and fits it),
+// otherwise each code used is mapped once to the dictionary of `dst`
+fn dict_append(src: FlatDict<'_>, codes: impl Iterator<Item = u32>, mut dst: VBufMut<'_>) -> Result<(), MemoryError> {
+    let dict = src.dictionary();
+    fn target<'a>(dst: &'a VBufMut<'_>) -> FlatStr<'a> {
+        DictStr::vector_cast(VBufRef { buffer: dst.buffer, schema: dst.schema, offset: 0, len: 0 }).dictionary()
+    }
+    let DSchemaEnum::DictStr(width) = dst.schema.decode() else { unreachable!() };
+    let fresh = target(&dst).is_empty() && dict.len() <= DictStr::capacity(width);
+    let shared = target(&dst).offsets() == dict.offsets() && target(&dst).values() == dict.values();
+    if fresh && !dict.is_empty() {
+        dst.buffer[0].try_extend(dict.values().as_bytes())?;
+        dst.buffer[1].try_extend(bytemuck::cast_slice(dict.offsets()))?;
+    }
+    if fresh || shared {
+        for code in codes { DictStr::push_code(dst.reborrow(), code)? }
+        return Ok(())
+    }
+    let mut index = target(&dst).iter().enumerate().map(|(i, x)| (x.to_string(), i as u32)).collect::<HashMap<_, _>>();
+    let mut remap = vec![u32::MAX; dict.len()];
+    for code in codes {
+        let code = code as usize;
+        if remap[code] == u32::MAX {
+            let x = dict.get(code);
+            remap[code] = match index.get(x) {
+                Some(y) => *y,
+                None => { let y = DictStr::insert(dst.reborrow(), x)?; index.insert(x.to_string(), y); y }
+            };
+        }
+        DictStr::push_code(dst.reborrow(), remap[code])?;
+    }
+    Ok(())
+}
+
+impl SelectKernel<{Tag::DictStr as u8}> for DictStr {
+    fn take_into(src: VBufRef<'_>, indices: &[u32], dst: VBufMut<'_>) -> Result<(), MemoryError> {
+        let src = DictStr::vector_cast(src);
+        dict_append(src, indices.iter().map(|i| src.codes().get(*i as usize)), dst)
+    }
+    fn extend_into(src: VBufRef<'_>, dst: VBufMut<'_>) -> Result<(), MemoryError> {
+        let src = DictStr::vector_cast(src);
+        dict_append(src, (0..src.len()).map(|i| src.codes().get(i)), dst)
+    }
+}
+
+// the elements of the selected lists are taken at once, then the lists are closed
+impl SelectKernel<{Tag::List as u8}> for List {
+    fn take_into(src: VBufRef<'_>, indices: &[u32], dst: VBufMut<'_>) -> Result<(), MemoryError> {
+        let src = List::vector_cast(src);
+        let rows = indices.iter().flat_map(|i| src.range(*i as usize)).collect::<Vec<_>>();
+        assert!(rows.last().is_none_or(|x| *x <= u32::MAX as usize), "elements cannot be indexed by u32");
+        let (values, ends) = List::columns_mut(dst)?;
+        take_into(src.column(), &rows.into_iter().map(|x| x as u32).collect::<Vec<_>>(), values)?;
+        List::push_lens(ends, indices.iter().map(|i| src.range(*i as usize).len()))
+    }
+    fn extend_into(src: VBufRef<'_>, dst: VBufMut<'_>) -> Result<(), MemoryError> {
+        let src = List::vector_cast(src);
+        let (values, ends) = List::columns_mut(dst)?;
+        extend_into(src.values(), values)?;
+        List::push_lens(ends, (0..src.len()).map(|i| src.range(i).len()))
+    }
+}
+
+impl SelectKernel<{Tag::Field as u8}> for Field {
+    fn take_into(src: VBufRef<'_>, indices: &[u32], dst: VBufMut<'_>) -> Result<(), MemoryError> {
+        take_into(src.field(), indices, dst.field())
+    }
+    fn extend_into(src: VBufRef<'_>, dst: VBufMut<'_>) -> Result<(), MemoryError> {
+        extend_into(src.field(), dst.field())
+    }
+}
+
+impl SelectKernel<{Tag::Pair as u8}> for Pair {
+    fn take_into(src: VBufRef<'_>, indices: &[u32], dst: VBufMut<'_>) -> Result<(), MemoryError> {
+        let ((a, b), (x, y)) = (src.pair(), dst.pair());
+        take_into(a, indices, x)?;
+        take_into(b, indices, y)
+    }
+    fn extend_into(src: VBufRef<'_>, dst: VBufMut<'_>) -> Result<(), MemoryError> {
+        let ((a, b), (x, y)) = (src.pair(), dst.pair());
+        extend_into(a, x)?;
+        extend_into(b, y)
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    // (a: i32, (s: str, (b: bool, (n: nil, d: decimal(10, 2)))))
+    fn schema() -> DSchema {
+        let d = Pair::encode(&[Field::named("n", Nil::encode(&[]).as_ref()).as_ref(), Field::named("d", Decimal::with(10, 2).unwrap().as_ref()).as_ref()]);
+        let b = Pair::encode(&[Field::named("b", Bool::encode(&[]).as_ref()).as_ref(), d.as_ref()]);
+        let s = Pair::encode(&[Field::named("s", Str::encode(&[]).as_ref()).as_ref(), b.as_ref()]);
+        Pair::encode(&[Field::named("a", I32::encode(&[]).as_ref()).as_ref(), s.as_ref()])
+    }
+
+    fn value(i: usize, strings: &[String]) -> Value<'_> {
+        Value::pair(
+            Value::field("a", Value::I32(i as i32)),
+            Value::pair(
+                Value::field("s", Value::Str(&strings[i])),
+                Value::pair(
+                    Value::field("b", Value::Bool(i.is_multiple_of(3))),
+                    Value::pair(Value::field("n", Value::Nil), Value::field("d", Value::Decimal(i as i128 * 7))),
+                ),
+            ),
+        )
+    }
+
+    fn columns(range: std::ops::Range<usize>, strings: &[String]) -> VBuf {
+        let mut columns = VBuf::new(schema());
+        for i in range { columns.push(&value(i, strings)) }
+        columns
+    }
+
+    // the bool column of the nested schema, as a bitmap
+    fn bitmap(columns: VBufRef<'_>) -> FlatBool<'_> {
+        let (_, rest) = columns.pair();
+        let (_, rest) = rest.pair();
+        Bool::vector_cast(rest.pair().0.field())
+    }
+
+    #[test]
+    fn take_filter() {
+        let strings = (0..100).map(|i| "s".repeat(i % 5) + &i.to_string()).collect::<Vec<_>>();
+        let columns = columns(0..100, &strings);
+        let indices = [99, 0, 42, 42, 7];
+        let taken = take(columns.as_ref(), &indices);
+        assert!(taken.len() == 5);
+        for (k, i) in indices.iter().enumerate() { assert!(taken.get(k) == value(*i as usize, &strings)) }
+        // take from a view, indices are relative to it
+        let taken = take(columns.as_ref().slice(40..), &[2, 0]);
+        assert!(taken.get(0) == value(42, &strings) && taken.get(1) == value(40, &strings));
+        let filtered = filter(columns.as_ref(), &bitmap(columns.as_ref()));
+        assert!(filtered.len() == 34);
+        for k in 0..34 { assert!(filtered.get(k) == value(k * 3, &strings)) }
+        assert!(take(columns.as_ref(), &[]).is_empty());
+    }
+
+    #[test]
+    fn concat_views() {
+        let strings = (0..60).map(|i| i.to_string()).collect::<Vec<_>>();
+        let (x, y) = (columns(0..25, &strings), columns(25..60, &strings));
+        let (head, tail) = y.as_ref().split_at(10);
+        let all = concat(&[x.as_ref(), head, tail.slice(..0), tail]);
+        assert!(all.len() == 60);
+        for i in 0..60 { assert!(all.get(i) == value(i, &strings)) }
+        // concat a concatenated buffer again
+        let twice = concat(&[all.as_ref().slice(50..), all.as_ref()]);
+        assert!(twice.len() == 70 && twice.get(9) == value(59, &strings) && twice.get(10) == value(0, &strings));
+    }
+
+    #[test]
+    fn lazy_selection() {
+        let strings = (0..100).map(|i| i.to_string()).collect::<Vec<_>>();
+        let columns = columns(0..100, &strings);
+        let mut selection = Selection::all(columns.len());
+        selection.filter(&bitmap(columns.as_ref()));
+        selection.retain(|i| i >= 50);
+        assert!(selection.len() == 17);
+        assert!(selection.get(columns.as_ref(), 0) == value(51, &strings));
+        // refine with a bitmap over the selected rows only
+        let mut odd = VBuf::new(Bool::encode(&[]));
+        for k in 0..selection.len() { odd.push(&Value::Bool(k % 2 == 1)) }
+        selection.refine(&Bool::vector_cast(odd.as_ref()));
+        assert!(selection.indices == [54, 60, 66, 72, 78, 84, 90, 96]);
+        let rows = selection.materialize(columns.as_ref());
+        for (k, i) in selection.indices.iter().enumerate() { assert!(rows.get(k) == value(*i as usize, &strings)) }
+    }
+
+    #[test]
+    fn lists() {
+        let list = |xs: &[i64]| Value::List(xs.iter().map(|x| Value::I64(*x)).collect());
+        let lists = [vec![1, 2], vec![], vec![3], vec![4, 5, 6]];
+        let mut columns = VBuf::new(List::encode(&[I64::encode(&[]).as_ref()]));
+        for x in &lists { columns.push(&list(x)) }
+        let taken = take(columns.as_ref().slice(1..), &[2, 0, 2]);
+        assert!((0..3).map(|i| taken.get(i)).eq([list(&[4, 5, 6]), list(&[]), list(&[4, 5, 6])]));
+        let all = concat(&[columns.as_ref().slice(2..), columns.as_ref().slice(..2)]);
+        assert!((0..4).map(|i| all.get(i)).eq([2, 3, 0, 1].map(|i| list(&lists[i]))));
+        assert!(List::vector_cast(all.as_ref()).offsets() == [0, 1, 4, 6, 6]);
+    }
+
+    #[test]
+    fn memory_pool() {
+        let strings = (0..100).map(|i| i.to_string()).collect::<Vec<_>>();
+        let columns = columns(0..100, &strings);
+        let pool = MemoryPool::new(1 << 16);
+        let taken = try_take_in(columns.as_ref(), &[5, 1], &pool).unwrap();
+        assert!(taken.get(0) == value(5, &strings) && taken.buffer.iter().all(|x| x.is_counter() || x.pool().is_some()));
+        let all = try_concat_in(&[columns.as_ref(), taken.as_ref()], &pool).unwrap();
+        assert!(all.len() == 102 && all.get(101) == value(1, &strings));
+        let filtered = try_filter_in(columns.as_ref(), &bitmap(columns.as_ref()), &pool).unwrap();
+        assert!(filtered.len() == 34 && filtered.get(1) == value(3, &strings));
+        drop((taken, all, filtered));
+        assert!(pool.used() == 0);
+        // the output doesn't fit in a small pool, nothing stays allocated
+        let pool = MemoryPool::new(1024);
+        assert!(matches!(try_concat_in(&[columns.as_ref()], &pool), Err(MemoryError::Limit(..))));
+        assert!(Selection::all(100).try_materialize_in(columns.as_ref(), &pool).is_err() && pool.used() == 0);
+    }
+}
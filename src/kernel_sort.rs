@@ -0,0 +1,322 @@
+use crate::data_schema::*;
+use crate::data_buffer::*;
+
+/*                                     */
+/* Sort kernels over normalized keys   */
+/*                                     */
+
+// Each row is encoded into a normalized key, so that comparing keys byte by byte (memcmp)
+// orders rows by all sort columns at once.
+// Every value starts with a marker byte: nulls sort before (0x00) or after (0x02) values (0x01).
+// Nil is the only null for now, nullable columns will reuse the marker.
+// Descending order inverts the bytes of each value but not its marker, so the position of nulls
+// doesn't depend on the direction.
+
+/// Order of one sort column
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub struct SortSpec {
+    pub descending: bool,
+    pub nulls_first: bool,
+}
+
+impl SortSpec {
+    pub fn asc() -> SortSpec {
+        SortSpec { descending: false, nulls_first: false }
+    }
+    pub fn desc() -> SortSpec {
+        SortSpec { descending: true, nulls_first: false }
+    }
+    pub fn nulls_first(self) -> SortSpec {
+        SortSpec { nulls_first: true, ..self }
+    }
+    pub fn nulls_last(self) -> SortSpec {
+        SortSpec { nulls_first: false, ..self }
+    }
+}
+
+const VALUE: u8 = 0x01;
+
+/// Append the normalized key of each row of a column to the key of the row
+pub trait SortKernel<const TAG: u8> {
+    fn encode_into(src: VBufRef<'_>, spec: SortSpec, keys: &mut [Vec<u8>]);
+}
+
+/// Append the normalized key of each row of `src` to `keys`, dispatched on the runtime schema
+pub fn encode_column(src: VBufRef<'_>, spec: SortSpec, keys: &mut [Vec<u8>]) {
+    assert!(src.len == keys.len(), "cannot encode {} rows into {} keys", src.len, keys.len());
+    macro_rules! Match {($($X: ident, )*) => {
+        match src.schema.tag() {
+            $($X::NUM => $X::encode_into(src, spec, keys), )*
+            _ => unreachable!()
+        }
+    };}
+    crate::Fill!{Match{<Here>}}
+}
+
+/// Normalized keys of rows across the columns
+pub fn normalized_keys(columns: &[VBufRef<'_>], specs: &[SortSpec]) -> Vec<Vec<u8>> {
+    assert!(columns.len() == specs.len(), "{} columns but {} sort specs", columns.len(), specs.len());
+    let Some(first) = columns.first() else { return vec![] };
+    let mut keys = vec![Vec::new(); first.len];
+    for (column, spec) in columns.iter().zip(specs) { encode_column(*column, *spec, &mut keys) }
+    keys
+}
+
+/// Row indices in sorted order, rows with equal keys come in any order
+pub fn sort_indices(columns: &[VBufRef<'_>], specs: &[SortSpec]) -> Vec<u32> {
+    let keys = normalized_keys(columns, specs);
+    assert!(keys.len() <= u32::MAX as usize, "{} rows cannot be indexed by u32", keys.len());
+    let mut indices = (0..keys.len() as u32).collect::<Vec<_>>();
+    indices.sort_unstable_by(|a, b| keys[*a as usize].cmp(&keys[*b as usize]));
+    indices
+}
+
+/// Row indices in sorted order, rows with equal keys keep their order
+pub fn sort_indices_stable(columns: &[VBufRef<'_>], specs: &[SortSpec]) -> Vec<u32> {
+    let keys = normalized_keys(columns, specs);
+    assert!(keys.len() <= u32::MAX as usize, "{} rows cannot be indexed by u32", keys.len());
+    let mut indices = (0..keys.len() as u32).collect::<Vec<_>>();
+    indices.sort_by(|a, b| keys[*a as usize].cmp(&keys[*b as usize]));
+    indices
+}
+
+// write the value marker and the value of each row, invert the value for descending order
+fn encode_values(keys: &mut [Vec<u8>], spec: SortSpec, mut put: impl FnMut(usize, &mut Vec<u8>)) {
+    for (i, key) in keys.iter_mut().enumerate() {
+        key.push(VALUE);
+        let start = key.len();
+        put(i, key);
+        if spec.descending { key[start..].iter_mut().for_each(|b| *b = !*b) }
+    }
+}
+
+/*                                    */
+/* Implementation for each data type  */
+/*                                    */
+
+// big endian with the sign bit flipped compares like the signed integer
+macro_rules! ImplIntegerSortKernel {($($X: ident: $Y: ty, $flip: expr, )*) => {$(
+    impl SortKernel<{Tag::$X as u8}> for $X {
+        fn encode_into(src: VBufRef<'_>, spec: SortSpec, keys: &mut [Vec<u8>]) {
+            let src = $X::vector_cast(src);
+            encode_values(keys, spec, |i, key| key.extend((src[i] ^ $flip).to_be_bytes()));
+        }
+    }
+)*};}
+
+ImplIntegerSortKernel! {
+    I64: i64, i64::MIN, I32: i32, i32::MIN, I16: i16, i16::MIN, I8: i8, i8::MIN,
+    U64: u64, 0, U32: u32, 0, U16: u16, 0, U8: u8, 0,
+    Date: i32, i32::MIN, Time: i64, i64::MIN,
+    Timestamp: i64, i64::MIN, TimestampTz: i64, i64::MIN,
+    Decimal: i128, i128::MIN,
+}
+
+// -0.0 is 0.0, NaN is larger than any other value; negative values have all bits inverted,
+// positive values only the sign bit, then the bits compare as unsigned integers
+macro_rules! ImplFloatSortKernel {($($X: ident: $Y: ty, $U: ty, )*) => {$(
+    impl SortKernel<{Tag::$X as u8}> for $X {
+        fn encode_into(src: VBufRef<'_>, spec: SortSpec, keys: &mut [Vec<u8>]) {
+            const SIGN: $U = 1 << (<$U>::BITS - 1);
+            let src = $X::vector_cast(src);
+            encode_values(keys, spec, |i, key| {
+                let x = if src[i] == 0.0 { 0.0 } else if src[i].is_nan() { <$Y>::NAN } else { src[i] };
+                let bits = x.to_bits();
+                key.extend((if bits & SIGN != 0 { !bits } else { bits ^ SIGN }).to_be_bytes())
+            });
+        }
+    }
+)*};}
+
+ImplFloatSortKernel! {
+    F64: f64, u64,
+    F32: f32, u32,
+}
+
+impl SortKernel<{Tag::Interval as u8}> for Interval {
+    fn encode_into(src: VBufRef<'_>, spec: SortSpec, keys: &mut [Vec<u8>]) {
+        let src = Interval::vector_cast(src);
+        encode_values(keys, spec, |i, key| {
+            key.extend((src[i].months ^ i32::MIN).to_be_bytes());
+            key.extend((src[i].days ^ i32::MIN).to_be_bytes());
+            key.extend((src[i].micros ^ i64::MIN).to_be_bytes());
+        });
+    }
+}
+
+impl SortKernel<{Tag::Bool as u8}> for Bool {
+    fn encode_into(src: VBufRef<'_>, spec: SortSpec, keys: &mut [Vec<u8>]) {
+        let src = Bool::vector_cast(src);
+        encode_values(keys, spec, |i, key| key.push(src.get(i) as u8));
+    }
+}
+
+impl SortKernel<{Tag::Nil as u8}> for Nil {
+    fn encode_into(_: VBufRef<'_>, spec: SortSpec, keys: &mut [Vec<u8>]) {
+        let null = if spec.nulls_first { VALUE - 1 } else { VALUE + 1 };
+        for key in keys.iter_mut() { key.push(null) }
+    }
+}
+
+impl SortKernel<{Tag::Pad as u8}> for Pad {
+    fn encode_into(_: VBufRef<'_>, _: SortSpec, _: &mut [Vec<u8>]) {
+        unreachable!("pad never appears in a valid schema")
+    }
+}
+
+// 0x00 is escaped as 0x00 0xff and the value ends with 0x00 0x00,
+// so no key is a prefix of another and a shorter value sorts before its extensions
+fn encode_bytes(key: &mut Vec<u8>, bytes: &[u8]) {
+    for b in bytes {
+        key.push(*b);
+        if *b == 0 { key.push(0xff) }
+    }
+    key.extend([0, 0]);
+}
+
+impl SortKernel<{Tag::Str as u8}> for Str {
+    fn encode_into(src: VBufRef<'_>, spec: SortSpec, keys: &mut [Vec<u8>]) {
+        let src = Str::vector_cast(src);
+        encode_values(keys, spec, |i, key| encode_bytes(key, src.get(i).as_bytes()));
+    }
+}
+
+impl SortKernel<{Tag::DictStr as u8}> for DictStr {
+    fn encode_into(src: VBufRef<'_>, spec: SortSpec, keys: &mut [Vec<u8>]) {
+        let src = DictStr::vector_cast(src);
+        encode_values(keys, spec, |i, key| encode_bytes(key, src.get(i).as_bytes()));
+    }
+}
+
+impl SortKernel<{Tag::Blob as u8}> for Blob {
+    fn encode_into(src: VBufRef<'_>, spec: SortSpec, keys: &mut [Vec<u8>]) {
+        let src = Blob::vector_cast(src);
+        encode_values(keys, spec, |i, key| encode_bytes(key, src.get(i)));
+    }
+}
+
+impl SortKernel<{Tag::FixedBinary as u8}> for FixedBinary {
+    fn encode_into(src: VBufRef<'_>, spec: SortSpec, keys: &mut [Vec<u8>]) {
+        let src = FixedBinary::vector_cast(src);
+        encode_values(keys, spec, |i, key| key.extend(src.get(i)));
+    }
+}
+
+// a list compares element by element: each element is marked with 0x01 and the list ends with 0x00,
+// so a shorter list sorts before its extensions; elements are encoded ascending, then inverted with the list
+impl SortKernel<{Tag::List as u8}> for List {
+    fn encode_into(src: VBufRef<'_>, spec: SortSpec, keys: &mut [Vec<u8>]) {
+        let src = List::vector_cast(src);
+        let values = src.values();
+        let mut elements = vec![Vec::new(); values.len];
+        encode_column(values, SortSpec { descending: false, ..spec }, &mut elements);
+        let base = src.offsets()[0] as usize;
+        encode_values(keys, spec, |i, key| {
+            let range = src.range(i);
+            for x in &elements[range.start - base..range.end - base] {
+                key.push(VALUE);
+                key.extend(x);
+            }
+            key.push(0);
+        });
+    }
+}
+
+impl SortKernel<{Tag::Field as u8}> for Field {
+    fn encode_into(src: VBufRef<'_>, spec: SortSpec, keys: &mut [Vec<u8>]) {
+        encode_column(src.field(), spec, keys)
+    }
+}
+
+// a pair sorts by its first element, then by its second element
+impl SortKernel<{Tag::Pair as u8}> for Pair {
+    fn encode_into(src: VBufRef<'_>, spec: SortSpec, keys: &mut [Vec<u8>]) {
+        let (a, b) = src.pair();
+        encode_column(a, spec, keys);
+        encode_column(b, spec, keys);
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::data_value::Value;
+    use rand::*;
+    use rand_xoshiro::*;
+
+    fn column(schema: DSchema, values: &[Value<'_>]) -> VBuf {
+        let mut column = VBuf::new(schema);
+        for v in values { column.push(v) }
+        column
+    }
+
+    #[test]
+    fn single_column() {
+        let xs = [3i64, -1, i64::MIN, 0, i64::MAX, -1];
+        let c = column(I64::encode(&[]), &xs.map(Value::I64));
+        let sorted = sort_indices(&[c.as_ref()], &[SortSpec::asc()]);
+        assert!(sorted.iter().map(|i| xs[*i as usize]).eq([i64::MIN, -1, -1, 0, 3, i64::MAX]));
+        let sorted = sort_indices(&[c.as_ref()], &[SortSpec::desc()]);
+        assert!(sorted.iter().map(|i| xs[*i as usize]).eq([i64::MAX, 3, 0, -1, -1, i64::MIN]));
+        let fs = [1.5f64, -0.0, f64::NAN, -2.0, f64::NEG_INFINITY, 0.0, f64::INFINITY];
+        let c = column(F64::encode(&[]), &fs.map(Value::F64));
+        let sorted = sort_indices_stable(&[c.as_ref()], &[SortSpec::asc()]);
+        assert!(sorted == [4, 3, 1, 5, 0, 6, 2]);
+        let words = ["b", "", "a\0", "a", "ab", "a\0\0"];
+        let c = column(Str::encode(&[]), &words.map(Value::Str));
+        let sorted = sort_indices(&[c.as_ref()], &[SortSpec::asc()]);
+        assert!(sorted.iter().map(|i| words[*i as usize]).eq(["", "a", "a\0", "a\0\0", "ab", "b"]));
+        let sorted = sort_indices(&[c.as_ref()], &[SortSpec::desc()]);
+        assert!(sorted.iter().map(|i| words[*i as usize]).eq(["b", "ab", "a\0\0", "a\0", "a", ""]));
+    }
+
+    #[test]
+    fn multiple_keys() {
+        // (a: u8 asc, b: str desc, c: decimal asc), compared with a sort on tuples
+        let mut rng = Xoroshiro128Plus::seed_from_u64(9900);
+        let rows = (0..500).map(|_| (rng.gen_range(0..4u8), format!("{}", rng.gen_range(0..5)), rng.gen_range(-3..3i128))).collect::<Vec<_>>();
+        let a = column(U8::encode(&[]), &rows.iter().map(|r| Value::U8(r.0)).collect::<Vec<_>>());
+        let b = column(Str::encode(&[]), &rows.iter().map(|r| Value::Str(&r.1)).collect::<Vec<_>>());
+        let c = column(Decimal::with(10, 2).unwrap(), &rows.iter().map(|r| Value::Decimal(r.2)).collect::<Vec<_>>());
+        let specs = [SortSpec::asc(), SortSpec::desc(), SortSpec::asc()];
+        let sorted = sort_indices_stable(&[a.as_ref(), b.as_ref(), c.as_ref()], &specs);
+        let mut expect = (0..500u32).collect::<Vec<_>>();
+        expect.sort_by(|x, y| {
+            let (x, y) = (&rows[*x as usize], &rows[*y as usize]);
+            x.0.cmp(&y.0).then(y.1.cmp(&x.1)).then(x.2.cmp(&y.2))
+        });
+        assert!(sorted == expect);
+        // the same keys nested in a pair, on a view of the rows
+        let schema = Pair::encode(&[U8::encode(&[]).as_ref(), Pair::encode(&[Str::encode(&[]).as_ref(), Bool::encode(&[]).as_ref()]).as_ref()]);
+        let pairs = column(schema, &rows.iter().map(|r| Value::pair(Value::U8(r.0), Value::pair(Value::Str(&r.1), Value::Bool(r.2 > 0)))).collect::<Vec<_>>());
+        let view = pairs.as_ref().slice(100..200);
+        let sorted = sort_indices(&[view], &[SortSpec::desc()]);
+        let key = |i: u32| { let r = &rows[100 + i as usize]; (r.0, r.1.clone(), r.2 > 0) };
+        assert!(sorted.windows(2).all(|w| key(w[0]) >= key(w[1])));
+    }
+
+    #[test]
+    fn nulls() {
+        // nil sorts before or after values in the same position, in either direction
+        let x = column(Pair::encode(&[I32::encode(&[]).as_ref(), I32::encode(&[]).as_ref()]), &[Value::pair(Value::I32(1), Value::I32(5))]);
+        let n = column(Pair::encode(&[I32::encode(&[]).as_ref(), Nil::encode(&[]).as_ref()]), &[Value::pair(Value::I32(1), Value::Nil)]);
+        for spec in [SortSpec::asc().nulls_first(), SortSpec::desc().nulls_first()] {
+            let (kx, kn) = (normalized_keys(&[x.as_ref()], &[spec]), normalized_keys(&[n.as_ref()], &[spec]));
+            assert!(kn < kx);
+            let spec = spec.nulls_last();
+            let (kx, kn) = (normalized_keys(&[x.as_ref()], &[spec]), normalized_keys(&[n.as_ref()], &[spec]));
+            assert!(kn > kx);
+        }
+    }
+
+    #[test]
+    fn lists() {
+        let lists = [vec![1, 2], vec![], vec![2], vec![0, 5], vec![1]];
+        let values = lists.iter().map(|xs| Value::List(xs.iter().map(|x| Value::I32(*x)).collect())).collect::<Vec<_>>();
+        let c = column(List::encode(&[I32::encode(&[]).as_ref()]), &values);
+        let sorted = sort_indices(&[c.as_ref()], &[SortSpec::asc()]);
+        assert!(sorted == [1, 3, 4, 0, 2]);
+        let sorted = sort_indices(&[c.as_ref().slice(1..)], &[SortSpec::desc()]);
+        assert!(sorted == [1, 3, 2, 0]);
+    }
+}
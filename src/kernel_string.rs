@@ -0,0 +1,329 @@
+use crate::data_schema::*;
+use crate::data_buffer::*;
+use crate::data_parser_string::{FlatStr, FlatStrBuilder};
+use crate::util_bytes::{MemoryError, MemoryPool};
+use std::sync::Arc;
+
+/*                                 */
+/* String function kernels         */
+/*                                 */
+
+// Kernels read a `FlatStr` and write a new column: str for functions on text,
+// i64 for lengths and bool for predicates. Positions and lengths count chars, like sql.
+// The column is allocated in `pool`, or by the global allocator without one.
+
+/// One operand of a string kernel, a column or a constant
+#[derive(Clone, Copy)]
+pub enum StrArg<'a> {
+    Column(FlatStr<'a>),
+    Scalar(&'a str),
+}
+
+impl<'a> StrArg<'a> {
+    fn get(&self, i: usize) -> &'a str {
+        match self {
+            StrArg::Column(s) => s.get(i),
+            StrArg::Scalar(s) => s,
+        }
+    }
+    fn len(&self) -> Option<usize> {
+        match self {
+            StrArg::Column(s) => Some(s.len()),
+            StrArg::Scalar(_) => None,
+        }
+    }
+}
+
+fn builder(len: usize, pool: Option<&Arc<MemoryPool>>) -> Result<FlatStrBuilder, MemoryError> {
+    match pool {
+        Some(pool) => FlatStrBuilder::try_with_capacity_in(len, 0, pool),
+        None => Ok(FlatStrBuilder::with_capacity(len, 0)),
+    }
+}
+
+// write one string per row, `f` appends the output of a row to a reused buffer
+fn map_str(s: FlatStr<'_>, pool: Option<&Arc<MemoryPool>>, mut f: impl FnMut(&str, &mut String)) -> Result<VBuf, MemoryError> {
+    let mut builder = builder(s.len(), pool)?;
+    let mut out = String::new();
+    for x in s.iter() {
+        out.clear();
+        f(x, &mut out);
+        builder.try_push(&out)?;
+    }
+    Ok(builder.finish())
+}
+
+fn map_i64(s: FlatStr<'_>, pool: Option<&Arc<MemoryPool>>, f: impl Fn(&str) -> i64) -> Result<VBuf, MemoryError> {
+    let out = s.iter().map(f).collect::<Vec<i64>>();
+    let mut column = match pool { Some(pool) => VBuf::try_new_in(I64::encode(&[]), pool)?, None => VBuf::new(I64::encode(&[])) };
+    column.buffer[0].try_extend(bytemuck::cast_slice(&out))?;
+    column.len = out.len();
+    Ok(column)
+}
+
+fn map_bool(s: FlatStr<'_>, pool: Option<&Arc<MemoryPool>>, f: impl FnMut(&str) -> bool) -> Result<VBuf, MemoryError> {
+    match pool {
+        Some(pool) => Bool::try_column_in(s.iter().map(f), pool),
+        None => Ok(Bool::column(s.iter().map(f))),
+    }
+}
+
+pub fn upper(s: FlatStr<'_>, pool: Option<&Arc<MemoryPool>>) -> Result<VBuf, MemoryError> {
+    map_str(s, pool, |x, out| if x.is_ascii() {
+        out.push_str(x);
+        out.make_ascii_uppercase();
+    } else {
+        out.extend(x.chars().flat_map(char::to_uppercase));
+    })
+}
+
+pub fn lower(s: FlatStr<'_>, pool: Option<&Arc<MemoryPool>>) -> Result<VBuf, MemoryError> {
+    map_str(s, pool, |x, out| if x.is_ascii() {
+        out.push_str(x);
+        out.make_ascii_lowercase();
+    } else {
+        out.extend(x.chars().flat_map(char::to_lowercase));
+    })
+}
+
+/// Chars from position `start` (counting from 1), at most `len` of them
+///
+/// Positions before 1 are cut off, so `substring('hello', 0, 3)` is `'he'`.
+pub fn substring(s: FlatStr<'_>, start: i64, len: Option<i64>, pool: Option<&Arc<MemoryPool>>) -> Result<VBuf, MemoryError> {
+    let end = len.map(|len| start.saturating_add(len.max(0)));
+    map_str(s, pool, |x, out| {
+        let (lo, hi) = (start.max(1) - 1, end.map(|end| end.max(1) - 1));
+        let chars = x.chars().skip(lo as usize);
+        match hi {
+            Some(hi) => out.extend(chars.take((hi - lo).max(0) as usize)),
+            None => out.extend(chars),
+        }
+    })
+}
+
+/// Number of bytes of each string
+pub fn byte_length(s: FlatStr<'_>, pool: Option<&Arc<MemoryPool>>) -> Result<VBuf, MemoryError> {
+    map_i64(s, pool, |x| x.len() as i64)
+}
+
+/// Number of chars of each string
+pub fn char_length(s: FlatStr<'_>, pool: Option<&Arc<MemoryPool>>) -> Result<VBuf, MemoryError> {
+    map_i64(s, pool, |x| if x.is_ascii() { x.len() as i64 } else { x.chars().count() as i64 })
+}
+
+/// Remove any of `chars` from both ends
+pub fn trim(s: FlatStr<'_>, chars: &str, pool: Option<&Arc<MemoryPool>>) -> Result<VBuf, MemoryError> {
+    map_str(s, pool, |x, out| out.push_str(x.trim_matches(|c| chars.contains(c))))
+}
+
+/// Concatenate strings row by row, at least one operand is a column
+pub fn concat(a: StrArg<'_>, b: StrArg<'_>, pool: Option<&Arc<MemoryPool>>) -> Result<VBuf, MemoryError> {
+    let len = match (a.len(), b.len()) {
+        (Some(x), Some(y)) => { assert!(x == y, "cannot concat {x} rows with {y} rows"); x }
+        (Some(x), None) | (None, Some(x)) => x,
+        (None, None) => panic!("concat of two constants is a constant, not a column"),
+    };
+    let mut builder = builder(len, pool)?;
+    let mut out = String::new();
+    for i in 0..len {
+        out.clear();
+        out.push_str(a.get(i));
+        out.push_str(b.get(i));
+        builder.try_push(&out)?;
+    }
+    Ok(builder.finish())
+}
+
+/// Replace all occurrences of `from` with `to`, an empty `from` replaces nothing
+pub fn replace(s: FlatStr<'_>, from: &str, to: &str, pool: Option<&Arc<MemoryPool>>) -> Result<VBuf, MemoryError> {
+    map_str(s, pool, |x, out| if from.is_empty() { out.push_str(x) } else {
+        let mut last = 0;
+        for (i, _) in x.match_indices(from) {
+            out.push_str(&x[last..i]);
+            out.push_str(to);
+            last = i + from.len();
+        }
+        out.push_str(&x[last..]);
+    })
+}
+
+pub fn starts_with(s: FlatStr<'_>, prefix: &str, pool: Option<&Arc<MemoryPool>>) -> Result<VBuf, MemoryError> {
+    map_bool(s, pool, |x| x.starts_with(prefix))
+}
+
+pub fn ends_with(s: FlatStr<'_>, suffix: &str, pool: Option<&Arc<MemoryPool>>) -> Result<VBuf, MemoryError> {
+    map_bool(s, pool, |x| x.ends_with(suffix))
+}
+
+pub fn contains(s: FlatStr<'_>, needle: &str, pool: Option<&Arc<MemoryPool>>) -> Result<VBuf, MemoryError> {
+    map_bool(s, pool, |x| x.contains(needle))
+}
+
+/*                           */
+/* LIKE pattern matching     */
+/*                           */
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum LikeToken {
+    Char(char),
+    // '_'
+    One,
+    // '%'
+    Many,
+}
+
+/// A compiled LIKE pattern
+///
+/// `%` matches any chars, `_` matches one char and `\` escapes the next char.
+/// Patterns of a literal with `%` only at the ends are matched as prefix, suffix or substring.
+#[derive(Debug, Clone, PartialEq)]
+pub enum LikePattern {
+    Exact(String),
+    Prefix(String),
+    Suffix(String),
+    Contains(String),
+    General(Vec<LikeToken>),
+}
+
+impl LikePattern {
+    pub fn new(pattern: &str) -> LikePattern {
+        let mut tokens = vec![];
+        let mut chars = pattern.chars();
+        while let Some(c) = chars.next() {
+            tokens.push(match c {
+                '\\' => LikeToken::Char(chars.next().unwrap_or('\\')),
+                '_' => LikeToken::One,
+                // consecutive '%' are the same as one
+                '%' if tokens.last() == Some(&LikeToken::Many) => continue,
+                '%' => LikeToken::Many,
+                c => LikeToken::Char(c),
+            });
+        }
+        let literal = |tokens: &[LikeToken]| tokens.iter().map(|t| match t {
+            LikeToken::Char(c) => Some(*c),
+            _ => None,
+        }).collect::<Option<String>>();
+        let (head, tail) = (tokens.first() == Some(&LikeToken::Many), tokens.len() > 1 && tokens.last() == Some(&LikeToken::Many));
+        let body = &tokens[head as usize..tokens.len() - tail as usize];
+        match (head, tail, literal(body)) {
+            (false, false, Some(s)) => LikePattern::Exact(s),
+            (false, true, Some(s)) => LikePattern::Prefix(s),
+            (true, false, Some(s)) => LikePattern::Suffix(s),
+            (true, true, Some(s)) => LikePattern::Contains(s),
+            _ => LikePattern::General(tokens),
+        }
+    }
+    pub fn matches(&self, x: &str) -> bool {
+        match self {
+            LikePattern::Exact(s) => x == s,
+            LikePattern::Prefix(s) => x.starts_with(s.as_str()),
+            LikePattern::Suffix(s) => x.ends_with(s.as_str()),
+            LikePattern::Contains(s) => x.contains(s.as_str()),
+            LikePattern::General(tokens) => like(tokens, x),
+        }
+    }
+}
+
+// greedy wildcard matching: on mismatch, let the last '%' take one more char and retry
+fn like(tokens: &[LikeToken], x: &str) -> bool {
+    let x = x.chars().collect::<Vec<_>>();
+    let (mut p, mut i) = (0, 0);
+    let mut star = None;
+    while i < x.len() {
+        match tokens.get(p) {
+            Some(LikeToken::Char(c)) if *c == x[i] => { p += 1; i += 1 }
+            Some(LikeToken::One) => { p += 1; i += 1 }
+            Some(LikeToken::Many) => { star = Some((p, i)); p += 1 }
+            _ => match star {
+                Some((sp, si)) => { star = Some((sp, si + 1)); p = sp + 1; i = si + 1 }
+                None => return false,
+            },
+        }
+    }
+    tokens[p..].iter().all(|t| *t == LikeToken::Many)
+}
+
+/// `s LIKE pattern`
+pub fn like_match(s: FlatStr<'_>, pattern: &str, pool: Option<&Arc<MemoryPool>>) -> Result<VBuf, MemoryError> {
+    let pattern = LikePattern::new(pattern);
+    map_bool(s, pool, |x| pattern.matches(x))
+}
+
+/// `s ILIKE pattern`, which ignores case
+pub fn ilike_match(s: FlatStr<'_>, pattern: &str, pool: Option<&Arc<MemoryPool>>) -> Result<VBuf, MemoryError> {
+    let pattern = LikePattern::new(&pattern.to_lowercase());
+    let mut lower = String::new();
+    map_bool(s, pool, |x| {
+        lower.clear();
+        lower.extend(x.chars().flat_map(char::to_lowercase));
+        pattern.matches(&lower)
+    })
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    fn strs(column: &VBuf) -> Vec<&str> {
+        Str::vector_cast(column.as_ref()).iter().collect()
+    }
+
+    fn bools(column: &VBuf) -> Vec<bool> {
+        Bool::vector_cast(column.as_ref()).iter().collect()
+    }
+
+    #[test]
+    fn text_functions() {
+        let column = ["Hello", "  wörld ", "", "ß ok"].into_iter().collect::<FlatStrBuilder>().finish();
+        let s = Str::vector_cast(column.as_ref());
+        assert!(strs(&upper(s, None).unwrap()) == ["HELLO", "  WÖRLD ", "", "SS OK"]);
+        assert!(strs(&lower(s, None).unwrap()) == ["hello", "  wörld ", "", "ß ok"]);
+        assert!(strs(&substring(s, 2, Some(3), None).unwrap()) == ["ell", " wö", "", " ok"]);
+        assert!(strs(&substring(s, 0, Some(3), None).unwrap()) == ["He", "  ", "", "ß "]);
+        assert!(strs(&substring(s, 4, None, None).unwrap()) == ["lo", "örld ", "", "k"]);
+        assert!(strs(&substring(s, 2, Some(-1), None).unwrap()) == ["", "", "", ""]);
+        assert!(I64::vector_cast(byte_length(s, None).unwrap().as_ref()) == [5, 9, 0, 5]);
+        assert!(I64::vector_cast(char_length(s, None).unwrap().as_ref()) == [5, 8, 0, 4]);
+        assert!(strs(&trim(s, " ", None).unwrap()) == ["Hello", "wörld", "", "ß ok"]);
+        assert!(strs(&trim(s, " Hdo", None).unwrap()) == ["ell", "wörl", "", "ß ok"]);
+        assert!(strs(&replace(s, "l", "L", None).unwrap()) == ["HeLLo", "  wörLd ", "", "ß ok"]);
+        assert!(strs(&replace(s, "", "x", None).unwrap()) == strs(&column));
+        assert!(strs(&concat(StrArg::Column(s), StrArg::Scalar("!"), None).unwrap()) == ["Hello!", "  wörld !", "!", "ß ok!"]);
+        assert!(strs(&concat(StrArg::Scalar(">"), StrArg::Column(s.slice(2..)), None).unwrap()) == [">", ">ß ok"]);
+        assert!(strs(&concat(StrArg::Column(s), StrArg::Column(s), None).unwrap())[0] == "HelloHello");
+        assert!(bools(&starts_with(s, "He", None).unwrap()) == [true, false, false, false]);
+        assert!(bools(&ends_with(s, "ok", None).unwrap()) == [false, false, false, true]);
+        assert!(bools(&contains(s, "", None).unwrap()) == [true; 4]);
+        // the columns are allocated in the pool of the caller
+        let pool = MemoryPool::new(4096);
+        let upper = upper(s, Some(&pool)).unwrap();
+        assert!(upper.buffer[0].pool().is_some() && pool.used() > 0);
+        let long = "x".repeat(1000);
+        assert!(matches!(concat(StrArg::Column(s), StrArg::Scalar(&long), Some(&pool)), Err(MemoryError::Limit(..))));
+        assert!(matches!(starts_with(s, "He", Some(&pool)), Ok(x) if x.buffer[0].pool().is_some()));
+        drop(upper);
+        assert!(pool.used() == 0);
+    }
+
+    #[test]
+    fn like_patterns() {
+        assert!(LikePattern::new("abc") == LikePattern::Exact("abc".into()));
+        assert!(LikePattern::new("abc%%") == LikePattern::Prefix("abc".into()));
+        assert!(LikePattern::new("%abc") == LikePattern::Suffix("abc".into()));
+        assert!(LikePattern::new("%a\\%c%") == LikePattern::Contains("a%c".into()));
+        assert!(LikePattern::new("%").matches("") && LikePattern::new("%%").matches("abc"));
+        let cases = [
+            ("a_c", "abc", true), ("a_c", "ac", false), ("a%c", "ac", true),
+            ("a%c", "abcbd", false), ("%b%d", "abcbd", true), ("_", "ö", true),
+            ("%_%_", "a", false), ("a%b%c", "aXbYbZc", true), ("a\\_c", "abc", false),
+        ];
+        for (pattern, x, expect) in cases {
+            assert!(LikePattern::new(pattern).matches(x) == expect, "{x} LIKE {pattern}");
+        }
+        let column = ["ERROR: disk full", "warn: retry", "error: timeout", "info"].into_iter().collect::<FlatStrBuilder>().finish();
+        let s = Str::vector_cast(column.as_ref());
+        assert!(bools(&like_match(s, "ERROR:%", None).unwrap()) == [true, false, false, false]);
+        assert!(bools(&ilike_match(s, "error:%", None).unwrap()) == [true, false, true, false]);
+        assert!(bools(&ilike_match(s, "%R_TRY", None).unwrap()) == [false, true, false, false]);
+    }
+}
@@ -4,6 +4,8 @@
 mod util_bytes;
 mod util_logging;
 mod util_pratt_parser;
+mod util_datetime;
+mod util_flatbuffers;
 
 // sql modules
 mod sql_parser_expr;
@@ -13,6 +15,7 @@ mod sql_schema;
 mod sql_planner;
 mod sql_compiler;
 mod sql_error;
+mod sql_function;
 
 // runtime modules
 mod rt_transaction;
@@ -26,16 +29,34 @@ mod data_parser_primitive;
 mod data_parser_string;
 mod data_parser_list;
 mod data_parser_pair;
+mod data_parser_field;
+mod data_parser_bool;
+mod data_parser_decimal;
+mod data_parser_binary;
+mod data_parser_dict;
+mod data_encoding;
+mod data_arrow;
+mod data_value;
+
+// vectorized kernel modules
+mod kernel_select;
+mod kernel_primitive;
+mod kernel_hash;
+mod kernel_sort;
+mod kernel_string;
+mod kernel_dict;
 
 // file modules
 mod storage_parquet;
 mod storage_in_memory;
+mod storage_arrow;
 
 // physical op modules (sub-op in some literatures)
 mod op_collect_hashmap;
 
 
-#[cfg(test)]
+// miri cannot run the constructor, tests then log nothing
+#[cfg(all(test, not(miri)))]
 #[ctor::ctor]
 fn init() {
     crate::util_logging::init();
@@ -8,6 +8,11 @@ pub enum SQLError<'a> {
     Visited,
     Merge(&'a SQLError<'a>, &'a SQLError<'a>),
     CannotFindIdent(usize),
+    MismatchSchema(&'a str /* expected */),
+    CannotFindNumber(usize),
+    CannotFindString(usize),
+    InvalidLiteral(usize /* offset */, &'a str /* expected */),
+    SchemaTooLarge,
 }
 
 impl<'a> MergeIn<SQLSpace<'a>> for SQLError<'a> {
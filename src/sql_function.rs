@@ -0,0 +1,169 @@
+use thiserror::Error;
+use crate::data_schema::*;
+use crate::data_buffer::*;
+use crate::data_parser_string::FlatStr;
+use crate::kernel_string::{self, StrArg};
+use crate::util_bytes::{MemoryError, MemoryPool};
+use std::sync::Arc;
+
+/*                                 */
+/* Registry of scalar sql functions */
+/*                                 */
+
+// The compiler looks functions up by name and calls them on evaluated arguments.
+// A function takes at least one column, constants stay unevaluated literals.
+
+/// An evaluated argument of a sql function
+#[derive(Clone, Copy)]
+pub enum SQLArg<'a> {
+    Column(VBufRef<'a>),
+    Str(&'a str),
+    Int(i64),
+}
+
+#[derive(Debug, Error, PartialEq, Eq)]
+pub enum SQLFunctionError {
+    #[error("unknown function {0}")]
+    Unknown(String),
+    #[error("{name} takes {min}..={max} arguments, got {got}")]
+    ArgumentCount { name: &'static str, min: usize, max: usize, got: usize },
+    #[error("argument {at} of {name} should be {expected}")]
+    ArgumentType { name: &'static str, at: usize, expected: &'static str },
+    #[error(transparent)]
+    Memory(#[from] MemoryError),
+}
+
+pub struct SQLFunction {
+    pub name: &'static str,
+    pub min_args: usize,
+    pub max_args: usize,
+    eval: fn(&Args<'_, '_>) -> Result<VBuf, SQLFunctionError>,
+}
+
+impl SQLFunction {
+    pub fn call(&self, args: &[SQLArg<'_>]) -> Result<VBuf, SQLFunctionError> {
+        self.call_to(args, None)
+    }
+    /// Call the function, its output column is allocated in `pool`
+    pub fn try_call_in(&self, args: &[SQLArg<'_>], pool: &Arc<MemoryPool>) -> Result<VBuf, SQLFunctionError> {
+        self.call_to(args, Some(pool))
+    }
+    fn call_to(&self, args: &[SQLArg<'_>], pool: Option<&Arc<MemoryPool>>) -> Result<VBuf, SQLFunctionError> {
+        let (name, min, max, got) = (self.name, self.min_args, self.max_args, args.len());
+        if got < min || got > max { return Err(SQLFunctionError::ArgumentCount { name, min, max, got }) }
+        (self.eval)(&Args { name, args, pool })
+    }
+}
+
+// checked access to the arguments of a call
+struct Args<'a, 'b> {
+    name: &'static str,
+    args: &'b [SQLArg<'a>],
+    pool: Option<&'b Arc<MemoryPool>>,
+}
+
+impl<'a> Args<'a, '_> {
+    fn error(&self, at: usize, expected: &'static str) -> SQLFunctionError {
+        SQLFunctionError::ArgumentType { name: self.name, at, expected }
+    }
+    fn str_column(&self, at: usize) -> Result<FlatStr<'a>, SQLFunctionError> {
+        match self.args[at] {
+            SQLArg::Column(c) if c.schema.tag() == Str::NUM => Ok(Str::vector_cast(c)),
+            _ => Err(self.error(at, "a str column")),
+        }
+    }
+    fn str(&self, at: usize) -> Result<&'a str, SQLFunctionError> {
+        match self.args[at] {
+            SQLArg::Str(s) => Ok(s),
+            _ => Err(self.error(at, "a str constant")),
+        }
+    }
+    fn int(&self, at: usize) -> Result<i64, SQLFunctionError> {
+        match self.args[at] {
+            SQLArg::Int(x) => Ok(x),
+            _ => Err(self.error(at, "an int constant")),
+        }
+    }
+    fn str_arg(&self, at: usize) -> Result<StrArg<'a>, SQLFunctionError> {
+        match self.args[at] {
+            SQLArg::Str(s) => Ok(StrArg::Scalar(s)),
+            _ => self.str_column(at).map(StrArg::Column),
+        }
+    }
+    fn get(&self, at: usize) -> Option<&SQLArg<'a>> {
+        self.args.get(at)
+    }
+}
+
+macro_rules! SQLFunctions {($($name: literal ($min: literal, $max: literal) => |$args: ident| $eval: expr, )*) => {
+    static SQL_FUNCTIONS: &[SQLFunction] = &[$(
+        SQLFunction { name: $name, min_args: $min, max_args: $max, eval: |$args| $eval },
+    )*];
+};}
+
+SQLFunctions! {
+    "upper" (1, 1) => |a| Ok(kernel_string::upper(a.str_column(0)?, a.pool)?),
+    "lower" (1, 1) => |a| Ok(kernel_string::lower(a.str_column(0)?, a.pool)?),
+    "substring" (2, 3) => |a| Ok(kernel_string::substring(
+        a.str_column(0)?, a.int(1)?, a.get(2).map(|_| a.int(2)).transpose()?, a.pool)?),
+    "length" (1, 1) => |a| Ok(kernel_string::char_length(a.str_column(0)?, a.pool)?),
+    "char_length" (1, 1) => |a| Ok(kernel_string::char_length(a.str_column(0)?, a.pool)?),
+    "octet_length" (1, 1) => |a| Ok(kernel_string::byte_length(a.str_column(0)?, a.pool)?),
+    "trim" (1, 2) => |a| Ok(kernel_string::trim(
+        a.str_column(0)?, a.get(1).map(|_| a.str(1)).transpose()?.unwrap_or(" "), a.pool)?),
+    "concat" (2, 2) => |a| match (a.str_arg(0)?, a.str_arg(1)?) {
+        (StrArg::Scalar(_), StrArg::Scalar(_)) => Err(a.error(0, "a str column")),
+        (StrArg::Column(x), StrArg::Column(y)) if x.len() != y.len() => Err(a.error(1, "a str column as long as the first")),
+        (x, y) => Ok(kernel_string::concat(x, y, a.pool)?),
+    },
+    "replace" (3, 3) => |a| Ok(kernel_string::replace(a.str_column(0)?, a.str(1)?, a.str(2)?, a.pool)?),
+    "starts_with" (2, 2) => |a| Ok(kernel_string::starts_with(a.str_column(0)?, a.str(1)?, a.pool)?),
+    "ends_with" (2, 2) => |a| Ok(kernel_string::ends_with(a.str_column(0)?, a.str(1)?, a.pool)?),
+    "contains" (2, 2) => |a| Ok(kernel_string::contains(a.str_column(0)?, a.str(1)?, a.pool)?),
+    "like" (2, 2) => |a| Ok(kernel_string::like_match(a.str_column(0)?, a.str(1)?, a.pool)?),
+    "ilike" (2, 2) => |a| Ok(kernel_string::ilike_match(a.str_column(0)?, a.str(1)?, a.pool)?),
+}
+
+/// Find a function by name, ignoring case
+pub fn lookup(name: &str) -> Result<&'static SQLFunction, SQLFunctionError> {
+    SQL_FUNCTIONS.iter()
+        .find(|f| f.name.eq_ignore_ascii_case(name))
+        .ok_or_else(|| SQLFunctionError::Unknown(name.to_string()))
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::data_parser_string::FlatStrBuilder;
+
+    #[test]
+    fn call_by_name() {
+        let column = ["Hello", " World "].into_iter().collect::<FlatStrBuilder>().finish();
+        let c = SQLArg::Column(column.as_ref());
+        let strs = |x: VBuf| Str::vector_cast(x.as_ref()).iter().map(String::from).collect::<Vec<_>>();
+        assert!(strs(lookup("UPPER").unwrap().call(&[c]).unwrap()) == ["HELLO", " WORLD "]);
+        assert!(strs(lookup("substring").unwrap().call(&[c, SQLArg::Int(2)]).unwrap()) == ["ello", "World "]);
+        assert!(strs(lookup("trim").unwrap().call(&[c]).unwrap()) == ["Hello", "World"]);
+        assert!(strs(lookup("concat").unwrap().call(&[SQLArg::Str("<"), c]).unwrap()) == ["<Hello", "< World "]);
+        let length = lookup("length").unwrap().call(&[c]).unwrap();
+        assert!(I64::vector_cast(length.as_ref()) == [5, 7]);
+        let like = lookup("ilike").unwrap().call(&[c, SQLArg::Str("%world%")]).unwrap();
+        assert!(Bool::vector_cast(like.as_ref()).iter().eq([false, true]));
+        // errors name the function and the argument
+        assert!(lookup("nope").err() == Some(SQLFunctionError::Unknown("nope".into())));
+        assert!(lookup("replace").unwrap().call(&[c]).err() == Some(SQLFunctionError::ArgumentCount { name: "replace", min: 3, max: 3, got: 1 }));
+        assert!(lookup("like").unwrap().call(&[c, SQLArg::Int(1)]).err() == Some(SQLFunctionError::ArgumentType { name: "like", at: 1, expected: "a str constant" }));
+        assert!(lookup("concat").unwrap().call(&[SQLArg::Str("a"), SQLArg::Str("b")]).is_err());
+        let short = ["a"].into_iter().collect::<FlatStrBuilder>().finish();
+        let concat = lookup("concat").unwrap().call(&[c, SQLArg::Column(short.as_ref())]);
+        assert!(concat.err() == Some(SQLFunctionError::ArgumentType { name: "concat", at: 1, expected: "a str column as long as the first" }));
+        let ints = VBuf::new(I64::encode(&[]));
+        assert!(lookup("upper").unwrap().call(&[SQLArg::Column(ints.as_ref())]).is_err());
+        // the output column is allocated in the pool of the caller
+        let pool = MemoryPool::new(1024);
+        let upper = lookup("upper").unwrap().try_call_in(&[c], &pool).unwrap();
+        assert!(strs(upper) == ["HELLO", " WORLD "] && pool.used() == 0);
+        let upper = lookup("upper").unwrap().try_call_in(&[c], &MemoryPool::new(16));
+        assert!(matches!(upper, Err(SQLFunctionError::Memory(MemoryError::Limit(..)))));
+    }
+}
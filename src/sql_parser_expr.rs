@@ -1,6 +1,8 @@
-use crate::sql_schema::*;
+use crate::{sql_error::SQLError, sql_parser_space::SQLSpace, sql_schema::*, util_pratt_parser::*};
+use crate::util_datetime::{self, MonthDayMicros};
 
 // In general, only expressions get compiled to physical operators
+#[derive(Debug, Clone)]
 pub enum SQLExpr<'a> {
     // SELECT * FROM <table> WHERE <filter>
     Select {
@@ -35,9 +37,108 @@ pub enum SQLExpr<'a> {
     // <float>
     Float {
         number: f64
-    }
+    },
+    // TRUE | FALSE
+    Boolean {
+        value: bool
+    },
+    // DATE '<yyyy-mm-dd>'
+    Date {
+        days: i32
+    },
+    // TIME '<hh:mm:ss>'
+    Time {
+        micros: i64
+    },
+    // TIMESTAMP '<yyyy-mm-dd hh:mm:ss>'
+    Timestamp {
+        micros: i64
+    },
+    // TIMESTAMPTZ '<yyyy-mm-dd hh:mm:ss+hh:mm>'
+    TimestampTz {
+        micros: i64
+    },
+    // INTERVAL '<n> <unit> ...'
+    Interval {
+        interval: MonthDayMicros
+    },
 }
 
 // JOIN Direction
+#[derive(Debug, Clone)]
 pub enum SQLJoinMethod {
+}
+
+type SQLTag<'a, P> = Tag<SQLExpr<'a>, SQLError<'a>, SQLSpace<'a>, P>;
+type SQLDyn<'a> = Box<dyn Parser<SQLExpr<'a>, SQLError<'a>, SQLSpace<'a>>>;
+
+// '<string>', where '' escapes a single quote
+#[derive(Debug, Clone, Copy)]
+pub struct SQLQuoted;
+
+impl<'a> Parser<&'a str, SQLError<'a>, SQLSpace<'a>> for SQLQuoted {
+    fn parse(&self, input: &str, progress: usize, extra: &mut SQLSpace<'a>) -> Result<(usize, &'a str), (usize, SQLError<'a>)> {
+        let Some(rest) = input[progress..].strip_prefix('\'') else {
+            return Err((progress, SQLError::CannotFindString(progress)))
+        };
+        let mut string = bumpalo::collections::String::new_in(extra.bump);
+        let mut chars = rest.char_indices().peekable();
+        while let Some((i, c)) = chars.next() {
+            if c != '\'' { string.push(c); continue }
+            if chars.next_if(|(_, c)| *c == '\'').is_some() { string.push(c); continue }
+            return Ok((progress + 2 + i, string.into_bump_str()))
+        }
+        Err((progress, SQLError::CannotFindString(progress)))
+    }
+}
+
+/// Literals of boolean, date and time types
+pub fn sql_parser_literal<'a>() -> SQLTag<'a, SQLDyn<'a>> {
+    let tok = |token: &'static str| Token::new(token).pad().err(|_, at, _| SQLError::MismatchToken(at, token));
+    // keywords are case-insensitive, but we only accept the all upper and all lower cases
+    let kw = move |upper: &'static str, lower: &'static str| tok(upper) ^ tok(lower);
+    let str = || Tag::new(SQLQuoted).pad();
+    fn check<'a, T>(expect: &'a str, x: Option<T>, at: usize) -> Result<T, SQLError<'a>> {
+        x.ok_or(SQLError::InvalidLiteral(at, expect))
+    }
+    let r#true = kw("TRUE", "true").out(|_, _| SQLExpr::Boolean { value: true });
+    let r#false = kw("FALSE", "false").out(|_, _| SQLExpr::Boolean { value: false });
+    let date = (kw("DATE", "date") % str()).err(|_, _, e| e.unwrap())
+        .and_then(|_, at, s| Ok(SQLExpr::Date { days: check("yyyy-mm-dd", util_datetime::parse_date(s), at)? }));
+    // longer keywords go first, as tokens match by prefix
+    let timestamptz = (kw("TIMESTAMPTZ", "timestamptz") % str()).err(|_, _, e| e.unwrap())
+        .and_then(|_, at, s| Ok(SQLExpr::TimestampTz { micros: check("yyyy-mm-dd hh:mm:ss+hh:mm", util_datetime::parse_timestamp(s), at)? }));
+    let timestamp = (kw("TIMESTAMP", "timestamp") % str()).err(|_, _, e| e.unwrap())
+        .and_then(|_, at, s| Ok(SQLExpr::Timestamp { micros: check("yyyy-mm-dd hh:mm:ss", util_datetime::parse_timestamp(s), at)? }));
+    let time = (kw("TIME", "time") % str()).err(|_, _, e| e.unwrap())
+        .and_then(|_, at, s| Ok(SQLExpr::Time { micros: check("hh:mm:ss", util_datetime::parse_time(s), at)? }));
+    let interval = (kw("INTERVAL", "interval") % str()).err(|_, _, e| e.unwrap())
+        .and_then(|_, at, s| Ok(SQLExpr::Interval { interval: check("<n> <unit> ...", util_datetime::parse_interval(s), at)? }));
+    let boolean = (r#true ^ r#false).erase();
+    let temporal = (date.erase() ^ timestamptz.erase() ^ timestamp.erase() ^ time.erase() ^ interval.erase()).erase();
+    (boolean ^ temporal).erase()
+}
+
+#[cfg(test)]
+mod test {
+    use bumpalo::Bump;
+    use super::*;
+
+    #[test]
+    fn parse_literal() {
+        let bump = Bump::new();
+        let parser = sql_parser_literal();
+        let parse = |input: &str| {
+            let mut space = SQLSpace::new(&bump, input);
+            parser.parse(input, 0, &mut space).map(|x| format!("{:?}", x.1)).ok()
+        };
+        assert!(parse("DATE '2026-01-01'") == Some("Date { days: 20454 }".into()));
+        assert!(parse("time '00:00:01'") == Some("Time { micros: 1000000 }".into()));
+        assert!(parse("TIMESTAMP '1970-01-02 00:00:00'") == Some("Timestamp { micros: 86400000000 }".into()));
+        assert!(parse("TIMESTAMPTZ '1970-01-01 01:00:00+01:00'") == Some("TimestampTz { micros: 0 }".into()));
+        assert!(parse("INTERVAL '1 month 2 days'") == Some("Interval { interval: MonthDayMicros { months: 1, days: 2, micros: 0 } }".into()));
+        assert!(parse("TRUE") == Some("Boolean { value: true }".into()));
+        assert!(parse("DATE '2026-02-30'").is_none());
+        assert!(parse("DATE 2026-02-03").is_none());
+    }
 }
\ No newline at end of file
@@ -1,5 +1,6 @@
 use crate::{sql_error::SQLError, sql_parser_space::SQLSpace, util_pratt_parser::*};
-use bumpalo::collections::Vec as BVec;
+use crate::data_schema::{self, DSchema, DSchemaRef, DSchemaEnum, DSchemaParser, Decimal, FixedBinary};
+use bumpalo::{Bump, collections::Vec as BVec};
 
 // SQLSchema
 #[derive(Debug, Clone)]
@@ -14,6 +15,145 @@ pub enum SQLSchema<'a> {
     I64, I32, I16, I8,
     U64, U32, U16, U8,
     Nil, F32, F64, Str, 
+    Bool, Date, Time, Timestamp, TimestampTz, Interval, Blob,
+    FixedBinary {
+        width: u32,
+    },
+    Decimal {
+        precision: u8,
+        scale: u8,
+    },
+}
+
+// Field names and tuple arities, which are lost when SQLSchema is lowered into DSchema
+#[derive(Debug, Clone)]
+pub enum SQLNames<'a> {
+    Leaf,
+    Tuple {
+        tuple: BVec<'a, SQLNames<'a>>
+    },
+    NamedTuple {
+        name:  BVec<'a, &'a str>,
+        tuple: BVec<'a, SQLNames<'a>>,
+    },
+}
+
+impl<'a> SQLSchema<'a> {
+    /// Lower a parsed schema into runtime schema. 
+    /// Tuples are encoded as right-nested pairs `(a, b, c) => Pair(a, Pair(b, c))`, 
+    /// an empty tuple is encoded as `Nil`, and an 1-tuple is encoded as its element. 
+    /// Fails with [`SQLError::SchemaTooLarge`] if the encoded schema doesn't fit in u16 offsets. 
+    pub fn lower(&self, bump: &'a Bump) -> Result<(DSchema, SQLNames<'a>), SQLError<'a>> {
+        use data_schema::*;
+        fn tuple<'a>(tuple: &[SQLSchema<'a>], field: Option<&[&'a str]>, bump: &'a Bump) -> Result<(DSchema, BVec<'a, SQLNames<'a>>), SQLError<'a>> {
+            let mut names = BVec::with_capacity_in(tuple.len(), bump);
+            // elements of a named tuple are wrapped as fields
+            let lower = |i: usize, x: &SQLSchema<'a>| {
+                let (schema, name) = x.lower(bump)?;
+                match field {
+                    Some(field) => Ok((Field::with(field[i], schema.as_ref()).ok_or(SQLError::SchemaTooLarge)?, name)),
+                    None => Ok((schema, name)),
+                }
+            };
+            let Some((last, init)) = tuple.split_last() else {
+                return Ok((Nil::encode(&[]), names))
+            };
+            let (mut schema, name) = lower(init.len(), last)?;
+            names.push(name);
+            for (i, x) in init.iter().enumerate().rev() {
+                let (head, name) = lower(i, x)?;
+                schema = Pair::with(head.as_ref(), schema.as_ref()).ok_or(SQLError::SchemaTooLarge)?;
+                names.push(name);
+            }
+            names.reverse();
+            Ok((schema, names))
+        }
+        let leaf = |schema| Ok((schema, SQLNames::Leaf));
+        match self {
+            SQLSchema::NamedTuple { name, tuple: t } => {
+                let (schema, tuple) = tuple(t, Some(name), bump)?;
+                Ok((schema, SQLNames::NamedTuple { name: name.clone(), tuple }))
+            }
+            SQLSchema::Tuple { tuple: t } => {
+                let (schema, tuple) = tuple(t, None, bump)?;
+                Ok((schema, SQLNames::Tuple { tuple }))
+            }
+            SQLSchema::I64 => leaf(I64::encode(&[])),
+            SQLSchema::I32 => leaf(I32::encode(&[])),
+            SQLSchema::I16 => leaf(I16::encode(&[])),
+            SQLSchema::I8  => leaf(I8::encode(&[])),
+            SQLSchema::U64 => leaf(U64::encode(&[])),
+            SQLSchema::U32 => leaf(U32::encode(&[])),
+            SQLSchema::U16 => leaf(U16::encode(&[])),
+            SQLSchema::U8  => leaf(U8::encode(&[])),
+            SQLSchema::Nil => leaf(Nil::encode(&[])),
+            SQLSchema::F32 => leaf(F32::encode(&[])),
+            SQLSchema::F64 => leaf(F64::encode(&[])),
+            SQLSchema::Str => leaf(Str::encode(&[])),
+            SQLSchema::Bool => leaf(Bool::encode(&[])),
+            SQLSchema::Date => leaf(Date::encode(&[])),
+            SQLSchema::Time => leaf(Time::encode(&[])),
+            SQLSchema::Timestamp => leaf(Timestamp::encode(&[])),
+            SQLSchema::TimestampTz => leaf(TimestampTz::encode(&[])),
+            SQLSchema::Interval => leaf(Interval::encode(&[])),
+            SQLSchema::Blob => leaf(Blob::encode(&[])),
+            SQLSchema::FixedBinary { width } => leaf(FixedBinary::with(*width).expect("binary width is checked by parser")),
+            SQLSchema::Decimal { precision, scale } => leaf(Decimal::with(*precision, *scale).expect("decimal precision and scale are checked by parser")),
+        }
+    }
+    /// Lift a runtime schema back to a parsed schema, the inverse of [`SQLSchema::lower`]. 
+    pub fn lift(schema: DSchemaRef<'_>, names: &SQLNames<'a>, bump: &'a Bump) -> Result<SQLSchema<'a>, SQLError<'a>> {
+        fn tuple<'a>(mut schema: DSchemaRef<'_>, names: &[SQLNames<'a>], field: Option<&[&'a str]>, bump: &'a Bump) -> Result<BVec<'a, SQLSchema<'a>>, SQLError<'a>> {
+            let mut tuple = BVec::with_capacity_in(names.len(), bump);
+            // unwrap the field of a named tuple's element
+            let lift = |i: usize, x: DSchemaRef<'_>, names: &SQLNames<'a>| {
+                let Some(field) = field else { return SQLSchema::lift(x, names, bump) };
+                match x.decode() {
+                    DSchemaEnum::Field(name, x) if name == field[i] => SQLSchema::lift(x, names, bump),
+                    _ => Err(SQLError::MismatchSchema(field[i])),
+                }
+            };
+            let Some((last, init)) = names.split_last() else {
+                let DSchemaEnum::Nil = schema.decode() else { Err(SQLError::MismatchSchema("nil"))? };
+                return Ok(tuple)
+            };
+            for (i, name) in init.iter().enumerate() {
+                let DSchemaEnum::Pair(_, rest, head) = schema.decode() else { Err(SQLError::MismatchSchema("pair"))? };
+                tuple.push(lift(i, head, name)?);
+                schema = rest;
+            }
+            tuple.push(lift(init.len(), schema, last)?);
+            Ok(tuple)
+        }
+        match names {
+            SQLNames::NamedTuple { name, tuple: t } => Ok(SQLSchema::NamedTuple { name: name.clone(), tuple: tuple(schema, t, Some(name), bump)? }),
+            SQLNames::Tuple { tuple: t } => Ok(SQLSchema::Tuple { tuple: tuple(schema, t, None, bump)? }),
+            SQLNames::Leaf => match schema.decode() {
+                DSchemaEnum::I64 => Ok(SQLSchema::I64),
+                DSchemaEnum::I32 => Ok(SQLSchema::I32),
+                DSchemaEnum::I16 => Ok(SQLSchema::I16),
+                DSchemaEnum::I8  => Ok(SQLSchema::I8),
+                DSchemaEnum::U64 => Ok(SQLSchema::U64),
+                DSchemaEnum::U32 => Ok(SQLSchema::U32),
+                DSchemaEnum::U16 => Ok(SQLSchema::U16),
+                DSchemaEnum::U8  => Ok(SQLSchema::U8),
+                DSchemaEnum::Nil => Ok(SQLSchema::Nil),
+                DSchemaEnum::F32 => Ok(SQLSchema::F32),
+                DSchemaEnum::F64 => Ok(SQLSchema::F64),
+                DSchemaEnum::Str => Ok(SQLSchema::Str),
+                DSchemaEnum::Bool => Ok(SQLSchema::Bool),
+                DSchemaEnum::Date => Ok(SQLSchema::Date),
+                DSchemaEnum::Time => Ok(SQLSchema::Time),
+                DSchemaEnum::Timestamp => Ok(SQLSchema::Timestamp),
+                DSchemaEnum::TimestampTz => Ok(SQLSchema::TimestampTz),
+                DSchemaEnum::Interval => Ok(SQLSchema::Interval),
+                DSchemaEnum::Decimal(precision, scale) => Ok(SQLSchema::Decimal { precision, scale }),
+                DSchemaEnum::Blob => Ok(SQLSchema::Blob),
+                DSchemaEnum::FixedBinary(width) => Ok(SQLSchema::FixedBinary { width }),
+                _ => Err(SQLError::MismatchSchema("primitive")),
+            }
+        }
+    }
 }
 
 type SQLTag<'a, P> = Tag<SQLSchema<'a>, SQLError<'a>, SQLSpace<'a>, P>;
@@ -35,6 +175,19 @@ impl<'a> Parser<&'a str, SQLError<'a>, SQLSpace<'a>> for SQLIdent {
     }
 }
 
+#[derive(Debug, Clone, Copy)]
+pub struct SQLUnsigned;
+
+impl<'a> Parser<u64, SQLError<'a>, SQLSpace<'a>> for SQLUnsigned {
+    fn parse(&self, input: &str, progress: usize, extra: &mut SQLSpace<'a>) -> Result<(usize, u64), (usize, SQLError<'a>)> {
+        let trimmed = input[progress..].len() - input[progress..].trim_start_matches(|x: char| x.is_ascii_digit()).len();
+        match input[progress..progress+trimmed].parse() {
+            Ok(number) => Ok((progress+trimmed, number)),
+            Err(_) => Err((progress, SQLError::CannotFindNumber(progress))),
+        }
+    }
+}
+
 pub fn sql_parser_schema<'a>() -> SQLTag<'a, SQLRec<'a>> {
     let tok = |token: &'static str| Token::new(token).pad().err(|_, at, _| SQLError::MismatchToken(at, token));
     let id = || Tag::new(SQLIdent).pad();
@@ -51,8 +204,32 @@ pub fn sql_parser_schema<'a>() -> SQLTag<'a, SQLRec<'a>> {
     let f32 = tok("f32").out(|_, _| SQLSchema::F32);
     let f64 = tok("f64").out(|_, _| SQLSchema::F64);
     let str = tok("str").out(|_, _| SQLSchema::Str);
+    let bool = tok("bool").out(|_, _| SQLSchema::Bool);
+    let date = tok("date").out(|_, _| SQLSchema::Date);
+    // longer keywords go first, as tokens match by prefix
+    let timestamptz = tok("timestamptz").out(|_, _| SQLSchema::TimestampTz);
+    let timestamp = tok("timestamp").out(|_, _| SQLSchema::Timestamp);
+    let time = tok("time").out(|_, _| SQLSchema::Time);
+    let interval = tok("interval").out(|_, _| SQLSchema::Interval);
+    let num = || Tag::new(SQLUnsigned).pad();
+    // decimal(precision, scale)
+    let decimal = ((tok("decimal") % tok("(")).err(|_, _, e| e.unwrap()) % num()).err(|_, _, e| e.unwrap());
+    let decimal = ((decimal / tok(",")).err(|_, _, e| e.unwrap()) + (num() / tok(")")).err(|_, _, e| e.unwrap()));
+    let decimal = decimal.and_then(|_, at, (precision, scale)| match (u8::try_from(precision), u8::try_from(scale)) {
+        (Ok(precision), Ok(scale)) if Decimal::with(precision, scale).is_some() => Ok(SQLSchema::Decimal { precision, scale }),
+        _ => Err(SQLError::InvalidLiteral(at, "decimal(precision <= 38, scale <= precision)")),
+    });
+    // binary(width)
+    let binary = ((tok("binary") % tok("(")).err(|_, _, e| e.unwrap()) % (num() / tok(")")).err(|_, _, e| e.unwrap())).err(|_, _, e| e.unwrap());
+    let binary = binary.and_then(|_, at, width| match u32::try_from(width) {
+        Ok(width) if width > 0 => Ok(SQLSchema::FixedBinary { width }),
+        _ => Err(SQLError::InvalidLiteral(at, "binary(0 < width < 2^32)")),
+    });
+    let blob = tok("blob").out(|_, _| SQLSchema::Blob) ^ tok("bytes").out(|_, _| SQLSchema::Blob);
     let simple = i64 ^ i32 ^ i16 ^ i8 ^ u64 ^ u32 ^ u16 ^ u8 ^ nil ^ f32 ^ f64 ^ str;
     let simple = simple.erase();
+    let typed = bool ^ date ^ timestamptz ^ timestamp ^ time ^ interval;
+    let simple = (simple ^ typed.erase() ^ decimal.erase() ^ blob.erase() ^ binary.erase()).erase();
     recurse(move |this| {
         // tuple with recursion
         let tuple = (this.clone() / tok(",")).err(|_, _, e| e.unwrap()) >> (
@@ -90,4 +267,49 @@ mod test {
         let parser = sql_parser_schema();
         println!("{:?}", parser.parse(input, 0, &mut space));
     }
+
+    #[test]
+    fn lower_lift() {
+        use crate::data_buffer::VBuf;
+        let input = "(a: i32, s: (i64, str), t: (), u: (f64,))";
+        let bump = Bump::new();
+        let mut space = SQLSpace::new(&bump, input);
+        let parser = sql_parser_schema();
+        let (_, schema) = parser.parse(input, 0, &mut space).unwrap();
+        let (lowered, names) = schema.lower(&bump).unwrap();
+        assert!("Pair(a: I32, Pair(s: Pair(I64, Str), Pair(t: Nil, u: F64)))" == format!("{:?}", lowered.as_ref()));
+        assert!(lowered.as_ref().lookup("s").map(|x| x.1) == Some(1..4));
+        let lifted = SQLSchema::lift(lowered.as_ref(), &names, &bump).unwrap();
+        assert!(VBuf::new(lowered).buffer.len() == 6);
+        assert!(format!("{schema:?}") == format!("{lifted:?}"));
+    }
+
+    #[test]
+    fn parse_typed() {
+        let input = "(ok: bool, at: timestamptz, day: date, price: decimal(12, 2), span: (time, interval), raw: (blob, binary(16)))";
+        let bump = Bump::new();
+        let mut space = SQLSpace::new(&bump, input);
+        let parser = sql_parser_schema();
+        let (_, schema) = parser.parse(input, 0, &mut space).unwrap();
+        let (lowered, names) = schema.lower(&bump).unwrap();
+        assert!("Pair(ok: Bool, Pair(at: TimestampTz, Pair(day: Date, Pair(price: Decimal(12, 2), Pair(span: Pair(Time, Interval), raw: Pair(Blob, FixedBinary(16)))))))" == format!("{:?}", lowered.as_ref()));
+        let lifted = SQLSchema::lift(lowered.as_ref(), &names, &bump).unwrap();
+        assert!(format!("{schema:?}") == format!("{lifted:?}"));
+        let input = "(price: decimal(40, 2))";
+        let mut space = SQLSpace::new(&bump, input);
+        assert!(parser.parse(input, 0, &mut space).is_err());
+    }
+
+    #[test]
+    fn lower_too_large() {
+        let bump = Bump::new();
+        // each pair adds at least 12 bytes, so 6000 columns overflow the u16 offsets
+        let mut tuple = BVec::new_in(&bump);
+        tuple.extend((0..6000).map(|_| SQLSchema::I64));
+        assert!(matches!(SQLSchema::Tuple { tuple }.lower(&bump), Err(SQLError::SchemaTooLarge)));
+        let long = bump.alloc_str(&"x".repeat(70000));
+        let name = BVec::from_iter_in([&*long], &bump);
+        let tuple = BVec::from_iter_in([SQLSchema::I32], &bump);
+        assert!(matches!(SQLSchema::NamedTuple { name, tuple }.lower(&bump), Err(SQLError::SchemaTooLarge)));
+    }
 }
\ No newline at end of file
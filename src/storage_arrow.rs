@@ -0,0 +1,1042 @@
+use std::collections::HashMap;
+use std::ffi::c_void;
+use std::fs::File;
+use std::io::{BufWriter, Read, Write};
+use std::path::Path;
+use std::ptr::null;
+use thiserror::Error;
+use crate::data_schema::*;
+use crate::data_buffer::*;
+use crate::data_arrow::*;
+use crate::util_bytes::Bytes;
+use crate::util_flatbuffers::{self as fb, FlatError, Item, Table};
+
+/*                             */
+/* Arrow IPC streams and files */
+/*                             */
+
+// A stream is a schema message followed by dictionary and record batch messages, each one a flatbuffer
+// with a body of buffers. A file holds a stream between magic bytes and a footer that locates the messages.
+// Columns map to arrow types like in `data_arrow`, the elements of a chain of unnamed pairs become the
+// fields of the schema. Dictionaries only grow: a batch adds its new strings as a delta and its codes
+// are remapped, so files can hold batches with different dictionaries. Buffers are not compressed.
+
+#[derive(Debug, Error)]
+pub enum IpcError {
+    #[error(transparent)]
+    Io(#[from] std::io::Error),
+    #[error(transparent)]
+    Arrow(#[from] ArrowError),
+    #[error(transparent)]
+    Flat(#[from] FlatError),
+    #[error("invalid arrow ipc: {0}")]
+    Invalid(String),
+    #[error("arrow ipc with {0} is not supported")]
+    Unsupported(String),
+    #[error("batch of {got} does not match the schema {expected}")]
+    SchemaMismatch { expected: String, got: String },
+}
+
+const MAGIC: &[u8] = b"ARROW1";
+const CONTINUATION: [u8; 4] = [0xFF; 4];
+const METADATA_V5: i16 = 4;
+
+const HEADER_SCHEMA: u8 = 1;
+const HEADER_DICTIONARY_BATCH: u8 = 2;
+const HEADER_RECORD_BATCH: u8 = 3;
+
+const TYPE_NULL: u8 = 1;
+const TYPE_INT: u8 = 2;
+const TYPE_FLOAT: u8 = 3;
+const TYPE_BINARY: u8 = 4;
+const TYPE_UTF8: u8 = 5;
+const TYPE_BOOL: u8 = 6;
+const TYPE_DECIMAL: u8 = 7;
+const TYPE_DATE: u8 = 8;
+const TYPE_TIME: u8 = 9;
+const TYPE_TIMESTAMP: u8 = 10;
+const TYPE_INTERVAL: u8 = 11;
+const TYPE_STRUCT: u8 = 13;
+const TYPE_FIXED_SIZE_BINARY: u8 = 15;
+const TYPE_LARGE_BINARY: u8 = 19;
+const TYPE_LARGE_UTF8: u8 = 20;
+const TYPE_BINARY_VIEW: u8 = 23;
+const TYPE_UTF8_VIEW: u8 = 24;
+
+/*                   */
+/* Types and formats */
+/*                   */
+
+fn int_format(bits: i32, signed: bool) -> Result<&'static str, IpcError> {
+    Ok(match (bits, signed) {
+        (8, true) => "c", (16, true) => "s", (32, true) => "i", (64, true) => "l",
+        (8, false) => "C", (16, false) => "S", (32, false) => "I", (64, false) => "L",
+        _ => Err(IpcError::Invalid(format!("int of {bits} bits")))?,
+    })
+}
+
+// type id and type table of an exported format
+fn ipc_type(format: &str) -> Result<(u8, Item), IpcError> {
+    let int = |bits: i32, signed: bool| (TYPE_INT, Item::Table(vec![(0, Item::I32(bits)), (1, Item::Bool(signed))]));
+    let unit = |id: u8, unit: i16| (id, Item::Table(vec![(0, Item::I16(unit))]));
+    let parse = |x: &str| x.parse::<i32>().map_err(|_| IpcError::Invalid(format!("format {format:?}")));
+    Ok(match format {
+        "n" => (TYPE_NULL, Item::Table(vec![])),
+        "b" => (TYPE_BOOL, Item::Table(vec![])),
+        "+s" => (TYPE_STRUCT, Item::Table(vec![])),
+        "c" => int(8, true), "s" => int(16, true), "i" => int(32, true), "l" => int(64, true),
+        "C" => int(8, false), "S" => int(16, false), "I" => int(32, false), "L" => int(64, false),
+        "f" => unit(TYPE_FLOAT, 1), "g" => unit(TYPE_FLOAT, 2),
+        "u" => (TYPE_UTF8, Item::Table(vec![])), "U" => (TYPE_LARGE_UTF8, Item::Table(vec![])),
+        "z" => (TYPE_BINARY, Item::Table(vec![])), "Z" => (TYPE_LARGE_BINARY, Item::Table(vec![])),
+        "tdD" => unit(TYPE_DATE, 0),
+        "ttu" => (TYPE_TIME, Item::Table(vec![(0, Item::I16(2)), (1, Item::I32(64))])),
+        "tin" => unit(TYPE_INTERVAL, 2),
+        "tsu:" => unit(TYPE_TIMESTAMP, 2),
+        _ if format.starts_with("tsu:") => (TYPE_TIMESTAMP, Item::Table(vec![(0, Item::I16(2)), (1, Item::Str(format[4..].into()))])),
+        _ if format.starts_with("w:") => (TYPE_FIXED_SIZE_BINARY, Item::Table(vec![(0, Item::I32(parse(&format[2..])?))])),
+        _ if format.starts_with("d:") => {
+            let (precision, scale) = format[2..].split_once(',').ok_or(IpcError::Invalid(format!("format {format:?}")))?;
+            (TYPE_DECIMAL, Item::Table(vec![(0, Item::I32(parse(precision)?)), (1, Item::I32(parse(scale)?)), (2, Item::I32(128))]))
+        }
+        _ => Err(IpcError::Unsupported(format!("format {format:?}")))?,
+    })
+}
+
+// format of the type of a field
+fn format_of(field: Table<'_>) -> Result<String, IpcError> {
+    let id = field.u8(2, 0)?;
+    // a type table with only default values may be left out
+    let empty = fb::finish(&Item::Table(vec![]));
+    let ty = match field.table(3)? { Some(ty) => ty, None => Table::root(&empty)? };
+    let unit = |x: i16| ["s", "m", "u", "n"].get(x as usize).copied().ok_or(IpcError::Invalid(format!("time unit {x}")));
+    Ok(match id {
+        TYPE_NULL => "n".into(),
+        TYPE_BOOL => "b".into(),
+        TYPE_STRUCT => "+s".into(),
+        TYPE_INT => int_format(ty.i32(0, 0)?, ty.bool(1, false)?)?.into(),
+        TYPE_FLOAT => match ty.i16(0, 0)? { 1 => "f", 2 => "g", _ => Err(IpcError::Unsupported("half floats".into()))? }.into(),
+        TYPE_UTF8 => "u".into(), TYPE_LARGE_UTF8 => "U".into(), TYPE_UTF8_VIEW => "vu".into(),
+        TYPE_BINARY => "z".into(), TYPE_LARGE_BINARY => "Z".into(), TYPE_BINARY_VIEW => "vz".into(),
+        TYPE_FIXED_SIZE_BINARY => format!("w:{}", ty.i32(0, 0)?),
+        TYPE_DECIMAL => match ty.i32(2, 128)? {
+            128 => format!("d:{},{}", ty.i32(0, 0)?, ty.i32(1, 0)?),
+            bits => Err(IpcError::Unsupported(format!("decimals of {bits} bits")))?,
+        },
+        TYPE_DATE => match ty.i16(0, 1)? { 0 => "tdD", _ => "tdm" }.into(),
+        TYPE_TIME => format!("tt{}", unit(ty.i16(0, 1)?)?),
+        TYPE_TIMESTAMP => format!("ts{}:{}", unit(ty.i16(0, 0)?)?, ty.str(1)?.unwrap_or("")),
+        TYPE_INTERVAL => match ty.i16(0, 0)? { 2 => "tin".into(), _ => Err(IpcError::Unsupported("intervals other than month-day-nano".into()))? },
+        _ => Err(IpcError::Unsupported(format!("type {id}")))?,
+    })
+}
+
+// field of the schema message for an exported schema, dictionaries are numbered in order
+fn field_item(schema: &ArrowSchema, next_id: &mut i64) -> Result<Item, IpcError> {
+    let mut fields = vec![(0, Item::Str(schema.name().into())), (1, Item::Bool(schema.flags & 2 != 0))];
+    match schema.dictionary() {
+        None => {
+            let (id, ty) = ipc_type(schema.format())?;
+            fields.extend([(2, Item::U8(id)), (3, ty)]);
+        }
+        Some(values) => {
+            let (id, ty) = ipc_type(values.format())?;
+            let (_, index) = ipc_type(schema.format())?;
+            fields.extend([(2, Item::U8(id)), (3, ty), (4, Item::Table(vec![(0, Item::I64(*next_id)), (1, index)]))]);
+            *next_id += 1;
+        }
+    }
+    let children = schema.children().iter().map(|c| field_item(c, next_id)).collect::<Result<Vec<_>, _>>()?;
+    fields.push((5, Item::Tables(children)));
+    Ok(Item::Table(fields))
+}
+
+// top-level fields, a chain of unnamed pairs is spread into its elements
+fn spread(schema: &ArrowSchema) -> bool {
+    schema.format() == "+s" && schema.name().is_empty()
+}
+
+fn fixed_width(format: &str) -> Result<usize, IpcError> {
+    Ok(match format {
+        "c" | "C" => 1,
+        "s" | "S" => 2,
+        "i" | "I" | "f" | "tdD" => 4,
+        "l" | "L" | "g" | "ttu" => 8,
+        "tin" => 16,
+        _ if format.starts_with("tsu:") => 8,
+        _ if format.starts_with("d:") => 16,
+        _ if format.starts_with("w:") => format[2..].parse().map_err(|_| IpcError::Invalid(format!("format {format:?}")))?,
+        _ => Err(IpcError::Unsupported(format!("format {format:?}")))?,
+    })
+}
+
+/*         */
+/* Writing */
+/*         */
+
+// an encoded message and its body
+struct Message {
+    meta: Vec<u8>,
+    body: Vec<u8>,
+    dictionary: bool,
+}
+
+fn message(header_type: u8, header: Item, body: Vec<u8>) -> Message {
+    let meta = fb::finish(&Item::Table(vec![
+        (0, Item::I16(METADATA_V5)),
+        (1, Item::U8(header_type)),
+        (2, header),
+        (3, Item::I64(body.len() as i64)),
+    ]));
+    Message { meta, body, dictionary: header_type == HEADER_DICTIONARY_BATCH }
+}
+
+// body of a record batch, with its field nodes and buffer locations
+#[derive(Default)]
+struct Body {
+    bytes: Vec<u8>,
+    nodes: Vec<u8>,
+    buffers: Vec<u8>,
+}
+
+impl Body {
+    fn node(&mut self, length: usize, null_count: usize) {
+        self.nodes.extend((length as i64).to_le_bytes());
+        self.nodes.extend((null_count as i64).to_le_bytes());
+    }
+    // buffers start at multiples of 8
+    fn buffer(&mut self, data: &[u8]) {
+        self.buffers.extend((self.bytes.len() as i64).to_le_bytes());
+        self.buffers.extend((data.len() as i64).to_le_bytes());
+        self.bytes.extend(data);
+        self.bytes.resize(self.bytes.len().next_multiple_of(8), 0);
+    }
+    fn record_batch(self, length: usize) -> (Item, Vec<u8>) {
+        let batch = Item::Table(vec![
+            (0, Item::I64(length as i64)),
+            (1, Item::Structs(self.nodes, 16)),
+            (2, Item::Structs(self.buffers, 16)),
+        ]);
+        (batch, self.bytes)
+    }
+}
+
+// encodes batches into messages, for both streams and files
+struct Encoder {
+    schema: DSchema,
+    arrow: ArrowSchema,
+    header: Item,
+    // codes of the strings written to each dictionary, none until the first one is written
+    dictionaries: Vec<Option<HashMap<Vec<u8>, u32>>>,
+}
+
+impl Encoder {
+    fn new(schema: DSchemaRef<'_>) -> Result<Encoder, IpcError> {
+        let arrow = export_schema(schema)?;
+        let mut next_id = 0;
+        let fields = match spread(&arrow) {
+            true => arrow.children().iter().map(|f| field_item(f, &mut next_id)).collect::<Result<Vec<_>, _>>()?,
+            false => vec![field_item(&arrow, &mut next_id)?],
+        };
+        // little endian, the only one written
+        let header = Item::Table(vec![(0, Item::I16(0)), (1, Item::Tables(fields))]);
+        Ok(Encoder { schema: DSchema::from_ref(schema), arrow, header, dictionaries: vec![None; next_id as usize] })
+    }
+    fn schema_message(&self) -> Message {
+        message(HEADER_SCHEMA, self.header.clone(), vec![])
+    }
+    // messages of a batch, the dictionary deltas it needs come first
+    fn encode(&mut self, batch: VBufRef<'_>) -> Result<Vec<Message>, IpcError> {
+        if batch.schema != self.schema.as_ref() {
+            Err(IpcError::SchemaMismatch { expected: format!("{:?}", self.schema.as_ref()), got: format!("{:?}", batch.schema) })?
+        }
+        // the exported array only lives in this call, while the batch is borrowed
+        let (array, _) = unsafe { export_ref(batch)? };
+        let schema = std::mem::replace(&mut self.arrow, ArrowSchema::empty());
+        let (schemas, arrays) = match spread(&schema) {
+            true => (schema.children().to_vec(), array.children().to_vec()),
+            false => (vec![&schema], vec![&array]),
+        };
+        let (mut messages, mut body, mut next_id) = (vec![], Body::default(), 0);
+        let result = schemas.iter().zip(arrays).try_for_each(|(s, a)| unsafe { self.encode_node(s, a, &mut body, &mut next_id, &mut messages) });
+        self.arrow = schema;
+        result?;
+        let (header, body) = body.record_batch(batch.len);
+        messages.push(message(HEADER_RECORD_BATCH, header, body));
+        Ok(messages)
+    }
+    unsafe fn encode_node(&mut self, schema: &ArrowSchema, array: &ArrowArray, body: &mut Body, next_id: &mut usize, messages: &mut Vec<Message>) -> Result<(), IpcError> {
+        let (format, len, offset) = (schema.format(), array.length as usize, array.offset as usize);
+        let buffers = array.buffers();
+        let bytes = |i: usize, start: usize, end: usize| std::slice::from_raw_parts((buffers[i] as *const u8).add(start), end - start);
+        body.node(len, array.null_count as usize);
+        if format == "n" { return Ok(()) }
+        // no validity, the columns have no nulls
+        body.buffer(&[]);
+        if let Some(dictionary) = array.dictionary() {
+            let id = *next_id;
+            *next_id += 1;
+            let width = fixed_width(format)?;
+            let remap = self.remap(id, dictionary, messages)?;
+            let mut indices = Vec::with_capacity(len * width);
+            for x in bytes(1, offset * width, (offset + len) * width).chunks(width) {
+                let mut code = [0; 4];
+                code[..width].copy_from_slice(x);
+                let code = remap[u32::from_le_bytes(code) as usize];
+                if width < 4 && code >> (8 * width) != 0 {
+                    Err(IpcError::Unsupported(format!("dictionary {id} beyond its {}-bit index", 8 * width)))?
+                }
+                indices.extend(&code.to_le_bytes()[..width]);
+            }
+            body.buffer(&indices);
+            return Ok(())
+        }
+        match format {
+            "+s" => {
+                for (s, a) in schema.children().iter().zip(array.children()) {
+                    self.encode_node(s, a, body, next_id, messages)?;
+                }
+            }
+            "b" => {
+                let bits = buffers[1] as *const u8;
+                let mut packed = vec![0u8; len.div_ceil(8)];
+                for i in 0..len {
+                    let at = offset + i;
+                    packed[i / 8] |= (*bits.add(at / 8) >> (at % 8) & 1) << (i % 8);
+                }
+                body.buffer(&packed);
+            }
+            // offsets are rebased to the first value of the view
+            "U" | "Z" => {
+                let offsets = std::slice::from_raw_parts((buffers[1] as *const i64).add(offset), len + 1);
+                body.buffer(bytemuck::cast_slice(&offsets.iter().map(|x| x - offsets[0]).collect::<Vec<_>>()));
+                body.buffer(bytes(2, offsets[0] as usize, offsets[len] as usize));
+            }
+            _ => {
+                let width = fixed_width(format)?;
+                body.buffer(bytes(1, offset * width, (offset + len) * width));
+            }
+        }
+        Ok(())
+    }
+    // codes of the dictionary of a batch in the written one, new strings go out as a delta
+    unsafe fn remap(&mut self, id: usize, dictionary: &ArrowArray, messages: &mut Vec<Message>) -> Result<Vec<u32>, IpcError> {
+        let buffers = dictionary.buffers();
+        let entries = dictionary.length as usize;
+        let offsets = std::slice::from_raw_parts(buffers[1] as *const i64, entries + 1);
+        let data = buffers[2] as *const u8;
+        let first = self.dictionaries[id].is_none();
+        let known = self.dictionaries[id].get_or_insert_with(HashMap::new);
+        let mut delta = Body::default();
+        let (mut delta_offsets, mut delta_data) = (vec![0i64], vec![]);
+        let remap = (0..entries).map(|i| {
+            let x = std::slice::from_raw_parts(data.add(offsets[i] as usize), (offsets[i + 1] - offsets[i]) as usize);
+            let code = known.len() as u32;
+            *known.entry(x.to_vec()).or_insert_with(|| {
+                delta_data.extend(x);
+                delta_offsets.push(delta_data.len() as i64);
+                code
+            })
+        }).collect::<Vec<_>>();
+        let added = delta_offsets.len() - 1;
+        if first || added > 0 {
+            delta.node(added, 0);
+            delta.buffer(&[]);
+            delta.buffer(bytemuck::cast_slice(&delta_offsets));
+            delta.buffer(&delta_data);
+            let (batch, body) = delta.record_batch(added);
+            let header = Item::Table(vec![(0, Item::I64(id as i64)), (1, batch), (2, Item::Bool(!first))]);
+            messages.push(message(HEADER_DICTIONARY_BATCH, header, body));
+        }
+        Ok(remap)
+    }
+}
+
+// write a message after its continuation marker and size, return the sizes of its metadata and body
+fn write_message(out: &mut impl Write, message: &Message) -> Result<(usize, usize), IpcError> {
+    // the metadata is padded so that the body starts at a multiple of 8, the padding counts as metadata
+    let padded = message.meta.len().next_multiple_of(8);
+    out.write_all(&CONTINUATION)?;
+    out.write_all(&(padded as i32).to_le_bytes())?;
+    out.write_all(&message.meta)?;
+    out.write_all(&vec![0; padded - message.meta.len()])?;
+    out.write_all(&message.body)?;
+    Ok((8 + padded, message.body.len()))
+}
+
+/// Writes batches of one schema as an arrow ipc stream
+pub struct StreamWriter<W: Write> {
+    out: W,
+    encoder: Encoder,
+}
+
+impl<W: Write> StreamWriter<W> {
+    pub fn new(mut out: W, schema: DSchemaRef<'_>) -> Result<StreamWriter<W>, IpcError> {
+        let encoder = Encoder::new(schema)?;
+        write_message(&mut out, &encoder.schema_message())?;
+        Ok(StreamWriter { out, encoder })
+    }
+    pub fn write(&mut self, batch: VBufRef<'_>) -> Result<(), IpcError> {
+        for message in self.encoder.encode(batch)? {
+            write_message(&mut self.out, &message)?;
+        }
+        Ok(())
+    }
+    /// Mark the end of the stream and return the output
+    pub fn finish(mut self) -> Result<W, IpcError> {
+        self.out.write_all(&CONTINUATION)?;
+        self.out.write_all(&[0; 4])?;
+        self.out.flush()?;
+        Ok(self.out)
+    }
+}
+
+/// Writes batches of one schema as an arrow ipc file
+pub struct FileWriter<W: Write> {
+    out: W,
+    encoder: Encoder,
+    position: usize,
+    // blocks of the footer, 24 bytes each
+    dictionaries: Vec<u8>,
+    batches: Vec<u8>,
+}
+
+impl FileWriter<BufWriter<File>> {
+    pub fn create(path: impl AsRef<Path>, schema: DSchemaRef<'_>) -> Result<FileWriter<BufWriter<File>>, IpcError> {
+        FileWriter::new(BufWriter::new(File::create(path)?), schema)
+    }
+}
+
+impl<W: Write> FileWriter<W> {
+    pub fn new(mut out: W, schema: DSchemaRef<'_>) -> Result<FileWriter<W>, IpcError> {
+        let encoder = Encoder::new(schema)?;
+        out.write_all(MAGIC)?;
+        out.write_all(&[0; 2])?;
+        let (meta, body) = write_message(&mut out, &encoder.schema_message())?;
+        Ok(FileWriter { out, encoder, position: 8 + meta + body, dictionaries: vec![], batches: vec![] })
+    }
+    pub fn write(&mut self, batch: VBufRef<'_>) -> Result<(), IpcError> {
+        for message in self.encoder.encode(batch)? {
+            let (meta, body) = write_message(&mut self.out, &message)?;
+            let blocks = if message.dictionary { &mut self.dictionaries } else { &mut self.batches };
+            blocks.extend((self.position as i64).to_le_bytes());
+            blocks.extend((meta as i32).to_le_bytes());
+            blocks.extend([0; 4]);
+            blocks.extend((body as i64).to_le_bytes());
+            self.position += meta + body;
+        }
+        Ok(())
+    }
+    /// Write the footer and return the output
+    pub fn finish(mut self) -> Result<W, IpcError> {
+        self.out.write_all(&CONTINUATION)?;
+        self.out.write_all(&[0; 4])?;
+        let footer = fb::finish(&Item::Table(vec![
+            (0, Item::I16(METADATA_V5)),
+            (1, self.encoder.header.clone()),
+            (2, Item::Structs(std::mem::take(&mut self.dictionaries), 24)),
+            (3, Item::Structs(std::mem::take(&mut self.batches), 24)),
+        ]));
+        self.out.write_all(&footer)?;
+        self.out.write_all(&(footer.len() as i32).to_le_bytes())?;
+        self.out.write_all(MAGIC)?;
+        self.out.flush()?;
+        Ok(self.out)
+    }
+}
+
+/*         */
+/* Reading */
+/*         */
+
+// a field of the schema message
+struct FieldSpec {
+    name: String,
+    format: String,
+    nullable: bool,
+    // id and value format of a dictionary, the format is then the one of the indices
+    dictionary: Option<(i64, String)>,
+    children: Vec<FieldSpec>,
+}
+
+impl FieldSpec {
+    fn read(field: Table<'_>) -> Result<FieldSpec, IpcError> {
+        let mut format = format_of(field)?;
+        let children = field.tables(5)?.into_iter().map(FieldSpec::read).collect::<Result<Vec<_>, _>>()?;
+        let dictionary = match field.table(4)? {
+            None => None,
+            Some(encoding) => {
+                if !["u", "U", "vu"].contains(&format.as_str()) { Err(IpcError::Unsupported(format!("dictionaries of {format:?}")))? }
+                // indices are 32-bit signed unless told otherwise
+                let index = match encoding.table(1)? {
+                    Some(int) => int_format(int.i32(0, 32)?, int.bool(1, true)?)?,
+                    None => "i",
+                };
+                Some((encoding.i64(0, 0)?, std::mem::replace(&mut format, index.into())))
+            }
+        };
+        Ok(FieldSpec { name: field.str(0)?.unwrap_or("").into(), format, nullable: field.bool(1, false)?, dictionary, children })
+    }
+    fn arrow_schema(&self) -> Result<ArrowSchema, IpcError> {
+        let children = self.children.iter().map(|c| c.arrow_schema()).collect::<Result<Vec<_>, _>>()?;
+        // dictionaries are kept decoded into str columns
+        let dictionary = self.dictionary.as_ref().map(|_| schema_node("U", "", 0, vec![], None)).transpose()?;
+        Ok(schema_node(&self.format, &self.name, if self.nullable { 2 } else { 0 }, children, dictionary)?)
+    }
+    fn dictionaries<'a>(&'a self, formats: &mut HashMap<i64, &'a str>) {
+        if let Some((id, format)) = &self.dictionary { formats.insert(*id, format); }
+        for child in &self.children { child.dictionaries(formats) }
+    }
+    // an array without rows, to learn the schema of the columns
+    fn empty_array(&self, dictionary: &VBuf) -> Result<ArrowArray, IpcError> {
+        let children = self.children.iter().map(|c| c.empty_array(dictionary)).collect::<Result<Vec<_>, _>>()?;
+        let dictionary = self.dictionary.as_ref().map(|_| unsafe { export_ref(dictionary.as_ref()) }.map(|(a, _)| a)).transpose()?;
+        Ok(ArrowArray::borrowed(0, 0, vec![null(); 3], children, dictionary))
+    }
+}
+
+// walks the nodes and buffers of a record batch in the order of the fields
+struct BodyReader<'a> {
+    body: &'a [u8],
+    length: usize,
+    nodes: std::vec::IntoIter<&'a [u8]>,
+    buffers: std::vec::IntoIter<&'a [u8]>,
+    variadic: std::vec::IntoIter<&'a [u8]>,
+    // sizes of the data buffers of each view column, passed as its last buffer
+    sizes: Vec<Vec<i64>>,
+}
+
+fn le_i64(x: &[u8]) -> i64 {
+    i64::from_le_bytes(x[..8].try_into().unwrap())
+}
+
+fn le_i32(x: &[u8]) -> i32 {
+    i32::from_le_bytes(x[..4].try_into().unwrap())
+}
+
+// a size or count from the input, which must not be negative
+fn size(x: i64, what: &str) -> Result<usize, IpcError> {
+    usize::try_from(x).map_err(|_| IpcError::Invalid(format!("{what} is {x}")))
+}
+
+// check the buffers of a node can hold `length` values, before import reads them through raw pointers
+fn check_node(format: &str, length: usize, buffers: &[&[u8]]) -> Result<(), IpcError> {
+    let invalid = |what: &str| Err(IpcError::Invalid(format!("{what} of {format:?} does not hold {length} values")));
+    let bits = length.div_ceil(8);
+    if buffers.first().is_some_and(|b| !b.is_empty() && b.len() < bits) { invalid("validity buffer")? }
+    let holds = |buffer: &[u8], count: usize, width: usize| count.checked_mul(width).is_some_and(|n| buffer.len() >= n);
+    match format {
+        "n" | "+s" => {}
+        "b" => if buffers[1].len() < bits { invalid("data buffer")? },
+        "u" | "U" | "z" | "Z" => {
+            if length == 0 { return Ok(()) }
+            let wide = format == "U" || format == "Z";
+            let width = if wide { 8 } else { 4 };
+            if !holds(buffers[1], length + 1, width) { invalid("offset buffer")? }
+            let offsets = buffers[1][..(length + 1) * width].chunks(width).map(|x| if wide { le_i64(x) } else { le_i32(x) as i64 });
+            // offsets start at zero or later and never go back
+            let mut last = 0;
+            for x in offsets {
+                if x < last { invalid("offsets")? }
+                last = x;
+            }
+            if last as u64 > buffers[2].len() as u64 { invalid("value buffer")? }
+        }
+        "vu" | "vz" => {
+            if !holds(buffers[1], length, 16) { invalid("view buffer")? }
+            // data buffers sit between the views and the buffer of their sizes
+            let data = &buffers[2..];
+            for view in buffers[1][..length * 16].chunks(16) {
+                let len = le_i32(view);
+                if len < 0 { invalid("views")? }
+                if len <= 12 { continue }
+                let (index, at) = (le_i32(&view[8..]), le_i32(&view[12..]));
+                let inside = usize::try_from(index).ok().and_then(|i| data.get(i))
+                    .is_some_and(|b| at >= 0 && at as u64 + len as u64 <= b.len() as u64);
+                if !inside { invalid("views")? }
+            }
+        }
+        // dictionary indices have an integer format, the scaled dates and times are not in `fixed_width`
+        _ => {
+            let width = match format {
+                "tdm" | "ttn" => 8,
+                "tts" | "ttm" => 4,
+                _ if format.starts_with("ts") => 8,
+                _ => fixed_width(format)?,
+            };
+            if !holds(buffers[1], length, width) { invalid("data buffer")? }
+        }
+    }
+    Ok(())
+}
+
+impl<'a> BodyReader<'a> {
+    fn new(batch: Table<'a>, body: &'a [u8]) -> Result<BodyReader<'a>, IpcError> {
+        if batch.table(3)?.is_some() { Err(IpcError::Unsupported("compressed buffers".into()))? }
+        Ok(BodyReader {
+            body,
+            length: size(batch.i64(0, 0)?, "record batch length")?,
+            nodes: batch.structs(1, 16)?.into_iter(),
+            buffers: batch.structs(2, 16)?.into_iter(),
+            variadic: batch.structs(4, 8)?.into_iter(),
+            sizes: vec![],
+        })
+    }
+    fn buffer(&mut self) -> Result<&'a [u8], IpcError> {
+        let buffer = self.buffers.next().ok_or(IpcError::Invalid("record batch has too few buffers".into()))?;
+        let (offset, len) = (size(le_i64(buffer), "buffer offset")?, size(le_i64(&buffer[8..]), "buffer length")?);
+        if offset % 8 != 0 { Err(IpcError::Invalid(format!("buffer at {offset} is not aligned")))? }
+        offset.checked_add(len).and_then(|end| self.body.get(offset..end)).ok_or(IpcError::Invalid(format!("buffer at {offset} is outside the body")))
+    }
+    fn array(&mut self, spec: &FieldSpec, dictionaries: &HashMap<i64, VBuf>) -> Result<ArrowArray, IpcError> {
+        let node = self.nodes.next().ok_or(IpcError::Invalid("record batch has too few nodes".into()))?;
+        let (length, null_count) = (size(le_i64(node), "node length")?, size(le_i64(&node[8..]), "null count")?);
+        if null_count > length { Err(IpcError::Invalid(format!("{null_count} nulls in {length} values")))? }
+        let count = match spec.format.as_str() {
+            "n" => 0,
+            "+s" => 1,
+            "u" | "U" | "z" | "Z" => 3,
+            "vu" | "vz" => {
+                let variadic = self.variadic.next().ok_or(IpcError::Invalid("record batch has too few variadic counts".into()))?;
+                2usize.checked_add(size(le_i64(variadic), "variadic count")?).ok_or(IpcError::Invalid("variadic count overflows".into()))?
+            }
+            _ => 2,
+        };
+        let slices = (0..count).map(|_| self.buffer()).collect::<Result<Vec<_>, _>>()?;
+        check_node(&spec.format, length, &slices)?;
+        let sizes = slices.iter().skip(2).map(|b| b.len() as i64).collect::<Vec<_>>();
+        // an empty validity buffer means there are no nulls
+        let mut buffers = slices.iter().enumerate()
+            .map(|(i, b)| if i == 0 && b.is_empty() { null() } else { b.as_ptr() as *const c_void })
+            .collect::<Vec<_>>();
+        if spec.format.starts_with('v') {
+            buffers.push(sizes.as_ptr() as *const c_void);
+            self.sizes.push(sizes);
+        }
+        let children = spec.children.iter().map(|c| self.array(c, dictionaries)).collect::<Result<Vec<_>, _>>()?;
+        if children.iter().any(|c| (c.length as usize) < length) { Err(IpcError::Invalid(format!("children of {:?} are shorter than {length}", spec.name)))? }
+        let dictionary = match &spec.dictionary {
+            None => None,
+            Some((id, _)) => {
+                let values = dictionaries.get(id).ok_or(IpcError::Invalid(format!("dictionary {id} is missing")))?;
+                Some(unsafe { export_ref(values.as_ref())? }.0)
+            }
+        };
+        Ok(ArrowArray::borrowed(length, null_count, buffers, children, dictionary))
+    }
+}
+
+// decodes messages after the schema, for both streams and files
+struct Decoder {
+    fields: Vec<FieldSpec>,
+    arrow: ArrowSchema,
+    schema: DSchema,
+    // values of each dictionary, decoded
+    dictionaries: HashMap<i64, VBuf>,
+}
+
+impl Decoder {
+    fn new(schema: Table<'_>) -> Result<Decoder, IpcError> {
+        if schema.i16(0, 0)? != 0 { Err(IpcError::Unsupported("big endian".into()))? }
+        let fields = schema.tables(1)?.into_iter().map(FieldSpec::read).collect::<Result<Vec<_>, _>>()?;
+        if fields.is_empty() { Err(IpcError::Unsupported("no fields".into()))? }
+        let children = fields.iter().map(|f| f.arrow_schema()).collect::<Result<Vec<_>, _>>()?;
+        let arrow = schema_node("+s", "", 0, children, None)?;
+        let dictionary = VBuf::new(Str::encode(&[]));
+        let children = fields.iter().map(|f| f.empty_array(&dictionary)).collect::<Result<Vec<_>, _>>()?;
+        let schema = unsafe { import(ArrowArray::borrowed(0, 0, vec![null()], children, None), &arrow)? }.schema;
+        Ok(Decoder { fields, arrow, schema, dictionaries: HashMap::new() })
+    }
+    // apply a message, a record batch comes out as columns
+    fn decode(&mut self, meta: &[u8], body: &[u8]) -> Result<Option<VBuf>, IpcError> {
+        let message = Table::root(meta)?;
+        let header = message.table(2)?.ok_or(IpcError::Invalid("message has no header".into()))?;
+        match message.u8(1, 0)? {
+            HEADER_DICTIONARY_BATCH => {
+                let id = header.i64(0, 0)?;
+                let mut formats = HashMap::new();
+                for field in &self.fields { field.dictionaries(&mut formats) }
+                let format = *formats.get(&id).ok_or(IpcError::Invalid(format!("dictionary {id} is not in the schema")))?;
+                let batch = header.table(1)?.ok_or(IpcError::Invalid(format!("dictionary {id} has no values")))?;
+                let spec = FieldSpec { name: String::new(), format: format.into(), nullable: false, dictionary: None, children: vec![] };
+                let array = BodyReader::new(batch, body)?.array(&spec, &self.dictionaries)?;
+                let values = unsafe { import(array, &spec.arrow_schema()?)? };
+                let values = match (header.bool(2, false)?, self.dictionaries.get(&id)) {
+                    (true, Some(known)) => crate::kernel_select::concat(&[known.as_ref(), values.as_ref()]),
+                    _ => values,
+                };
+                self.dictionaries.insert(id, values);
+                Ok(None)
+            }
+            HEADER_RECORD_BATCH => {
+                let mut reader = BodyReader::new(header, body)?;
+                let children = self.fields.iter().map(|f| reader.array(f, &self.dictionaries)).collect::<Result<Vec<_>, _>>()?;
+                if children.iter().any(|c| (c.length as usize) < reader.length) { Err(IpcError::Invalid(format!("columns are shorter than {}", reader.length)))? }
+                let array = ArrowArray::borrowed(reader.length, 0, vec![null()], children, None);
+                Ok(Some(unsafe { import(array, &self.arrow)? }))
+            }
+            HEADER_SCHEMA => Err(IpcError::Invalid("schema after the first message".into())),
+            other => Err(IpcError::Unsupported(format!("message type {other}"))),
+        }
+    }
+}
+
+// buffers are read as u64 so that the values in them are aligned
+fn aligned(len: usize) -> Vec<u64> {
+    vec![0; len.div_ceil(8)]
+}
+
+// metadata of a message and its body
+type Encapsulated = (Vec<u8>, Vec<u64>);
+
+// the next message, none at the end of the stream
+fn read_message(input: &mut impl Read) -> Result<Option<Encapsulated>, IpcError> {
+    let mut word = [0; 4];
+    // a stream may end without the end of stream marker
+    match input.read_exact(&mut word) {
+        Err(e) if e.kind() == std::io::ErrorKind::UnexpectedEof => return Ok(None),
+        x => x?,
+    }
+    // streams older than arrow 0.15 have no continuation marker
+    if word == CONTINUATION { input.read_exact(&mut word)? }
+    let len = i32::from_le_bytes(word);
+    if len < 0 { Err(IpcError::Invalid(format!("message length {len}")))? }
+    if len == 0 { return Ok(None) }
+    let meta = read_bytes(input, len as usize)?;
+    let len = size(Table::root(&meta)?.i64(3, 0)?, "body length")?;
+    let bytes = read_bytes(input, len)?;
+    let mut body = aligned(len);
+    bytemuck::cast_slice_mut(&mut body)[..len].copy_from_slice(&bytes);
+    Ok(Some((meta, body)))
+}
+
+// read `len` bytes as they arrive, so that a size made up by the input allocates no more than the input holds
+fn read_bytes(input: &mut impl Read, len: usize) -> Result<Vec<u8>, IpcError> {
+    let mut bytes = vec![];
+    input.take(len as u64).read_to_end(&mut bytes)?;
+    if bytes.len() < len { Err(IpcError::Invalid(format!("message is truncated at {} of {len} bytes", bytes.len())))? }
+    Ok(bytes)
+}
+
+/// Reads batches from an arrow ipc stream
+pub struct StreamReader<R: Read> {
+    input: R,
+    decoder: Decoder,
+    done: bool,
+}
+
+impl<R: Read> StreamReader<R> {
+    pub fn new(mut input: R) -> Result<StreamReader<R>, IpcError> {
+        let (meta, _) = read_message(&mut input)?.ok_or(IpcError::Invalid("stream has no schema".into()))?;
+        let message = Table::root(&meta)?;
+        if message.u8(1, 0)? != HEADER_SCHEMA { Err(IpcError::Invalid("stream does not start with a schema".into()))? }
+        let decoder = Decoder::new(message.table(2)?.ok_or(IpcError::Invalid("message has no header".into()))?)?;
+        Ok(StreamReader { input, decoder, done: false })
+    }
+    pub fn schema(&self) -> DSchemaRef<'_> {
+        self.decoder.schema.as_ref()
+    }
+}
+
+impl<R: Read> Iterator for StreamReader<R> {
+    type Item = Result<VBuf, IpcError>;
+    fn next(&mut self) -> Option<Self::Item> {
+        while !self.done {
+            let batch = read_message(&mut self.input).and_then(|message| match message {
+                Some((meta, body)) => self.decoder.decode(&meta, bytemuck::cast_slice(&body)),
+                None => { self.done = true; Ok(None) }
+            });
+            match batch {
+                Ok(None) => continue,
+                Ok(Some(batch)) => return Some(Ok(batch)),
+                Err(e) => { self.done = true; return Some(Err(e)) }
+            }
+        }
+        None
+    }
+}
+
+/// Reads batches from an arrow ipc file, in any order
+///
+/// The file is mapped rather than read, and its dictionaries are decoded when it is opened.
+pub struct FileReader {
+    // the file is mapped, batches are decoded straight from its pages
+    data: Bytes,
+    decoder: Decoder,
+    // offset, metadata size and body size of each record batch
+    batches: Vec<(usize, usize, usize)>,
+}
+
+fn block(x: &[u8]) -> Result<(usize, usize, usize), IpcError> {
+    Ok((size(le_i64(x), "block offset")?, size(le_i32(&x[8..]) as i64, "metadata length")?, size(le_i64(&x[16..]), "body length")?))
+}
+
+// metadata and body of the message in a block
+fn message_at(file: &[u8], (offset, meta, body): (usize, usize, usize)) -> Result<(&[u8], &[u8]), IpcError> {
+    let missing = || IpcError::Invalid(format!("message at {offset} is outside the file"));
+    let range = |start: usize, len: usize| start.checked_add(len).map(|end| start..end);
+    let prefix = range(offset, meta).and_then(|r| file.get(r)).ok_or_else(missing)?;
+    let meta = if prefix.starts_with(&CONTINUATION) { prefix.get(8..) } else { prefix.get(4..) }.ok_or_else(missing)?;
+    Ok((meta, range(offset + prefix.len(), body).and_then(|r| file.get(r)).ok_or_else(missing)?))
+}
+
+impl FileReader {
+    /// Open a file by mapping it
+    ///
+    /// # Safety
+    ///
+    /// Same as [`Bytes::map`]: the file must not be truncated or written while the reader is alive.
+    pub unsafe fn open(path: impl AsRef<Path>) -> Result<FileReader, IpcError> {
+        let file = File::open(path)?;
+        let len = file.metadata()?.len() as usize;
+        FileReader::new(unsafe { Bytes::map(&file, 0, len) }?)
+    }
+    pub fn from_bytes(bytes: &[u8]) -> Result<FileReader, IpcError> {
+        let mut data = Bytes::with_capacity(bytes.len());
+        data.extend(bytes);
+        FileReader::new(data)
+    }
+    fn new(data: Bytes) -> Result<FileReader, IpcError> {
+        let file = data.slice(..);
+        let len = file.len();
+        if len < 18 || !file.starts_with(MAGIC) || !file.ends_with(MAGIC) { Err(IpcError::Invalid("file does not start and end with ARROW1".into()))? }
+        let footer_len = size(le_i32(&file[len - 10..]) as i64, "footer length")?;
+        let footer = (len - 10).checked_sub(footer_len).map(|start| &file[start..len - 10]).ok_or(IpcError::Invalid("footer is outside the file".into()))?;
+        let footer = Table::root(footer)?;
+        let mut decoder = Decoder::new(footer.table(1)?.ok_or(IpcError::Invalid("footer has no schema".into()))?)?;
+        for x in footer.structs(2, 24)? {
+            let (meta, body) = message_at(file, block(x)?)?;
+            decoder.decode(meta, body)?;
+        }
+        let batches = footer.structs(3, 24)?.into_iter().map(block).collect::<Result<_, _>>()?;
+        Ok(FileReader { data, decoder, batches })
+    }
+    pub fn schema(&self) -> DSchemaRef<'_> {
+        self.decoder.schema.as_ref()
+    }
+    pub fn num_batches(&self) -> usize {
+        self.batches.len()
+    }
+    /// The i-th batch, panics if out of bound
+    pub fn batch(&mut self, i: usize) -> Result<VBuf, IpcError> {
+        let (meta, body) = message_at(self.data.slice(..), self.batches[i])?;
+        self.decoder.decode(meta, body)?.ok_or(IpcError::Invalid(format!("block of batch {i} is not a record batch")))
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::data_value::Value;
+    use crate::util_datetime::MonthDayMicros;
+
+    // (id: i64, (name: dict str, (flag: bool, (n: nil, (p: (d: decimal(10, 2), t: timestamptz), (i: interval, x: binary(2)))))))
+    fn batch(from: usize, names: &[&str]) -> VBuf {
+        let field = |name: &str, schema: DSchema| Field::named(name, schema.as_ref());
+        let p = Pair::encode(&[field("d", Decimal::with(10, 2).unwrap()).as_ref(), field("t", TimestampTz::encode(&[])).as_ref()]);
+        let tail = Pair::encode(&[field("i", Interval::encode(&[])).as_ref(), field("x", FixedBinary::with(2).unwrap()).as_ref()]);
+        let tail = Pair::encode(&[field("p", p).as_ref(), tail.as_ref()]);
+        let tail = Pair::encode(&[field("n", Nil::encode(&[])).as_ref(), tail.as_ref()]);
+        let tail = Pair::encode(&[field("flag", Bool::encode(&[])).as_ref(), tail.as_ref()]);
+        let tail = Pair::encode(&[field("name", DictStr::with(1).unwrap()).as_ref(), tail.as_ref()]);
+        let schema = Pair::encode(&[field("id", I64::encode(&[])).as_ref(), tail.as_ref()]);
+        let mut columns = VBuf::new(schema);
+        for i in from..from + 10 {
+            let p = Value::pair(Value::field("d", Value::Decimal(i as i128 * 7)), Value::field("t", Value::TimestampTz(i as i64 * 1_000_000)));
+            let tail = Value::pair(Value::field("i", Value::Interval(MonthDayMicros { months: 0, days: i as i32, micros: 3 })), Value::field("x", Value::FixedBinary(b"ab")));
+            let tail = Value::pair(Value::field("p", p), tail);
+            let tail = Value::pair(Value::field("n", Value::Nil), tail);
+            let tail = Value::pair(Value::field("flag", Value::Bool(i % 3 == 1)), tail);
+            let tail = Value::pair(Value::field("name", Value::Str(names[i % names.len()])), tail);
+            columns.push(&Value::pair(Value::field("id", Value::I64(i as i64)), tail));
+        }
+        columns
+    }
+
+    fn assert_same(a: VBufRef<'_>, b: VBufRef<'_>) {
+        assert!(a.schema == b.schema && a.len == b.len, "{:?} != {:?}", a.schema, b.schema);
+        for i in 0..a.len { assert!(a.get(i) == b.get(i), "{:?} != {:?}", a.get(i), b.get(i)) }
+    }
+
+    #[test]
+    fn stream_round_trip() {
+        let (a, b) = (batch(0, &["de", "fr"]), batch(10, &["us", "de", "jp"]));
+        let mut writer = StreamWriter::new(vec![], a.schema.as_ref()).unwrap();
+        writer.write(a.as_ref()).unwrap();
+        // a view, with a dictionary that shares some strings
+        writer.write(b.as_ref().slice(3..8)).unwrap();
+        writer.write(a.as_ref().slice(..0)).unwrap();
+        assert!(matches!(writer.write(Bool::column([true]).as_ref()), Err(IpcError::SchemaMismatch { .. })));
+        let bytes = writer.finish().unwrap();
+        let mut reader = StreamReader::new(bytes.as_slice()).unwrap();
+        assert!(reader.schema() == a.schema.as_ref());
+        assert_same(reader.next().unwrap().unwrap().as_ref(), a.as_ref());
+        let second = reader.next().unwrap().unwrap();
+        assert_same(second.as_ref(), b.as_ref().slice(3..8));
+        // the dictionary grew by a delta
+        let names = DictStr::vector_cast(second.as_ref().pair().1.pair().0.field());
+        assert!(names.dictionary().iter().eq(["de", "fr", "jp", "us"]));
+        assert!(reader.next().unwrap().unwrap().is_empty());
+        assert!(reader.next().is_none());
+        // a truncated stream fails instead of ending early
+        let mut reader = StreamReader::new(&bytes[..bytes.len() - 100]).unwrap();
+        assert!(reader.next().unwrap().is_ok() && reader.by_ref().any(|x| x.is_err()));
+    }
+
+    #[test]
+    fn file_round_trip() {
+        let path = std::env::temp_dir().join(format!("muadb-ipc-{}.arrow", std::process::id()));
+        let batches = [batch(0, &["a"]), batch(10, &["b", "a"]), batch(20, &["c"])];
+        let mut writer = FileWriter::create(&path, batches[0].schema.as_ref()).unwrap();
+        for x in &batches { writer.write(x.as_ref()).unwrap() }
+        writer.finish().unwrap();
+        let mut reader = unsafe { FileReader::open(&path) }.unwrap();
+        std::fs::remove_file(&path).unwrap();
+        assert!(reader.num_batches() == 3 && reader.schema() == batches[0].schema.as_ref());
+        for i in [2, 0, 1] { assert_same(reader.batch(i).unwrap().as_ref(), batches[i].as_ref()) }
+        // a single column is a single field
+        let column = DictStr::vector_cast(batches[1].as_ref().pair().1.pair().0.field()).decode();
+        let mut writer = FileWriter::new(vec![], column.schema.as_ref()).unwrap();
+        writer.write(column.as_ref()).unwrap();
+        let bytes = writer.finish().unwrap();
+        assert_same(FileReader::from_bytes(&bytes).unwrap().batch(0).unwrap().as_ref(), column.as_ref());
+        assert!(matches!(FileReader::from_bytes(&bytes[..bytes.len() - 1]), Err(IpcError::Invalid(_))));
+    }
+
+    #[test]
+    fn foreign_types() {
+        // a stream written by others: utf8 with 32-bit offsets, timestamps in millis, int32 dictionary indices
+        let field = |name: &str, id: u8, ty: Item, dictionary: Option<Item>| {
+            let mut fields = vec![(0, Item::Str(name.into())), (1, Item::Bool(true)), (2, Item::U8(id)), (3, ty)];
+            fields.extend(dictionary.map(|d| (4, d)));
+            Item::Table(fields)
+        };
+        let schema = Item::Table(vec![(1, Item::Tables(vec![
+            field("s", TYPE_UTF8, Item::Table(vec![]), None),
+            field("t", TYPE_TIMESTAMP, Item::Table(vec![(0, Item::I16(1))]), None),
+            field("c", TYPE_UTF8, Item::Table(vec![]), Some(Item::Table(vec![(0, Item::I64(7))]))),
+        ]))]);
+        let mut stream = vec![];
+        write_message(&mut stream, &message(HEADER_SCHEMA, schema, vec![])).unwrap();
+        let mut dictionary = Body::default();
+        dictionary.node(2, 0);
+        dictionary.buffer(&[]);
+        dictionary.buffer(bytemuck::cast_slice(&[0i32, 1, 3]));
+        dictionary.buffer(b"xyz");
+        let (values, bytes) = dictionary.record_batch(2);
+        write_message(&mut stream, &message(HEADER_DICTIONARY_BATCH, Item::Table(vec![(0, Item::I64(7)), (1, values)]), bytes)).unwrap();
+        let mut body = Body::default();
+        body.node(2, 0);
+        body.buffer(&[]);
+        body.buffer(bytemuck::cast_slice(&[0i32, 2, 2]));
+        body.buffer(b"hi");
+        body.node(2, 0);
+        body.buffer(&[]);
+        body.buffer(bytemuck::cast_slice(&[1500i64, -1]));
+        body.node(2, 0);
+        body.buffer(&[]);
+        body.buffer(bytemuck::cast_slice(&[1i32, 0]));
+        let (batch, bytes) = body.record_batch(2);
+        write_message(&mut stream, &message(HEADER_RECORD_BATCH, batch, bytes)).unwrap();
+        let mut reader = StreamReader::new(stream.as_slice()).unwrap();
+        assert!(format!("{:?}", reader.schema()).contains("DictStr(u32)"));
+        let columns = reader.next().unwrap().unwrap();
+        let row = |i: usize| Value::pair(Value::field("s", Value::Str(["hi", ""][i])), Value::pair(
+            Value::field("t", Value::Timestamp([1_500_000, -1000][i])), Value::field("c", Value::Str(["yz", "x"][i]))));
+        assert!(columns.get(0) == row(0) && columns.get(1) == row(1));
+        assert!(reader.next().is_none());
+    }
+
+    #[test]
+    fn malformed_input() {
+        let field = |name: &str, id: u8, ty: Item| Item::Table(vec![(0, Item::Str(name.into())), (2, Item::U8(id)), (3, ty)]);
+        let read = |fields: Vec<Item>, length: usize, body: Body, variadic: &[i64]| {
+            let mut stream = vec![];
+            write_message(&mut stream, &message(HEADER_SCHEMA, Item::Table(vec![(1, Item::Tables(fields))]), vec![])).unwrap();
+            let (batch, bytes) = body.record_batch(length);
+            let Item::Table(mut batch) = batch else { unreachable!() };
+            batch.push((4, Item::Structs(bytemuck::cast_slice(variadic).to_vec(), 8)));
+            write_message(&mut stream, &message(HEADER_RECORD_BATCH, Item::Table(batch), bytes)).unwrap();
+            StreamReader::new(stream.as_slice()).unwrap().next().unwrap()
+        };
+        let long = || vec![field("a", TYPE_INT, Item::Table(vec![(0, Item::I32(64)), (1, Item::Bool(true))]))];
+        let longs = |length: i64, nulls: i64, values: &[i64]| {
+            let mut body = Body::default();
+            body.nodes.extend(length.to_le_bytes().into_iter().chain(nulls.to_le_bytes()));
+            body.buffer(&[]);
+            body.buffer(bytemuck::cast_slice(values));
+            body
+        };
+        let invalid = |x: Result<VBuf, IpcError>| matches!(x, Err(IpcError::Invalid(_)));
+        assert!(read(long(), 2, longs(2, 0, &[1, 2]), &[]).unwrap().len() == 2);
+        // lengths past the buffers, negative, or with more nulls than values
+        assert!(invalid(read(long(), 2, longs(1 << 24, 0, &[1, 2]), &[])));
+        assert!(invalid(read(long(), 2, longs(-1, 0, &[1, 2]), &[])));
+        assert!(invalid(read(long(), 2, longs(2, 3, &[1, 2]), &[])));
+        // a column shorter than the batch
+        assert!(invalid(read(long(), 3, longs(2, 0, &[1, 2]), &[])));
+        // a buffer outside the body
+        let mut body = longs(2, 0, &[1, 2]);
+        body.buffers[16..24].copy_from_slice(&(i64::MAX - 7).to_le_bytes());
+        assert!(invalid(read(long(), 2, body, &[])));
+        // offsets that go back or past the values
+        let utf8 = || vec![field("s", TYPE_UTF8, Item::Table(vec![]))];
+        let strings = |offsets: &[i32]| {
+            let mut body = Body::default();
+            body.node(2, 0);
+            body.buffer(&[]);
+            body.buffer(bytemuck::cast_slice(offsets));
+            body.buffer(b"abc");
+            body
+        };
+        assert!(read(utf8(), 2, strings(&[0, 1, 3]), &[]).is_ok());
+        assert!(invalid(read(utf8(), 2, strings(&[0, 2, 1]), &[])));
+        assert!(invalid(read(utf8(), 2, strings(&[0, 1, 4]), &[])));
+        assert!(invalid(read(utf8(), 2, strings(&[-1, 1, 3]), &[])));
+        assert!(invalid(read(utf8(), 2, strings(&[0, 1]), &[])));
+        // views into a data buffer that is missing or too short
+        let views = || vec![field("v", TYPE_BINARY_VIEW, Item::Table(vec![]))];
+        let view = |index: i32, at: i32, variadic: usize| {
+            let mut body = Body::default();
+            body.node(1, 0);
+            body.buffer(&[]);
+            body.buffer(&[&20i32.to_le_bytes()[..], b"abcd", &index.to_le_bytes(), &at.to_le_bytes()].concat());
+            for _ in 0..variadic { body.buffer(&[7; 24]) }
+            body
+        };
+        assert!(read(views(), 1, view(0, 4, 1), &[1]).unwrap().get(0) == Value::field("v", Value::Blob(&[7; 20])));
+        assert!(invalid(read(views(), 1, view(1, 0, 1), &[1])));
+        assert!(invalid(read(views(), 1, view(0, 5, 1), &[1])));
+        assert!(invalid(read(views(), 1, view(0, -4, 1), &[1])));
+        assert!(invalid(read(views(), 1, view(0, 0, 0), &[])));
+        // metadata is padded to 8 bytes
+        let mut stream = vec![];
+        let (meta, body) = write_message(&mut stream, &Message { meta: vec![1; 13], body: vec![2; 8], dictionary: false }).unwrap();
+        assert!(meta == 24 && body == 8 && stream.len() == 32 && stream[4..8] == 16i32.to_le_bytes() && stream[21..24] == [0; 3]);
+        // message sizes made up by the input
+        for len in [-8i64, 1 << 60] {
+            let mut stream = vec![];
+            write_message(&mut stream, &message(HEADER_SCHEMA, Item::Table(vec![(1, Item::Tables(long()))]), vec![])).unwrap();
+            let (batch, _) = longs(2, 0, &[1, 2]).record_batch(2);
+            let meta = fb::finish(&Item::Table(vec![(0, Item::I16(METADATA_V5)), (1, Item::U8(HEADER_RECORD_BATCH)), (2, batch), (3, Item::I64(len))]));
+            write_message(&mut stream, &Message { meta, body: vec![0; 16], dictionary: false }).unwrap();
+            assert!(invalid(StreamReader::new(stream.as_slice()).unwrap().next().unwrap()));
+        }
+        // only a zero length ends the stream
+        let mut stream = vec![];
+        write_message(&mut stream, &message(HEADER_SCHEMA, Item::Table(vec![(1, Item::Tables(long()))]), vec![])).unwrap();
+        stream.extend(CONTINUATION.into_iter().chain((-8i32).to_le_bytes()));
+        assert!(invalid(StreamReader::new(stream.as_slice()).unwrap().next().unwrap()));
+        stream.truncate(stream.len() - 4);
+        stream.extend(0i32.to_le_bytes());
+        assert!(StreamReader::new(stream.as_slice()).unwrap().next().is_none());
+        let mut writer = FileWriter::new(vec![], I64::encode(&[]).as_ref()).unwrap();
+        writer.write(VBuf::new(I64::encode(&[])).as_ref()).unwrap();
+        let mut bytes = writer.finish().unwrap();
+        let at = bytes.len() - 10;
+        for len in [-1, i32::MAX] {
+            bytes[at..at + 4].copy_from_slice(&len.to_le_bytes());
+            assert!(matches!(FileReader::from_bytes(&bytes), Err(IpcError::Invalid(_))));
+        }
+    }
+}
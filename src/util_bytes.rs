@@ -1,146 +1,426 @@
-use std::ptr::null_mut;
+use std::ptr::{null, null_mut};
 use std::alloc::*;
+use std::sync::Arc;
+use std::sync::atomic::{AtomicU32, AtomicUsize, Ordering, fence};
+use thiserror::Error;
 
 /// bytes, but aligned as u64, so it can hold u64
-pub struct Bytes(*mut u8);
-const HEAD: usize = 16;
+///
+/// Clones share the allocation, which is copied by the first write to a shared one.
+/// Bytes mapped from a file are read-only the same way, a write copies them to the heap.
+/// A counter holds no bytes, only the number of values in a column of zero-sized values.
+pub struct Bytes(Repr);
+
+#[derive(Clone, Copy)]
+enum Repr {
+    // the header of the allocation, null when empty
+    Heap(*mut u8),
+    Counter(usize),
+}
+const HEAD: usize = std::mem::size_of::<Head>();
 const UNIT: usize = 16;
 const ALIGN: usize = 16;
 
+// the header before the bytes
+#[repr(C, align(16))]
+struct Head {
+    len: u64,
+    // capacity in units, zero for a file mapping
+    units: u32,
+    // owners of the allocation
+    refs: AtomicU32,
+    // pool the allocation is counted in, null for the global allocator
+    pool: *const MemoryPool,
+}
+
+#[derive(Debug, Error, PartialEq, Eq)]
+pub enum MemoryError {
+    #[error("allocating {0} more bytes exceeds the memory limit of {1} bytes")]
+    Limit(usize, usize),
+    #[error("allocator cannot allocate {0} bytes")]
+    Exhausted(usize),
+}
+
+/// Counts the bytes allocated in it, e.g. for one query or one table
+///
+/// Bytes allocated in a pool grow, shrink and copy in the same pool, and keep the pool alive.
+pub struct MemoryPool {
+    allocator: Box<dyn GlobalAlloc + Send + Sync>,
+    limit: usize,
+    used: AtomicUsize,
+    peak: AtomicUsize,
+}
+
+impl MemoryPool {
+    /// A pool of the system allocator that fails allocations beyond `limit` bytes in use
+    pub fn new(limit: usize) -> Arc<MemoryPool> {
+        MemoryPool::with_allocator(limit, System)
+    }
+    pub fn with_allocator(limit: usize, allocator: impl GlobalAlloc + Send + Sync + 'static) -> Arc<MemoryPool> {
+        Arc::new(MemoryPool { allocator: Box::new(allocator), limit, used: AtomicUsize::new(0), peak: AtomicUsize::new(0) })
+    }
+    pub fn limit(&self) -> usize {
+        self.limit
+    }
+    /// Bytes in use, headers included
+    pub fn used(&self) -> usize {
+        self.used.load(Ordering::Relaxed)
+    }
+    /// Most bytes in use at once
+    pub fn peak(&self) -> usize {
+        self.peak.load(Ordering::Relaxed)
+    }
+    fn acquire(&self, size: usize) -> Result<(), MemoryError> {
+        let used = self.used.fetch_update(Ordering::Relaxed, Ordering::Relaxed, |used| used.checked_add(size).filter(|x| *x <= self.limit));
+        let used = used.map_err(|_| MemoryError::Limit(size, self.limit))?;
+        self.peak.fetch_max(used + size, Ordering::Relaxed);
+        Ok(())
+    }
+    fn release(&self, size: usize) {
+        self.used.fetch_sub(size, Ordering::Relaxed);
+    }
+}
+
+// move the allocation at `ptr` from `old` to `new` bytes, a null `ptr` is a new zeroed allocation
+unsafe fn reallocate_in(pool: *const MemoryPool, ptr: *mut u8, old: usize, new: usize) -> Result<*mut u8, MemoryError> {
+    let layout = Layout::from_size_align(new, ALIGN).unwrap();
+    let Some(pool) = pool.as_ref() else {
+        let ptr = match ptr.is_null() {
+            true => std::alloc::alloc_zeroed(layout),
+            false => std::alloc::realloc(ptr, Layout::from_size_align(old, ALIGN).unwrap(), new),
+        };
+        if ptr.is_null() { handle_alloc_error(layout) }
+        return Ok(ptr)
+    };
+    if new > old { pool.acquire(new - old)? }
+    let out = match ptr.is_null() {
+        true => pool.allocator.alloc_zeroed(layout),
+        false => pool.allocator.realloc(ptr, Layout::from_size_align(old, ALIGN).unwrap(), new),
+    };
+    if out.is_null() {
+        pool.release(new.saturating_sub(old));
+        return Err(MemoryError::Exhausted(new))
+    }
+    if new < old { pool.release(old - new) }
+    Ok(out)
+}
+
 impl Bytes {
-    pub fn new() -> Self { Bytes(null_mut()) }
-    pub fn new_as_usize() -> Self {unsafe { Bytes(null_mut::<u8>().add(1)) }}
-    fn initialize(&mut self) {
-        debug_assert!(self.0 as usize % 2 == 0, "pointer not aligned to 4 means the value cannnot be used as a pointer's address");
-        if self.0 != null_mut() { return }
-        let layout = Layout::from_size_align(HEAD + UNIT, ALIGN).unwrap();
+    pub fn new() -> Self { Bytes(Repr::Heap(null_mut())) }
+    /// A counter at zero, for a column of zero-sized values
+    pub fn counter() -> Self { Bytes(Repr::Counter(0)) }
+    pub fn is_counter(&self) -> bool {
+        matches!(self.0, Repr::Counter(_))
+    }
+    /// The number of values counted, panics for bytes
+    pub fn count(&self) -> usize {
+        let Repr::Counter(count) = self.0 else { panic!("bytes are not a counter") };
+        count
+    }
+    /// Count `n` more values, panics for bytes
+    pub fn add_count(&mut self, n: usize) {
+        let Repr::Counter(count) = &mut self.0 else { panic!("bytes are not a counter") };
+        *count = count.checked_add(n).expect("counter overflow");
+    }
+    // the header of the allocation, null when empty, panics for a counter
+    fn ptr(&self) -> *mut u8 {
+        let Repr::Heap(ptr) = self.0 else { panic!("a counter holds no bytes") };
+        ptr
+    }
+    /// Empty bytes that hold at least `cap` bytes before reallocating
+    pub fn with_capacity(cap: usize) -> Self {
+        let mut bytes = Bytes::new();
+        bytes.reserve(cap);
+        bytes
+    }
+    /// Empty bytes allocated in `pool`, growing them fails instead of exceeding its limit
+    pub fn try_with_capacity_in(cap: usize, pool: &Arc<MemoryPool>) -> Result<Self, MemoryError> {
+        let mut bytes = Bytes::new();
+        bytes.reallocate(cap.max(1).next_multiple_of(UNIT), Arc::as_ptr(pool))?;
+        Ok(bytes)
+    }
+    /// The pool the bytes are allocated in, none for the global allocator
+    pub fn pool(&self) -> Option<Arc<MemoryPool>> {
+        if !matches!(self.0, Repr::Heap(ptr) if !ptr.is_null()) || self.head().pool.is_null() { return None }
         unsafe {
-            let ptr = std::alloc::alloc_zeroed(layout);
-            *(ptr as *mut [u64;2]) = [0, UNIT as u64];
-            *self = Bytes(ptr);
+            Arc::increment_strong_count(self.head().pool);
+            Some(Arc::from_raw(self.head().pool))
         }
     }
-    pub fn as_u64(&self) -> usize {
-        debug_assert!(self.0 as usize % 2 == 1);
-        self.0 as usize / 2
+    fn head(&self) -> &Head {
+        debug_assert!(!self.ptr().is_null());
+        unsafe { &*(self.ptr() as *const Head) }
     }
-    pub fn add(&mut self, x: usize) {
-        debug_assert!(self.0 as usize % 2 == 1);
+    // move to an allocation of `cap` bytes, a new allocation starts empty in `pool`
+    fn reallocate(&mut self, cap: usize, pool: *const MemoryPool) -> Result<(), MemoryError> {
+        debug_assert!(cap >= self.len() && cap.is_multiple_of(UNIT));
+        let units = u32::try_from(cap / UNIT).map_err(|_| MemoryError::Exhausted(HEAD + cap))?;
         unsafe {
-            self.0 = self.0.add(x * 2);
+            if !self.ptr().is_null() {
+                self.0 = Repr::Heap(reallocate_in(self.head().pool, self.ptr(), HEAD + self.capacity(), HEAD + cap)?);
+                (*(self.ptr() as *mut Head)).units = units;
+                return Ok(())
+            }
+            self.0 = Repr::Heap(reallocate_in(pool, null_mut(), 0, HEAD + cap)?);
+            // every allocation in a pool holds on to it
+            if !pool.is_null() { Arc::increment_strong_count(pool) }
+            (self.ptr() as *mut Head).write(Head { len: 0, units, refs: AtomicU32::new(1), pool });
+            Ok(())
+        }
+    }
+    /// Read-only bytes of `len` bytes at `offset` in a file, mapped instead of read
+    ///
+    /// `offset` must be a multiple of 16.
+    ///
+    /// # Safety
+    ///
+    /// Until the bytes and all their clones are dropped, nothing may truncate or write the mapped region of the file,
+    /// in this process or any other, since the bytes read the pages of the file as they are.
+    #[cfg(unix)]
+    pub unsafe fn map(file: &std::fs::File, offset: u64, len: usize) -> std::io::Result<Self> {
+        use std::io::{Error, ErrorKind};
+        use std::os::fd::AsRawFd;
+        if !offset.is_multiple_of(ALIGN as u64) {
+            return Err(Error::new(ErrorKind::InvalidInput, format!("offset {offset} is not aligned to {ALIGN}")))
+        }
+        match offset.checked_add(len as u64) {
+            Some(end) if end <= file.metadata()?.len() => {}
+            _ => return Err(Error::new(ErrorKind::UnexpectedEof, format!("{offset} + {len} is past the end of file"))),
+        }
+        if len == 0 { return Ok(Bytes::new()) }
+        let page = page_size();
+        let skip = (offset % page as u64) as usize;
+        let span = (skip + len).next_multiple_of(page);
+        unsafe {
+            // an anonymous page in front holds the header when the region starts at a page,
+            // otherwise the header goes to a private copy of the first page, never to the file
+            let base = libc::mmap(null_mut(), page + span, libc::PROT_READ | libc::PROT_WRITE, libc::MAP_PRIVATE | libc::MAP_ANONYMOUS, -1, 0);
+            if base == libc::MAP_FAILED { return Err(Error::last_os_error()) }
+            let base = base as *mut u8;
+            let flags = libc::MAP_PRIVATE | libc::MAP_FIXED;
+            let at = libc::mmap(base.add(page) as _, span, libc::PROT_READ | libc::PROT_WRITE, flags, file.as_raw_fd(), (offset - skip as u64) as libc::off_t);
+            if at == libc::MAP_FAILED {
+                let err = Error::last_os_error();
+                libc::munmap(base as _, page + span);
+                return Err(err)
+            }
+            let ptr = base.add(page + skip - HEAD);
+            (ptr as *mut Head).write(Head { len: len as u64, units: 0, refs: AtomicU32::new(1), pool: null() });
+            Ok(Bytes(Repr::Heap(ptr)))
         }
     }
+    /// Whether the bytes are mapped from a file
+    pub fn is_mapped(&self) -> bool {
+        matches!(self.0, Repr::Heap(ptr) if !ptr.is_null()) && self.head().units == 0
+    }
+    // give back the pages of a mapping
+    #[cfg(unix)]
+    fn unmap(&mut self) {
+        let page = page_size();
+        let data = self.ptr() as usize + HEAD;
+        let span = (data % page + self.len()).next_multiple_of(page);
+        let base = data / page * page - page;
+        unsafe { libc::munmap(base as _, page + span); }
+    }
+    // writes go to an allocation of their own, a shared or mapped one is copied first
+    fn make_unique(&mut self) -> Result<(), MemoryError> {
+        if self.ptr().is_null() || (self.head().refs.load(Ordering::Acquire) == 1 && self.head().units != 0) { return Ok(()) }
+        let mut copy = Bytes::new();
+        copy.reallocate(self.capacity().max(self.len().next_multiple_of(UNIT)), self.head().pool)?;
+        unsafe { std::ptr::copy_nonoverlapping(self.ptr().add(HEAD), copy.ptr().add(HEAD), self.len()) }
+        copy.set_len(self.len());
+        *self = copy;
+        Ok(())
+    }
+    // shrinking shared bytes copies them, which panics if their pool is full, `try_reserve(0)` copies them fallibly first
+    fn set_len(&mut self, len: usize) {
+        if self.ptr().is_null() { return }
+        self.make_unique().unwrap_or_else(|e| panic!("{e}"));
+        debug_assert!(len <= self.capacity());
+        unsafe { (*(self.ptr() as *mut Head)).len = len as u64 }
+    }
+    /// Whether clones share the allocation, writing to shared bytes copies them
+    pub fn is_shared(&self) -> bool {
+        matches!(self.0, Repr::Heap(ptr) if !ptr.is_null()) && self.head().refs.load(Ordering::Acquire) > 1
+    }
     pub fn len(&self) -> usize {
-        debug_assert!(self.0 as usize % 2 == 0, "pointer not aligned to 4 means the value cannnot be used as a pointer's address");
-        let Bytes(ptr) = self;
-        if ptr.is_null() { return 0 }
-        unsafe { (*(*ptr as *mut [u64;2]))[0] as usize }
+        if self.ptr().is_null() { return 0 }
+        self.head().len as usize
     }
+    /// Bytes that fit before reallocating, none for mapped bytes
     pub fn capacity(&self) -> usize {
-        debug_assert!(self.0 as usize % 2 == 0, "pointer not aligned to 4 means the value cannnot be used as a pointer's address");
-        let Bytes(ptr) = self;
-        if ptr.is_null() { return 0 }
-        unsafe { (*(*ptr as *mut [u64;2]))[1] as usize }
+        if self.ptr().is_null() { return 0 }
+        self.head().units as usize * UNIT
+    }
+    /// Make room for `additional` more bytes, growing at least twofold to keep pushes amortized
+    ///
+    /// Panics if the pool of the bytes is out of memory, see [`Bytes::try_reserve`].
+    pub fn reserve(&mut self, additional: usize) {
+        self.try_reserve(additional).unwrap_or_else(|e| panic!("{e}"))
+    }
+    /// Make room for `additional` more bytes, or fail without changing the bytes
+    pub fn try_reserve(&mut self, additional: usize) -> Result<(), MemoryError> {
+        self.make_unique()?;
+        let need = self.len() + additional;
+        if need <= self.capacity() { return Ok(()) }
+        self.reallocate(need.max(self.capacity() * 2).max(UNIT).next_multiple_of(UNIT), null())
+    }
+    /// Keep the first `len` bytes, the capacity stays
+    pub fn truncate(&mut self, len: usize) {
+        if len < self.len() { self.set_len(len) }
+    }
+    pub fn clear(&mut self) {
+        self.truncate(0)
+    }
+    /// Give back the capacity beyond the length, rounded up to the allocation unit
+    ///
+    /// Shrinking is a hint, the bytes stay as they are if the copy of shared bytes or the reallocation fails.
+    pub fn shrink_to_fit(&mut self) {
+        if self.ptr().is_null() { return }
+        // empty bytes free their allocation, unless it ties them to a pool
+        if self.len() == 0 && self.head().pool.is_null() { *self = Bytes::new(); return }
+        if self.is_mapped() || self.make_unique().is_err() { return }
+        let cap = self.len().max(1).next_multiple_of(UNIT);
+        if cap < self.capacity() { self.reallocate(cap, null()).ok(); }
+    }
+    /// Truncate to `len` bytes, or extend with copies of `byte`
+    ///
+    /// Panics if the pool of the bytes is out of memory, see [`Bytes::try_resize`].
+    pub fn resize(&mut self, len: usize, byte: u8) {
+        self.try_resize(len, byte).unwrap_or_else(|e| panic!("{e}"))
+    }
+    /// Truncate to `len` bytes, or extend with copies of `byte` if there is memory for them
+    pub fn try_resize(&mut self, len: usize, byte: u8) -> Result<(), MemoryError> {
+        let old = self.len();
+        self.try_reserve(len.saturating_sub(old))?;
+        if len <= old { self.truncate(len); return Ok(()) }
+        unsafe { std::ptr::write_bytes(self.ptr().add(HEAD + old), byte, len - old) }
+        self.set_len(len);
+        Ok(())
     }
     pub fn pad(&mut self, align: usize) {
-        debug_assert!(self.0 as usize % 2 == 0, "pointer not aligned to 4 means the value cannnot be used as a pointer's address");
-        if self.len() % align == 0 { return }
-        let rest = align - self.len() % align;
-        let zero = [0; 128];
-        self.extend(&zero[..rest]);
+        self.try_pad(align).unwrap_or_else(|e| panic!("{e}"))
+    }
+    pub fn try_pad(&mut self, align: usize) -> Result<(), MemoryError> {
+        if self.len().is_multiple_of(align) { return Ok(()) }
+        self.try_resize(self.len().next_multiple_of(align), 0)
     }
+    /// Panics if the pool of the bytes is out of memory, see [`Bytes::try_push`]
     pub fn push(&mut self, byte: u8) {
-        debug_assert!(self.0 as usize % 2 == 0, "pointer not aligned to 4 means the value cannnot be used as a pointer's address");
-        self.initialize();
+        self.try_push(byte).unwrap_or_else(|e| panic!("{e}"))
+    }
+    /// Append a byte, or fail without changing the bytes
+    pub fn try_push(&mut self, byte: u8) -> Result<(), MemoryError> {
+        self.try_reserve(1)?;
         let len = self.len();
-        let cap = self.capacity();
-        let Bytes(ptr) = self;
-        if len + 1 > cap {unsafe {
-            let layout = Layout::from_size_align(cap + HEAD, ALIGN).unwrap();
-            *ptr = std::alloc::realloc(*ptr, layout, cap * 2 + HEAD);
-            (*(*ptr as *mut [u64;2]))[1] *= 2;
-        }}
-        unsafe {
-            *(*ptr as *mut u8).byte_add(len + HEAD) = byte;
-            (*(*ptr as *mut [u64;2]))[0] += 1;
-        }
+        unsafe { *self.ptr().add(HEAD + len) = byte }
+        self.set_len(len + 1);
+        Ok(())
     }
+    /// Remove the last byte, the capacity stays like after `truncate`
     pub fn pop(&mut self) -> Option<u8> {
-        debug_assert!(self.0 as usize % 2 == 0, "pointer not aligned to 4 means the value cannnot be used as a pointer's address");
-        if self.len() == 0 { return None }
-        let len = self.len();
-        let cap = self.capacity();
-        let Bytes(ptr) = self;
-        let last = unsafe { *(*ptr as *mut u8).byte_add(len + HEAD - 1) };
-        if len * 2 + HEAD < cap {unsafe{
-            let layout = Layout::from_size_align(cap + HEAD, ALIGN).unwrap();
-            *ptr = std::alloc::realloc(*ptr, layout, cap / 2 + HEAD);
-            (*(*ptr as *mut [u64;2]))[1] /= 2;
-        }}
-        unsafe {
-            (*(*ptr as *mut [u64;2]))[0] -= 1;
-            Some(last)
-        }
+        let len = self.len().checked_sub(1)?;
+        let last = unsafe { *self.ptr().add(HEAD + len) };
+        self.set_len(len);
+        Some(last)
     }
+    /// Panics if the pool of the bytes is out of memory, see [`Bytes::try_extend`]
     pub fn extend(&mut self, slice: &[u8]) {
-        debug_assert!(self.0 as usize % 2 == 0, "pointer not aligned to 4 means the value cannnot be used as a pointer's address");
-        self.initialize();
+        self.try_extend(slice).unwrap_or_else(|e| panic!("{e}"))
+    }
+    /// Append a slice, or fail without changing the bytes
+    pub fn try_extend(&mut self, slice: &[u8]) -> Result<(), MemoryError> {
+        // an empty extend still allocates, like a push would
+        if self.ptr().is_null() { self.try_reserve(UNIT)? }
+        self.try_reserve(slice.len())?;
         let len = self.len();
-        let mut cap = self.capacity();
-        let Bytes(ptr) = self;
-        while len + slice.len() > cap {unsafe {
-            let layout = Layout::from_size_align(cap + HEAD, ALIGN).unwrap();
-            *ptr = std::alloc::realloc(*ptr, layout, cap * 2 + HEAD);
-            cap *= 2;
-            (*(*ptr as *mut [u64;2]))[1] = cap as u64;
-        }}
-        unsafe {
-            std::ptr::copy(slice.as_ptr(), ptr.add(len + HEAD), slice.len());
-            (*(*ptr as *mut [u64;2]))[0] += slice.len() as u64;
-        }
+        unsafe { std::ptr::copy(slice.as_ptr(), self.ptr().add(HEAD + len), slice.len()) }
+        self.set_len(len + slice.len());
+        Ok(())
     }
     pub fn filled(len: usize, byte: u8) -> Self {
-        let layout = Layout::from_size_align(HEAD + (len + ALIGN - 1) & !(ALIGN - 1), ALIGN).unwrap();
-        println!("{layout:?}");
-        unsafe {
-            let ptr = std::alloc::alloc(layout);
-            std::ptr::write_bytes(ptr.add(HEAD), byte, len);
-            (*(ptr as *mut [u64;2]))[0] = len as u64;
-            (*(ptr as *mut [u64;2]))[1] = ((len + ALIGN - 1) & !(ALIGN - 1)) as u64;
-            Bytes(ptr)
-        }
+        let mut bytes = Bytes::new();
+        bytes.resize(len, byte);
+        bytes
     }
-    pub fn slice(&self, range: impl std::ops::RangeBounds<usize>) -> &'_ [u8] {
-        debug_assert!(self.0 as usize % 2 == 0, "pointer not aligned to 4 means the value cannnot be used as a pointer's address");
+    // resolve a range against the length, panics if it is out of `0..len`
+    fn range(&self, range: impl std::ops::RangeBounds<usize>) -> std::ops::Range<usize> {
         use std::ops::Bound::*;
-        let Bytes(ptr) = self;
-        if ptr.is_null() { return &[] }
-        let start = 16 + match range.start_bound() {
+        let len = self.len();
+        let start = match range.start_bound() {
             Included(x) => *x,
             Excluded(x) => *x+1,
             Unbounded => 0,
         };
-        let end = 16 + match range.end_bound() {
+        let end = match range.end_bound() {
             Included(x) => *x+1,
             Excluded(x) => *x,
-            Unbounded => self.len()
+            Unbounded => len,
         };
-        debug_assert!(end >= start);
+        assert!(start <= end && end <= len, "{start}..{end} is out of 0..{len}");
+        start..end
+    }
+    /// The bytes in `range`, panics if it is out of `0..len`
+    pub fn slice(&self, range: impl std::ops::RangeBounds<usize>) -> &'_ [u8] {
+        let range = self.range(range);
+        let ptr = self.ptr();
+        if ptr.is_null() { return &[] }
         unsafe {
-            std::slice::from_raw_parts(ptr.add(start), end - start)
+            std::slice::from_raw_parts(ptr.add(HEAD + range.start), range.len())
+        }
+    }
+    /// The bytes in `range` to write, shared bytes are copied first like in `set_len`
+    pub fn slice_mut(&mut self, range: impl std::ops::RangeBounds<usize>) -> &'_ mut [u8] {
+        let range = self.range(range);
+        self.make_unique().unwrap_or_else(|e| panic!("{e}"));
+        let ptr = self.ptr();
+        if ptr.is_null() { return &mut [] }
+        unsafe {
+            std::slice::from_raw_parts_mut(ptr.add(HEAD + range.start), range.len())
         }
     }
 }
 
+#[cfg(unix)]
+fn page_size() -> usize {
+    unsafe { libc::sysconf(libc::_SC_PAGESIZE) as usize }
+}
+
+// shared allocations are only read, writes copy them first, and owners are counted atomically,
+// pools are only touched through atomics and their allocator is Sync
+unsafe impl Send for Bytes {}
+unsafe impl Sync for Bytes {}
+
+impl Clone for Bytes {
+    fn clone(&self) -> Self {
+        if !matches!(self.0, Repr::Heap(ptr) if !ptr.is_null()) { return Bytes(self.0) }
+        let refs = self.head().refs.fetch_add(1, Ordering::Relaxed);
+        assert!(refs < u32::MAX, "too many clones of bytes");
+        Bytes(self.0)
+    }
+}
+
 impl Drop for Bytes {
     fn drop(&mut self) {
-        if self.0 as usize % 2 != 0 { return }
+        if !matches!(self.0, Repr::Heap(ptr) if !ptr.is_null()) { return }
+        // the last owner frees, after every other owner is done reading
+        if self.head().refs.fetch_sub(1, Ordering::Release) != 1 { return }
+        fence(Ordering::Acquire);
+        #[cfg(unix)]
+        if self.head().units == 0 { return self.unmap() }
         let cap = self.capacity();
-        let Bytes(ptr) = self;
+        let pool = self.head().pool;
+        let ptr = self.ptr();
         let Ok(layout) = Layout::from_size_align(cap + HEAD, ALIGN) else {
             panic!("invalid layout SIZE:{} ALIGN:{}", cap + HEAD, ALIGN);
         };
-        unsafe { std::alloc::dealloc(*ptr, layout); }
+        unsafe {
+            let Some(counted) = pool.as_ref() else { return std::alloc::dealloc(ptr, layout) };
+            counted.allocator.dealloc(ptr, layout);
+            counted.release(cap + HEAD);
+            // the handle is dropped through the stored pointer, the reference above cannot own it
+            drop(Arc::from_raw(pool));
+        }
     }
 }
 
@@ -150,19 +430,23 @@ mod test {
     use rand_xoshiro::*;
     use super::*;
 
+    // miri is slow, it runs fewer and shorter rounds
+    const ROUNDS: usize = if cfg!(miri) { 2 } else { 100 };
+    const STEPS: usize = if cfg!(miri) { 64 } else { 4096 };
+
     #[test]
     fn push_pop_repeat() {
         let mut rng = Xoroshiro128Plus::seed_from_u64(7788);
         let mut x = vec![];
         let mut y = Bytes::new();
-        for _j in 0..100 {
-            for _i in 0..rng.gen_range(0..4096) {
+        for _j in 0..ROUNDS {
+            for _i in 0..rng.gen_range(0..STEPS) {
                 assert!(&x[..] == y.slice(..), "{:?} != {:?}", &x[..], y.slice(..));
                 let x = x.pop();
                 let y = y.pop();
                 assert!(x == y, "{x:?} != {y:?}");
             }
-            for _i in 0..rng.gen_range(0..4096) {
+            for _i in 0..rng.gen_range(0..STEPS) {
                 let n = rng.gen::<u8>();
                 x.push(n);
                 y.push(n);
@@ -171,19 +455,162 @@ mod test {
         }
     }
 
+    #[test]
+    fn reserve_truncate_shrink() {
+        let mut x = Bytes::with_capacity(100);
+        assert!(x.len() == 0 && x.capacity() == 112);
+        let at = x.slice(..).as_ptr();
+        x.extend(&[1; 100]);
+        assert!(x.capacity() == 112 && x.slice(..).as_ptr() == at);
+        x.truncate(10);
+        x.resize(20, 9);
+        assert!(x.slice(..) == [[1; 10], [9; 10]].concat() && x.capacity() == 112);
+        // pops keep the allocation until asked to shrink
+        while x.len() > 3 { x.pop(); }
+        assert!(x.capacity() == 112);
+        x.shrink_to_fit();
+        assert!(x.capacity() == 16 && x.slice(..) == [1, 1, 1]);
+        x.reserve(17);
+        assert!(x.capacity() == 32);
+        x.clear();
+        x.shrink_to_fit();
+        assert!(x.len() == 0 && x.capacity() == 0);
+        let mut y = Bytes::filled(0, 7);
+        y.push(3);
+        y.pad(8);
+        assert!(y.slice(..) == [3, 0, 0, 0, 0, 0, 0, 0]);
+    }
+
+    #[test]
+    fn clone_on_write() {
+        let mut x = Bytes::new();
+        x.extend(&[1, 2, 3]);
+        let mut y = x.clone();
+        assert!(x.is_shared() && y.slice(..).as_ptr() == x.slice(..).as_ptr());
+        // the first write copies, the original keeps its bytes
+        y.push(4);
+        assert!(!x.is_shared() && !y.is_shared());
+        assert!(x.slice(..) == [1, 2, 3] && y.slice(..) == [1, 2, 3, 4]);
+        y.slice_mut(..)[0] = 9;
+        assert!(x.slice(..) == [1, 2, 3] && y.slice(..) == [9, 2, 3, 4]);
+        drop(y);
+        let empty = Bytes::new();
+        assert!(!empty.clone().is_shared() && empty.clone().slice(..).is_empty());
+    }
+
+    #[test]
+    #[cfg_attr(miri, ignore)]
+    fn snapshots_across_threads() {
+        let mut x = Bytes::new();
+        x.extend(&[1, 2, 3]);
+        // snapshots are read and dropped by other threads
+        let readers = (0..4).map(|_| {
+            let snapshot = x.clone();
+            std::thread::spawn(move || snapshot.slice(..).iter().map(|b| *b as usize).sum::<usize>())
+        }).collect::<Vec<_>>();
+        x.truncate(1);
+        for reader in readers { assert!(reader.join().unwrap() == 6) }
+        assert!(!x.is_shared() && x.slice(..) == [1]);
+    }
+
+    #[test]
+    fn counter() {
+        let mut x = Bytes::counter();
+        assert!(x.is_counter() && x.count() == 0 && !Bytes::new().is_counter());
+        x.add_count(3);
+        let mut y = x.clone();
+        y.add_count(usize::MAX - 3);
+        assert!(x.count() == 3 && y.count() == usize::MAX);
+        assert!(!x.is_shared() && !x.is_mapped() && x.pool().is_none());
+        assert!(std::panic::catch_unwind(move || y.add_count(1)).is_err());
+    }
+
+    #[test]
+    #[should_panic(expected = "a counter holds no bytes")]
+    fn counter_has_no_bytes() {
+        Bytes::counter().slice(2..4);
+    }
+
+    #[test]
+    #[should_panic(expected = "0..17 is out of 0..16")]
+    fn slice_past_the_end() {
+        // the capacity beyond the length is not readable either
+        let x = Bytes::filled(16, 1);
+        x.slice(..17);
+    }
+
+    #[test]
+    fn memory_pool() {
+        let pool = MemoryPool::new(2048);
+        let mut x = Bytes::try_with_capacity_in(100, &pool).unwrap();
+        assert!(pool.used() == HEAD + 112 && x.pool().is_some() && Bytes::new().pool().is_none());
+        x.extend(&[1; 112]);
+        // growth stays in the pool and fails at its limit, leaving the bytes as they were
+        x.reserve(400);
+        assert!(pool.used() == HEAD + 512 && pool.peak() == pool.used());
+        assert!(x.try_reserve(2048) == Err(MemoryError::Limit(1648, 2048)));
+        assert!(x.len() == 112 && x.capacity() == 512 && pool.used() == HEAD + 512);
+        assert!(x.try_extend(&[0; 2000]) == Err(MemoryError::Limit(1600, 2048)) && x.len() == 112);
+        assert!(x.try_push(3).is_ok() && x.try_resize(2048, 0).is_err() && x.slice(112..) == [3]);
+        // a copy on write goes to the same pool
+        let mut y = x.clone();
+        y.push(2);
+        assert!(pool.used() == 2 * (HEAD + 512) && !x.is_shared() && y.pool().is_some());
+        drop(y);
+        x.truncate(16);
+        x.shrink_to_fit();
+        assert!(pool.used() == HEAD + 16 && x.slice(..) == [1; 16]);
+        x.clear();
+        x.shrink_to_fit();
+        assert!(x.capacity() == UNIT && pool.used() == HEAD + UNIT && pool.peak() == 2 * (HEAD + 512));
+        // the pool outlives the handle while bytes are allocated in it
+        let peak = pool.peak();
+        drop(pool);
+        let pool = x.pool().unwrap();
+        drop(x);
+        assert!(pool.used() == 0 && pool.peak() == peak);
+    }
+
+    #[test]
+    #[cfg_attr(miri, ignore)]
+    fn map_file() {
+        use std::io::Write;
+        let path = std::env::temp_dir().join(format!("muadb-map-{}.bin", std::process::id()));
+        let data = (0..10000).map(|i| (i % 251) as u8).collect::<Vec<_>>();
+        std::fs::File::create(&path).unwrap().write_all(&data).unwrap();
+        let file = std::fs::File::open(&path).unwrap();
+        std::fs::remove_file(&path).unwrap();
+        for (offset, len) in [(0, 10000), (32, 100), (4096, 4096), (4112, 5888), (9984, 16)] {
+            let x = unsafe { Bytes::map(&file, offset as u64, len) }.unwrap();
+            assert!(x.is_mapped() && x.capacity() == 0 && x.slice(..) == &data[offset..offset + len]);
+            assert!((x.slice(..).as_ptr() as usize).is_multiple_of(ALIGN));
+            // snapshots of a mapping are shared, a write copies to the heap
+            let y = x.clone();
+            let mut z = std::thread::spawn(move || y).join().unwrap();
+            z.push(1);
+            assert!(!z.is_mapped() && z.slice(..len) == x.slice(..) && z.slice(len..) == [1]);
+            assert!(x.is_mapped() && !x.is_shared() && x.slice(..) == &data[offset..offset + len]);
+        }
+        unsafe {
+            assert!(Bytes::map(&file, 8, 8).is_err() && Bytes::map(&file, 9984, 17).is_err());
+            assert!(Bytes::map(&file, u64::MAX - 15, 32).is_err());
+            assert!(Bytes::map(&file, 10000, 0).unwrap().len() == 0);
+        }
+    }
+
     #[test]
     fn fill_push_pop_repeat() {
         let mut rng = Xoroshiro128Plus::seed_from_u64(7788);
         let mut x = vec![7; 1049];
         let mut y = Bytes::filled(1049, 7u8);
-        for _j in 0..100 {
-            for _i in 0..rng.gen_range(0..4096) {
+        for _j in 0..ROUNDS {
+            for _i in 0..rng.gen_range(0..STEPS) {
                 assert!(&x[..] == y.slice(..), "{:?} != {:?}", &x[..], y.slice(..));
                 let x = x.pop();
                 let y = y.pop();
                 assert!(x == y, "{x:?} != {y:?}");
             }
-            for _i in 0..rng.gen_range(0..4096) {
+            for _i in 0..rng.gen_range(0..STEPS) {
                 let n = rng.gen::<u8>();
                 x.push(n);
                 y.push(n);
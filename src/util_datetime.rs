@@ -0,0 +1,171 @@
+//! Calendar arithmetic for date and time types.
+//! Dates are days since 1970-01-01, times are microseconds since midnight,
+//! and timestamps are microseconds since 1970-01-01T00:00:00 (in UTC if it has a time zone).
+//! The conversion between days and civil dates follows Howard Hinnant's `days_from_civil` algorithm.
+
+use bytemuck::{Pod, Zeroable};
+
+pub const MICROS_PER_SECOND: i64 = 1_000_000;
+pub const MICROS_PER_DAY: i64 = 86_400 * MICROS_PER_SECOND;
+
+/// A calendar interval, months and days are kept apart because their lengths vary
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+#[repr(C)]
+pub struct MonthDayMicros {
+    pub months: i32,
+    pub days: i32,
+    pub micros: i64,
+}
+unsafe impl Zeroable for MonthDayMicros {}
+unsafe impl Pod for MonthDayMicros {}
+
+/// Days since epoch of a civil date, `None` if it doesn't fit in i64
+pub fn days_from_civil(y: i64, m: u32, d: u32) -> Option<i64> {
+    let y = if m <= 2 { y - 1 } else { y };
+    let era = y.div_euclid(400);
+    let yoe = y.rem_euclid(400);
+    let doy = (153 * ((m as i64 + 9) % 12) + 2) / 5 + d as i64 - 1;
+    let doe = yoe * 365 + yoe / 4 - yoe / 100 + doy;
+    era.checked_mul(146097)?.checked_add(doe - 719468)
+}
+
+pub fn civil_from_days(z: i64) -> (i64, u32, u32) {
+    let z = z + 719468;
+    let era = z.div_euclid(146097);
+    let doe = z.rem_euclid(146097);
+    let yoe = (doe - doe / 1460 + doe / 36524 - doe / 146096) / 365;
+    let doy = doe - (365 * yoe + yoe / 4 - yoe / 100);
+    let mp = (5 * doy + 2) / 153;
+    let d = (doy - (153 * mp + 2) / 5 + 1) as u32;
+    let m = if mp < 10 { mp + 3 } else { mp - 9 } as u32;
+    (if m <= 2 { yoe + era * 400 + 1 } else { yoe + era * 400 }, m, d)
+}
+
+fn days_in_month(y: i64, m: u32) -> u32 {
+    match m {
+        2 if y % 4 == 0 && (y % 100 != 0 || y % 400 == 0) => 29,
+        2 => 28,
+        4 | 6 | 9 | 11 => 30,
+        _ => 31,
+    }
+}
+
+fn digits(s: &str) -> Option<i64> {
+    if s.is_empty() || !s.bytes().all(|x| x.is_ascii_digit()) { return None }
+    s.parse().ok()
+}
+
+/// Parse `YYYY-MM-DD` into days since epoch
+pub fn parse_date(s: &str) -> Option<i32> {
+    let mut it = s.splitn(3, '-');
+    let y = digits(it.next()?)?;
+    let m = digits(it.next()?)? as u32;
+    let d = digits(it.next()?)? as u32;
+    if !(1..=12).contains(&m) || d == 0 || d > days_in_month(y, m) { return None }
+    days_from_civil(y, m, d)?.try_into().ok()
+}
+
+/// Parse `HH:MM:SS[.ffffff]` into microseconds since midnight
+pub fn parse_time(s: &str) -> Option<i64> {
+    let (s, frac) = s.split_once('.').unwrap_or((s, ""));
+    let mut it = s.splitn(3, ':');
+    let h = digits(it.next()?)?;
+    let m = digits(it.next()?)?;
+    let sec = digits(it.next()?)?;
+    if h > 23 || m > 59 || sec > 59 || frac.len() > 6 { return None }
+    let frac = if frac.is_empty() { 0 } else { digits(frac)? * 10i64.pow(6 - frac.len() as u32) };
+    Some(((h * 60 + m) * 60 + sec) * MICROS_PER_SECOND + frac)
+}
+
+/// Parse `YYYY-MM-DD[( |T)HH:MM:SS[.ffffff]][Z|(+|-)HH:MM]` into microseconds since epoch.
+/// The time zone offset is applied, so the result is in UTC.
+pub fn parse_timestamp(s: &str) -> Option<i64> {
+    let (date, time) = s.split_once([' ', 'T']).unwrap_or((s, "00:00:00"));
+    let (time, offset) = match time.find(['Z', '+', '-']) {
+        Some(i) if &time[i..] == "Z" => (&time[..i], 0),
+        Some(i) => {
+            let (h, m) = time[i+1..].split_once(':')?;
+            let (h, m) = (digits(h)?, digits(m)?);
+            if h > 23 || m > 59 { return None }
+            let sign = if &time[i..i+1] == "-" { -1 } else { 1 };
+            (&time[..i], sign * (h * 60 + m) * 60 * MICROS_PER_SECOND)
+        }
+        None => (time, 0),
+    };
+    let days = parse_date(date)? as i64;
+    days.checked_mul(MICROS_PER_DAY)?.checked_add(parse_time(time)? - offset)
+}
+
+/// Parse a list of `<number> <unit>` like `1 year 2 months 3 days 4 hours`
+pub fn parse_interval(s: &str) -> Option<MonthDayMicros> {
+    let mut interval = MonthDayMicros::default();
+    let mut it = s.split_whitespace();
+    let mut empty = true;
+    while let Some(n) = it.next() {
+        empty = false;
+        let (sign, n) = match n.strip_prefix('-') { Some(n) => (-1, n), None => (1, n) };
+        let n = sign * digits(n)?;
+        match it.next()?.to_ascii_lowercase().trim_end_matches('s') {
+            "year"   => interval.months = interval.months.checked_add(n.checked_mul(12)?.try_into().ok()?)?,
+            "month"  => interval.months = interval.months.checked_add(n.try_into().ok()?)?,
+            "week"   => interval.days = interval.days.checked_add(n.checked_mul(7)?.try_into().ok()?)?,
+            "day"    => interval.days = interval.days.checked_add(n.try_into().ok()?)?,
+            "hour"   => interval.micros = interval.micros.checked_add(n.checked_mul(3600 * MICROS_PER_SECOND)?)?,
+            "minute" => interval.micros = interval.micros.checked_add(n.checked_mul(60 * MICROS_PER_SECOND)?)?,
+            "second" => interval.micros = interval.micros.checked_add(n.checked_mul(MICROS_PER_SECOND)?)?,
+            _ => return None,
+        }
+    }
+    (!empty).then_some(interval)
+}
+
+pub fn format_date(days: i32) -> String {
+    let (y, m, d) = civil_from_days(days as i64);
+    format!("{y:04}-{m:02}-{d:02}")
+}
+
+pub fn format_time(micros: i64) -> String {
+    let (s, f) = (micros / MICROS_PER_SECOND, micros % MICROS_PER_SECOND);
+    let t = format!("{:02}:{:02}:{:02}", s / 3600, s / 60 % 60, s % 60);
+    if f == 0 { t } else { format!("{t}.{f:06}") }
+}
+
+pub fn format_timestamp(micros: i64) -> String {
+    let days = micros.div_euclid(MICROS_PER_DAY);
+    format!("{} {}", format_date(days as i32), format_time(micros.rem_euclid(MICROS_PER_DAY)))
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn date_roundtrip() {
+        assert!(parse_date("1970-01-01") == Some(0));
+        assert!(parse_date("2026-01-01") == Some(20454));
+        assert!(parse_date("1969-12-31") == Some(-1));
+        assert!(parse_date("2024-02-30").is_none());
+        assert!(parse_date("2024-13-01").is_none());
+        assert!(parse_date("999999999999999999-03-01").is_none());
+        for days in -700_000..800_000 {
+            assert!(parse_date(&format_date(days)) == Some(days), "{days} {}", format_date(days));
+        }
+    }
+
+    #[test]
+    fn time_and_timestamp() {
+        assert!(parse_time("12:34:56.5") == Some(((12 * 60 + 34) * 60 + 56) * MICROS_PER_SECOND + 500_000));
+        assert!(parse_time("24:00:00").is_none());
+        assert!(format_time(parse_time("01:02:03.000004").unwrap()) == "01:02:03.000004");
+        assert!(parse_timestamp("2026-01-01 00:00:00") == Some(20454 * MICROS_PER_DAY));
+        assert!(parse_timestamp("2026-01-01T08:00:00+08:00") == parse_timestamp("2026-01-01"));
+        assert!(parse_timestamp("2026-01-01 00:00:00Z") == parse_timestamp("2026-01-01"));
+        assert!(format_timestamp(-1) == "1969-12-31 23:59:59.999999");
+        assert!(parse_interval("1 year 2 months -3 days 4 hours") == Some(MonthDayMicros { months: 14, days: -3, micros: 4 * 3600 * MICROS_PER_SECOND }));
+        assert!(parse_interval("1 fortnight").is_none());
+        // out of range instead of overflow
+        assert!(parse_timestamp("5000000-01-01").is_none());
+        assert!(parse_interval("999999999999999999 years").is_none());
+        assert!(parse_interval("999999999999999999 weeks").is_none());
+    }
+}
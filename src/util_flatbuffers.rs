@@ -0,0 +1,250 @@
+//! Just enough FlatBuffers to read and write the metadata of Arrow IPC.
+//!
+//! Tables are read in place. Written buffers put each table before its children,
+//! so that every offset points forward like the format requires.
+
+use thiserror::Error;
+
+#[derive(Debug, Error, PartialEq, Eq)]
+pub enum FlatError {
+    #[error("flatbuffer is truncated at {0}")]
+    Truncated(usize),
+    #[error("flatbuffer string at {0} is not utf8")]
+    Utf8(usize),
+}
+
+fn read<const N: usize>(buf: &[u8], at: usize) -> Result<[u8; N], FlatError> {
+    buf.get(at..at + N).map(|x| x.try_into().unwrap()).ok_or(FlatError::Truncated(at))
+}
+
+fn read_u32(buf: &[u8], at: usize) -> Result<usize, FlatError> {
+    Ok(u32::from_le_bytes(read(buf, at)?) as usize)
+}
+
+/// A table inside a flatbuffer
+#[derive(Clone, Copy)]
+pub struct Table<'a> {
+    buf: &'a [u8],
+    pos: usize,
+}
+
+impl<'a> Table<'a> {
+    /// The root table of a buffer
+    pub fn root(buf: &'a [u8]) -> Result<Table<'a>, FlatError> {
+        Table::at(buf, read_u32(buf, 0)?)
+    }
+    fn at(buf: &'a [u8], pos: usize) -> Result<Table<'a>, FlatError> {
+        let table = Table { buf, pos };
+        read::<2>(buf, table.vtable()?)?;
+        Ok(table)
+    }
+    fn vtable(&self) -> Result<usize, FlatError> {
+        let soffset = i32::from_le_bytes(read(self.buf, self.pos)?) as i64;
+        usize::try_from(self.pos as i64 - soffset).map_err(|_| FlatError::Truncated(self.pos))
+    }
+    // position of a field, or none if it is absent
+    fn field(&self, slot: usize) -> Result<Option<usize>, FlatError> {
+        let vtable = self.vtable()?;
+        let size = u16::from_le_bytes(read(self.buf, vtable)?) as usize;
+        if 4 + 2 * slot >= size { return Ok(None) }
+        let offset = u16::from_le_bytes(read(self.buf, vtable + 4 + 2 * slot)?) as usize;
+        Ok((offset != 0).then_some(self.pos + offset))
+    }
+    // position an offset field points to
+    fn follow(&self, slot: usize) -> Result<Option<usize>, FlatError> {
+        let Some(at) = self.field(slot)? else { return Ok(None) };
+        Ok(Some(at + read_u32(self.buf, at)?))
+    }
+    fn scalar<const N: usize>(&self, slot: usize, default: [u8; N]) -> Result<[u8; N], FlatError> {
+        match self.field(slot)? {
+            Some(at) => read(self.buf, at),
+            None => Ok(default),
+        }
+    }
+    pub fn u8(&self, slot: usize, default: u8) -> Result<u8, FlatError> {
+        Ok(self.scalar(slot, [default])?[0])
+    }
+    pub fn bool(&self, slot: usize, default: bool) -> Result<bool, FlatError> {
+        Ok(self.u8(slot, default as u8)? != 0)
+    }
+    pub fn i16(&self, slot: usize, default: i16) -> Result<i16, FlatError> {
+        Ok(i16::from_le_bytes(self.scalar(slot, default.to_le_bytes())?))
+    }
+    pub fn i32(&self, slot: usize, default: i32) -> Result<i32, FlatError> {
+        Ok(i32::from_le_bytes(self.scalar(slot, default.to_le_bytes())?))
+    }
+    pub fn i64(&self, slot: usize, default: i64) -> Result<i64, FlatError> {
+        Ok(i64::from_le_bytes(self.scalar(slot, default.to_le_bytes())?))
+    }
+    pub fn table(&self, slot: usize) -> Result<Option<Table<'a>>, FlatError> {
+        self.follow(slot)?.map(|at| Table::at(self.buf, at)).transpose()
+    }
+    pub fn str(&self, slot: usize) -> Result<Option<&'a str>, FlatError> {
+        let Some(at) = self.follow(slot)? else { return Ok(None) };
+        let len = read_u32(self.buf, at)?;
+        let bytes = self.buf.get(at + 4..at + 4 + len).ok_or(FlatError::Truncated(at))?;
+        std::str::from_utf8(bytes).map(Some).map_err(|_| FlatError::Utf8(at))
+    }
+    /// A vector of tables, empty if absent
+    pub fn tables(&self, slot: usize) -> Result<Vec<Table<'a>>, FlatError> {
+        let Some(at) = self.follow(slot)? else { return Ok(vec![]) };
+        (0..read_u32(self.buf, at)?).map(|i| {
+            let at = at + 4 + 4 * i;
+            Table::at(self.buf, at + read_u32(self.buf, at)?)
+        }).collect()
+    }
+    /// A vector of structs of `size` bytes each, empty if absent
+    pub fn structs(&self, slot: usize, size: usize) -> Result<Vec<&'a [u8]>, FlatError> {
+        let Some(at) = self.follow(slot)? else { return Ok(vec![]) };
+        let len = read_u32(self.buf, at)?;
+        let bytes = self.buf.get(at + 4..at + 4 + len * size).ok_or(FlatError::Truncated(at))?;
+        Ok(bytes.chunks(size).collect())
+    }
+}
+
+/// A value to write, a table lists its present fields by slot
+#[derive(Debug, Clone)]
+pub enum Item {
+    U8(u8),
+    Bool(bool),
+    I16(i16),
+    I32(i32),
+    I64(i64),
+    Str(String),
+    Table(Vec<(usize, Item)>),
+    Tables(Vec<Item>),
+    /// Raw bytes of the structs and the size of one struct
+    Structs(Vec<u8>, usize),
+}
+
+impl Item {
+    // size of the field inside a table, offsets take 4 bytes
+    fn size(&self) -> usize {
+        match self {
+            Item::U8(_) | Item::Bool(_) => 1,
+            Item::I16(_) => 2,
+            Item::I64(_) => 8,
+            _ => 4,
+        }
+    }
+}
+
+fn align(buf: &mut Vec<u8>, to: usize) {
+    buf.resize(buf.len().next_multiple_of(to), 0);
+}
+
+fn patch(buf: &mut [u8], at: usize, target: usize) {
+    buf[at..at + 4].copy_from_slice(&((target - at) as u32).to_le_bytes());
+}
+
+// write an object and return its position
+fn write(buf: &mut Vec<u8>, item: &Item) -> usize {
+    match item {
+        Item::Table(fields) => {
+            let slots = fields.iter().map(|(slot, _)| slot + 1).max().unwrap_or(0);
+            let vtable_size = 4 + 2 * slots;
+            // wider fields first, each aligned to its size, after the offset to the vtable
+            let mut order = (0..fields.len()).collect::<Vec<_>>();
+            order.sort_by_key(|i| std::cmp::Reverse(fields[*i].1.size()));
+            let mut offsets = vec![0; fields.len()];
+            let mut end = 4usize;
+            for i in order {
+                let size = fields[i].1.size();
+                offsets[i] = end.next_multiple_of(size);
+                end = offsets[i] + size;
+            }
+            let table = (buf.len() + vtable_size).next_multiple_of(8);
+            let vtable = table - vtable_size;
+            buf.resize(table + end, 0);
+            buf[vtable..vtable + 2].copy_from_slice(&(vtable_size as u16).to_le_bytes());
+            buf[vtable + 2..vtable + 4].copy_from_slice(&(end as u16).to_le_bytes());
+            for ((slot, _), offset) in fields.iter().zip(&offsets) {
+                buf[vtable + 4 + 2 * slot..vtable + 6 + 2 * slot].copy_from_slice(&(*offset as u16).to_le_bytes());
+            }
+            buf[table..table + 4].copy_from_slice(&(vtable_size as i32).to_le_bytes());
+            for ((_, item), offset) in fields.iter().zip(&offsets) {
+                let at = table + offset;
+                match item {
+                    Item::U8(x) => buf[at] = *x,
+                    Item::Bool(x) => buf[at] = *x as u8,
+                    Item::I16(x) => buf[at..at + 2].copy_from_slice(&x.to_le_bytes()),
+                    Item::I32(x) => buf[at..at + 4].copy_from_slice(&x.to_le_bytes()),
+                    Item::I64(x) => buf[at..at + 8].copy_from_slice(&x.to_le_bytes()),
+                    _ => {
+                        let child = write(buf, item);
+                        patch(buf, at, child);
+                    }
+                }
+            }
+            table
+        }
+        Item::Str(x) => {
+            align(buf, 4);
+            let at = buf.len();
+            buf.extend((x.len() as u32).to_le_bytes());
+            buf.extend(x.as_bytes());
+            buf.push(0);
+            at
+        }
+        Item::Tables(items) => {
+            align(buf, 4);
+            let at = buf.len();
+            buf.extend((items.len() as u32).to_le_bytes());
+            buf.resize(at + 4 + 4 * items.len(), 0);
+            for (i, item) in items.iter().enumerate() {
+                let child = write(buf, item);
+                patch(buf, at + 4 + 4 * i, child);
+            }
+            at
+        }
+        Item::Structs(bytes, size) => {
+            // the structs are aligned to 8, the length goes right before them
+            let at = (buf.len() + 4).next_multiple_of(8) - 4;
+            buf.resize(at, 0);
+            buf.extend(((bytes.len() / size) as u32).to_le_bytes());
+            buf.extend(bytes);
+            at
+        }
+        _ => panic!("{item:?} can only be written inside a table"),
+    }
+}
+
+/// Write a buffer with `root` as its root table, padded to a multiple of 8 bytes
+pub fn finish(root: &Item) -> Vec<u8> {
+    let mut buf = vec![0; 4];
+    let at = write(&mut buf, root);
+    patch(&mut buf, 0, at);
+    align(&mut buf, 8);
+    buf
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn write_read() {
+        let child = |name: &str| Item::Table(vec![(0, Item::Str(name.into())), (2, Item::I32(-7))]);
+        let buf = finish(&Item::Table(vec![
+            (0, Item::I16(4)),
+            (1, Item::U8(3)),
+            (2, child("x")),
+            (3, Item::I64(1 << 40)),
+            (5, Item::Tables(vec![child("a"), child("bc")])),
+            (6, Item::Structs((0..32).collect(), 16)),
+            (7, Item::Bool(true)),
+        ]));
+        assert!(buf.len().is_multiple_of(8));
+        let root = Table::root(&buf).unwrap();
+        assert!(root.i16(0, 0).unwrap() == 4 && root.u8(1, 0).unwrap() == 3 && root.i64(3, 0).unwrap() == 1 << 40);
+        assert!(root.bool(7, false).unwrap() && root.i32(4, 9).unwrap() == 9 && root.i32(100, 1).unwrap() == 1);
+        let x = root.table(2).unwrap().unwrap();
+        assert!(x.str(0).unwrap() == Some("x") && x.i32(2, 0).unwrap() == -7 && x.str(1).unwrap().is_none());
+        assert!(root.tables(5).unwrap().iter().map(|t| t.str(0).unwrap().unwrap()).eq(["a", "bc"]));
+        assert!(root.tables(4).unwrap().is_empty());
+        let structs = root.structs(6, 16).unwrap();
+        assert!(structs.len() == 2 && structs[1][0] == 16);
+        // every offset points inside the buffer
+        assert!(Table::root(&buf[..buf.len() / 2]).and_then(|t| t.tables(5)).is_err());
+    }
+}
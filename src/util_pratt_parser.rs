@@ -106,6 +106,17 @@ impl<O, E, X, P> Tag<O, E, X, P>
             phantom: PhantomData
         }
     }
+    /// map the output with a function that may fail (e.g. checking a literal)
+    pub fn and_then<Z, FUNC>(self, map: FUNC) -> Tag<Z, E, X, AndThen<O, E, X, P, Z, FUNC>>
+        where X: Extra<Z, E>,
+              Z: Clone,
+              FUNC: Fn(&mut X, usize, O) -> Result<Z, E>,
+    {
+        Tag{
+            inner: AndThen{map, inner: self.inner, phantom: PhantomData}, 
+            phantom: PhantomData
+        }
+    }
 }
 
 #[derive(Debug, Clone, Copy)]
@@ -469,6 +480,38 @@ where
     }
 }
 
+#[derive(Clone, Debug, Copy)]
+pub struct AndThen<O, E, X, P, Z, FUNC>
+where 
+    P: Parser<O, E, X>, 
+    O: Clone,
+    E: Clone,
+    Z: Clone,
+    FUNC: Fn(&mut X, usize, O) -> Result<Z, E>,
+    X: Extra<O, E> + Extra<Z, E>
+{
+    map: FUNC,
+    inner: P,
+    phantom: PhantomData<(O, E, X, Z)>
+}
+impl<O, E, X, P, Z, FUNC> Parser<Z, E, X> for AndThen<O, E, X, P, Z, FUNC>
+where 
+    P: Parser<O, E, X>, 
+    O: Clone,
+    E: Clone,
+    Z: Clone,
+    FUNC: Fn(&mut X, usize, O) -> Result<Z, E>,
+    X: Extra<O, E> + Extra<Z, E>
+{
+    fn parse(&self, input: &str, progress: usize, extra: &mut X) -> Result<(usize, Z), (usize, E)> {
+        let (end, out) = self.inner.parse(input, progress, extra)?;
+        match (self.map)(extra, progress, out) {
+            Ok(out) => Ok((end, out)),
+            Err(err) => Err((progress, err)),
+        }
+    }
+}
+
 #[derive(Debug, Clone, Copy)]
 pub struct Token<X: Extra<(), ()>> {
     token: &'static str, 